@@ -1,28 +1,60 @@
 use crate::game_server::Broadcast;
-use crate::protocol::Channel;
+use crate::protocol::{Channel, DisconnectReason, ReceiveError, SequenceSnapshot};
 use parking_lot::Mutex;
 use std::collections::BTreeMap;
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 
 #[derive(Eq, PartialEq)]
 pub enum ReceiveResult {
     Success(u32),
     CreateChannelFirst,
+    WouldBlock,
+}
+
+struct SuspendedChannel {
+    guid: Option<u32>,
+    channel: Mutex<Channel>,
+    suspended_at: Instant,
 }
 
 pub struct ChannelManager {
     unauthenticated: BTreeMap<SocketAddr, Mutex<Channel>>,
     authenticated: AuthenticatedChannelManager,
+    suspended: BTreeMap<SocketAddr, SuspendedChannel>,
+    disconnect_grace_period: Duration,
+    disconnect_counts: Mutex<BTreeMap<DisconnectReason, u64>>,
 }
 
 impl ChannelManager {
-    pub fn new() -> Self {
+    pub fn new(disconnect_grace_period: Duration) -> Self {
         ChannelManager {
             unauthenticated: Default::default(),
             authenticated: Default::default(),
+            suspended: Default::default(),
+            disconnect_grace_period,
+            disconnect_counts: Default::default(),
         }
     }
 
+    // Keyed by metric label so the counts can be reported directly
+    pub fn disconnect_counts(&self) -> BTreeMap<&'static str, u64> {
+        self.disconnect_counts
+            .lock()
+            .iter()
+            .map(|(reason, count)| (reason.as_metric_label(), *count))
+            .collect()
+    }
+
+    fn disconnect(&self, channel: &mut Channel, reason: DisconnectReason) {
+        channel.disconnect(reason);
+        self.count_disconnect(reason);
+    }
+
+    fn count_disconnect(&self, reason: DisconnectReason) {
+        *self.disconnect_counts.lock().entry(reason).or_insert(0) += 1;
+    }
+
     pub fn get_by_addr(&self, addr: &SocketAddr) -> Option<&Mutex<Channel>> {
         self.unauthenticated
             .get(addr)
@@ -37,11 +69,17 @@ impl ChannelManager {
         self.authenticated.guid(addr)
     }
 
+    pub fn addr(&self, guid: u32) -> Option<SocketAddr> {
+        self.authenticated.addr(guid)
+    }
+
+    // A suspended channel at this address is left for remove_expired, so its player is still
+    // logged out
     pub fn insert(&mut self, addr: &SocketAddr, channel: Channel) -> Option<Mutex<Channel>> {
         let previous = self
             .unauthenticated
             .remove(addr)
-            .or(self.authenticated.remove(addr));
+            .or(self.authenticated.remove(addr).map(|(_, channel)| channel));
         self.unauthenticated.insert(*addr, Mutex::new(channel));
         previous
     }
@@ -54,10 +92,169 @@ impl ChannelManager {
         self.authenticated.insert(addr, guid, channel);
     }
 
+    pub fn suspend(&mut self, addr: &SocketAddr) {
+        let possible_channel = self
+            .unauthenticated
+            .remove(addr)
+            .map(|channel| (None, channel))
+            .or(self
+                .authenticated
+                .remove(addr)
+                .map(|(guid, channel)| (Some(guid), channel)));
+
+        if let Some((guid, channel)) = possible_channel {
+            self.suspended.insert(
+                *addr,
+                SuspendedChannel {
+                    guid,
+                    channel,
+                    suspended_at: Instant::now(),
+                },
+            );
+        }
+    }
+
+    pub fn suspend_timed_out(&mut self, timeout_millis: u128) {
+        // The sequence numbers are logged to help diagnose connections that stopped responding
+        let timed_out_channels: Vec<(SocketAddr, SequenceSnapshot)> = self
+            .unauthenticated
+            .iter()
+            .chain(self.authenticated.iter())
+            .filter_map(|(addr, channel)| {
+                let channel_handle = channel.lock();
+                (channel_handle.millis_since_last_receive() >= timeout_millis)
+                    .then(|| (*addr, channel_handle.sequence_snapshot()))
+            })
+            .collect();
+
+        for (addr, sequence_snapshot) in timed_out_channels {
+            println!(
+                "Channel {} timed out, suspending: {:?}",
+                addr, sequence_snapshot
+            );
+            self.suspend(&addr);
+            self.count_disconnect(DisconnectReason::Timeout);
+        }
+    }
+
+    // Resumes a suspended channel if the data comes from the same address or remaps a suspended
+    // channel's session to this address. Expired channels are left for remove_expired.
+    pub fn resume(&mut self, addr: &SocketAddr, data: &[u8]) -> bool {
+        let grace_period = self.disconnect_grace_period;
+        let possible_addr = if self.suspended.contains_key(addr) {
+            Some(*addr)
+        } else {
+            self.suspended
+                .iter()
+                .find(|(_, suspended)| suspended.channel.lock().matches_remap(data))
+                .map(|(previous_addr, _)| *previous_addr)
+        }
+        .filter(|previous_addr| {
+            self.suspended[previous_addr].suspended_at.elapsed() < grace_period
+        });
+
+        if let Some(previous_addr) = possible_addr {
+            let suspended = self
+                .suspended
+                .remove(&previous_addr)
+                .expect("Suspended channel was removed while resuming");
+
+            if let Some(guid) = suspended.guid {
+                self.authenticated.insert(addr, guid, suspended.channel);
+            } else {
+                self.unauthenticated.insert(*addr, suspended.channel);
+            }
+
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn remove_expired(&mut self) -> Vec<u32> {
+        let grace_period = self.disconnect_grace_period;
+        let expired_addrs: Vec<SocketAddr> = self
+            .suspended
+            .iter()
+            .filter(|(_, suspended)| suspended.suspended_at.elapsed() >= grace_period)
+            .map(|(addr, _)| *addr)
+            .collect();
+
+        let mut expired_guids = Vec::new();
+        for addr in expired_addrs {
+            // The client never came back during the grace period
+            println!("Removing suspended channel {}", addr);
+            self.count_disconnect(DisconnectReason::UnreachableConnection);
+            if let Some(guid) = self
+                .suspended
+                .remove(&addr)
+                .and_then(|suspended| suspended.guid)
+            {
+                expired_guids.push(guid);
+            }
+        }
+
+        expired_guids
+    }
+
+    // Closed channels have already told their client that they are ending, so there is no grace
+    // period to wait for
+    pub fn remove_closed(&mut self) -> Vec<u32> {
+        let closed_addrs: Vec<SocketAddr> = self
+            .unauthenticated
+            .iter()
+            .chain(self.authenticated.iter())
+            .filter(|(_, channel)| channel.lock().is_closed())
+            .map(|(addr, _)| *addr)
+            .collect();
+
+        let mut closed_guids = Vec::new();
+        for addr in closed_addrs {
+            println!("Removing closed channel {}", addr);
+            self.unauthenticated.remove(&addr);
+            if let Some((guid, _)) = self.authenticated.remove(&addr) {
+                closed_guids.push(guid);
+            }
+        }
+
+        closed_guids
+    }
+
+    // Tells every client that the server is closing their channel, returning the final packets
+    // to send to each client
+    pub fn shutdown(&mut self, reason: DisconnectReason) -> Vec<(SocketAddr, Vec<Vec<u8>>)> {
+        let mut final_packets = Vec::new();
+        let channels = self
+            .unauthenticated
+            .iter()
+            .chain(self.authenticated.iter())
+            .chain(
+                self.suspended
+                    .iter()
+                    .map(|(addr, suspended)| (addr, &suspended.channel)),
+            );
+
+        for (addr, channel) in channels {
+            let mut channel_handle = channel.lock();
+            self.disconnect(&mut channel_handle, reason);
+            match channel_handle.send_next(u8::MAX) {
+                Ok(packets) => final_packets.push((*addr, packets)),
+                Err(err) => println!("Send error while shutting down {}: {:?}", addr, err),
+            }
+        }
+
+        self.unauthenticated.clear();
+        self.authenticated = Default::default();
+        self.suspended.clear();
+
+        final_packets
+    }
+
     pub fn receive(&self, addr: &SocketAddr, data: &[u8]) -> ReceiveResult {
         if let Some(channel) = self.get_by_addr(addr) {
             match channel.lock().receive(data) {
                 Ok(packets_received) => ReceiveResult::Success(packets_received),
+                Err(ReceiveError::WouldBlock) => ReceiveResult::WouldBlock,
                 Err(err) => {
                     println!("Deserialize error on channel {}: {:?}", addr, err);
                     ReceiveResult::Success(0)
@@ -69,10 +266,21 @@ impl ChannelManager {
     }
 
     pub fn process_next(&self, addr: &SocketAddr, count: u8) -> Vec<Vec<u8>> {
-        self.get_by_addr(addr)
+        let mut channel_handle = self
+            .get_by_addr(addr)
             .expect("Tried to process data on non-existent channel")
-            .lock()
-            .process_next(count)
+            .lock();
+        let was_disconnecting = channel_handle.disconnect_reason().is_some();
+        let packets = channel_handle.process_next(count);
+
+        // The channel may disconnect itself, such as when a gap in the client's data goes stale
+        if !was_disconnecting {
+            if let Some(reason) = channel_handle.disconnect_reason() {
+                self.count_disconnect(reason);
+            }
+        }
+
+        packets
     }
 
     pub fn broadcast(&self, broadcasts: Vec<Broadcast>) -> Vec<u32> {
@@ -82,14 +290,25 @@ impl ChannelManager {
             let (guids, packets) = match broadcast {
                 Broadcast::Single(guid, packets) => (vec![guid], packets),
                 Broadcast::Multi(guids, packets) => (guids, packets),
+                Broadcast::Disconnect(guid, reason) => {
+                    if let Some(channel) = self.get_by_guid(guid) {
+                        self.disconnect(&mut channel.lock(), reason);
+                    } else {
+                        missing_guids.push(guid);
+                    }
+
+                    continue;
+                }
             };
 
             for guid in guids {
                 if let Some(channel) = self.get_by_guid(guid) {
                     let mut channel_handle = channel.lock();
-                    packets.iter().for_each(|packet| {
-                        channel_handle.prepare_to_send_data(packet.clone());
-                    })
+                    for packet in packets.iter() {
+                        if let Err(err) = channel_handle.prepare_to_send_data(packet.clone()) {
+                            println!("Unable to send data to player {}: {:?}", guid, err);
+                        }
+                    }
                 } else {
                     missing_guids.push(guid);
                 }
@@ -99,6 +318,54 @@ impl ChannelManager {
         missing_guids
     }
 
+    pub fn send_and_disconnect(
+        &self,
+        addr: &SocketAddr,
+        packets: Vec<Vec<u8>>,
+        reason: DisconnectReason,
+    ) {
+        if let Some(channel) = self.get_by_addr(addr) {
+            let mut channel_handle = channel.lock();
+            for packet in packets {
+                if let Err(err) = channel_handle.prepare_to_send_data(packet) {
+                    println!("Unable to send data to {}: {:?}", addr, err);
+                }
+            }
+            self.disconnect(&mut channel_handle, reason);
+        }
+    }
+
+    // Returns whether the channel was disconnected for sending too many unknown op codes in a row
+    pub fn record_unknown_op_code(
+        &self,
+        addr: &SocketAddr,
+        max_unknown_op_codes: Option<u32>,
+    ) -> bool {
+        let Some(channel) = self.get_by_addr(addr) else {
+            return false;
+        };
+
+        let mut channel_handle = channel.lock();
+        let unknown_op_codes = channel_handle.record_unknown_op_code();
+        match max_unknown_op_codes {
+            Some(max_unknown_op_codes) if unknown_op_codes > max_unknown_op_codes => {
+                println!(
+                    "Channel {} sent {} unknown op codes in a row, disconnecting",
+                    addr, unknown_op_codes
+                );
+                self.disconnect(&mut channel_handle, DisconnectReason::CorruptPacket);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn reset_unknown_op_codes(&self, addr: &SocketAddr) {
+        if let Some(channel) = self.get_by_addr(addr) {
+            channel.lock().reset_unknown_op_codes();
+        }
+    }
+
     pub fn send_next(&self, addr: &SocketAddr, count: u8) -> Vec<Vec<u8>> {
         let send_result = self
             .get_by_addr(addr)
@@ -116,6 +383,7 @@ impl ChannelManager {
 #[derive(Default)]
 struct AuthenticatedChannelManager {
     socket_to_guid: BTreeMap<SocketAddr, u32>,
+    guid_to_socket: BTreeMap<u32, SocketAddr>,
     channels: BTreeMap<u32, Mutex<Channel>>,
 }
 
@@ -135,21 +403,269 @@ impl AuthenticatedChannelManager {
         self.socket_to_guid.get(addr).copied()
     }
 
+    pub fn addr(&self, guid: u32) -> Option<SocketAddr> {
+        self.guid_to_socket.get(&guid).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&SocketAddr, &Mutex<Channel>)> {
+        self.socket_to_guid.iter().map(|(addr, guid)| {
+            (
+                addr,
+                self.channels
+                    .get(guid)
+                    .expect("Entry in socket to GUID mapping has no corresponding channel"),
+            )
+        })
+    }
+
     pub fn insert(
         &mut self,
         addr: &SocketAddr,
         guid: u32,
         channel: Mutex<Channel>,
     ) -> Option<Mutex<Channel>> {
+        // The player may have authenticated from a different address before
+        if let Some(previous_addr) = self.guid_to_socket.insert(guid, *addr) {
+            self.socket_to_guid.remove(&previous_addr);
+        }
+
         self.socket_to_guid.insert(*addr, guid);
         self.channels.insert(guid, channel)
     }
 
-    pub fn remove(&mut self, addr: &SocketAddr) -> Option<Mutex<Channel>> {
+    pub fn remove(&mut self, addr: &SocketAddr) -> Option<(u32, Mutex<Channel>)> {
         self.socket_to_guid.remove(addr).map(|guid| {
-            self.channels
-                .remove(&guid)
-                .expect("Entry in socket to GUID mapping has no corresponding channel")
+            self.guid_to_socket.remove(&guid);
+            (
+                guid,
+                self.channels
+                    .remove(&guid)
+                    .expect("Entry in socket to GUID mapping has no corresponding channel"),
+            )
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Packet, StaleGapPolicy};
+
+    fn test_addr() -> SocketAddr {
+        "127.0.0.1:20226".parse().unwrap()
+    }
+
+    fn make_suspended_manager(disconnect_grace_period: Duration) -> ChannelManager {
+        let mut channel_manager = ChannelManager::new(disconnect_grace_period);
+        channel_manager.insert(&test_addr(), Channel::new(200, 1000, 5));
+        channel_manager.authenticate(&test_addr(), 1);
+        channel_manager.suspend(&test_addr());
+        channel_manager
+    }
+
+    #[test]
+    fn test_resume_within_grace_period() {
+        let mut channel_manager = make_suspended_manager(Duration::from_secs(60));
+        assert!(channel_manager.get_by_addr(&test_addr()).is_none());
+
+        assert!(channel_manager.resume(&test_addr(), &[]));
+        assert!(channel_manager.get_by_addr(&test_addr()).is_some());
+        assert_eq!(channel_manager.guid(&test_addr()), Some(1));
+        assert!(channel_manager.remove_expired().is_empty());
+    }
+
+    #[test]
+    fn test_no_resume_after_grace_period() {
+        let mut channel_manager = make_suspended_manager(Duration::ZERO);
+        assert_eq!(channel_manager.remove_expired(), vec![1]);
+
+        assert!(!channel_manager.resume(&test_addr(), &[]));
+        assert!(channel_manager.get_by_addr(&test_addr()).is_none());
+    }
+
+    #[test]
+    fn test_expired_player_is_logged_out_after_failed_resume() {
+        let mut channel_manager = make_suspended_manager(Duration::ZERO);
+
+        // A datagram can arrive after the grace period but before the expired channel is removed
+        assert!(!channel_manager.resume(&test_addr(), &[]));
+        channel_manager.insert(&test_addr(), Channel::new(200, 1000, 5));
+
+        assert_eq!(channel_manager.remove_expired(), vec![1]);
+        assert!(channel_manager.get_by_addr(&test_addr()).is_some());
+    }
+
+    #[test]
+    fn test_timeouts_and_expiries_are_counted() {
+        let mut channel_manager = ChannelManager::new(Duration::ZERO);
+        channel_manager.insert(&test_addr(), Channel::new(200, 1000, 5));
+        channel_manager.authenticate(&test_addr(), 1);
+
+        channel_manager.suspend_timed_out(0);
+        assert_eq!(
+            channel_manager.disconnect_counts(),
+            BTreeMap::from([("timeout", 1)])
+        );
+
+        assert_eq!(channel_manager.remove_expired(), vec![1]);
+        assert_eq!(
+            channel_manager.disconnect_counts(),
+            BTreeMap::from([("timeout", 1), ("unreachable_connection", 1)])
+        );
+    }
+
+    #[test]
+    fn test_guid_maps_to_addr_while_authenticated() {
+        let mut channel_manager = ChannelManager::new(Duration::from_secs(60));
+        channel_manager.insert(&test_addr(), Channel::new(200, 1000, 5));
+        assert_eq!(channel_manager.addr(1), None);
+
+        channel_manager.authenticate(&test_addr(), 1);
+        assert_eq!(channel_manager.addr(1), Some(test_addr()));
+
+        channel_manager.suspend(&test_addr());
+        assert_eq!(channel_manager.addr(1), None);
+    }
+
+    #[test]
+    fn test_guid_maps_to_newest_addr() {
+        let new_addr = "127.0.0.1:20227".parse().unwrap();
+        let mut channel_manager = ChannelManager::new(Duration::from_secs(60));
+        channel_manager.insert(&test_addr(), Channel::new(200, 1000, 5));
+        channel_manager.authenticate(&test_addr(), 1);
+        channel_manager.insert(&new_addr, Channel::new(200, 1000, 5));
+        channel_manager.authenticate(&new_addr, 1);
+
+        assert_eq!(channel_manager.addr(1), Some(new_addr));
+        assert_eq!(channel_manager.guid(&test_addr()), None);
+    }
+
+    fn connect(channel_manager: &mut ChannelManager, addr: &SocketAddr) -> Channel {
+        connect_channel(channel_manager, addr, Channel::new(200, 1000, 0))
+    }
+
+    fn connect_channel(
+        channel_manager: &mut ChannelManager,
+        addr: &SocketAddr,
+        channel: Channel,
+    ) -> Channel {
+        let mut client = Channel::new(200, 1000, 0);
+        client.connect(12345, 200, "CloneWars".to_string());
+
+        channel_manager.insert(addr, channel);
+        for datagram in client.send_next(10).unwrap() {
+            channel_manager.receive(addr, &datagram);
+        }
+        channel_manager.process_next(addr, 10);
+
+        for datagram in channel_manager.send_next(addr, 10) {
+            client.receive(&datagram).unwrap();
+        }
+        client.process_next(10);
+        assert!(client.is_connected());
+
+        client
+    }
+
+    #[test]
+    fn test_shutdown_disconnects_every_channel() {
+        let authenticated_addr = "127.0.0.1:20227".parse().unwrap();
+        let mut channel_manager = ChannelManager::new(Duration::from_secs(60));
+        let unauthenticated_client = connect(&mut channel_manager, &test_addr());
+        let authenticated_client = connect(&mut channel_manager, &authenticated_addr);
+        channel_manager.authenticate(&authenticated_addr, 1);
+
+        let final_packets = channel_manager.shutdown(DisconnectReason::ManagerDeleted);
+        assert_eq!(final_packets.len(), 2);
+        for (addr, datagrams) in final_packets {
+            let client = if addr == authenticated_addr {
+                &authenticated_client
+            } else {
+                &unauthenticated_client
+            };
+
+            let disconnected = datagrams.iter().any(|datagram| {
+                client
+                    .decode_from_wire(datagram)
+                    .unwrap()
+                    .iter()
+                    .any(|packet| {
+                        matches!(
+                            packet,
+                            Packet::Disconnect(12345, DisconnectReason::ManagerDeleted)
+                        )
+                    })
+            });
+            assert!(disconnected);
+        }
+
+        assert!(channel_manager.get_by_addr(&test_addr()).is_none());
+        assert!(channel_manager.get_by_guid(1).is_none());
+        assert_eq!(
+            channel_manager.disconnect_counts(),
+            BTreeMap::from([("manager_deleted", 2)])
+        );
+    }
+
+    #[test]
+    fn test_repeated_unknown_op_codes_disconnect() {
+        let mut channel_manager = ChannelManager::new(Duration::from_secs(60));
+        connect(&mut channel_manager, &test_addr());
+        channel_manager.authenticate(&test_addr(), 1);
+
+        // Occasional unknown op codes between valid packets never reach the limit
+        for _ in 0..10 {
+            assert!(!channel_manager.record_unknown_op_code(&test_addr(), Some(3)));
+            assert!(!channel_manager.record_unknown_op_code(&test_addr(), Some(3)));
+            channel_manager.reset_unknown_op_codes(&test_addr());
+        }
+        assert!(channel_manager.disconnect_counts().is_empty());
+
+        for _ in 0..3 {
+            assert!(!channel_manager.record_unknown_op_code(&test_addr(), Some(3)));
+        }
+        assert!(channel_manager.record_unknown_op_code(&test_addr(), Some(3)));
+        assert_eq!(
+            channel_manager.disconnect_counts(),
+            BTreeMap::from([("corrupt_packet", 1)])
+        );
+    }
+
+    #[test]
+    fn test_unknown_op_codes_are_allowed_without_limit() {
+        let mut channel_manager = ChannelManager::new(Duration::from_secs(60));
+        connect(&mut channel_manager, &test_addr());
+
+        for _ in 0..1000 {
+            assert!(!channel_manager.record_unknown_op_code(&test_addr(), None));
+        }
+        assert!(channel_manager.disconnect_counts().is_empty());
+    }
+
+    #[test]
+    fn test_stale_gap_removes_channel() {
+        let mut channel_manager = ChannelManager::new(Duration::from_secs(60));
+        let client = connect_channel(
+            &mut channel_manager,
+            &test_addr(),
+            Channel::new(200, 1000, 0).with_max_reorder_age(0, StaleGapPolicy::Disconnect),
+        );
+        channel_manager.authenticate(&test_addr(), 1);
+
+        // Only the second packet arrives, leaving a gap that is immediately stale
+        let datagram = client.encode_for_wire(&Packet::Data(1, vec![1])).unwrap();
+        channel_manager.receive(&test_addr(), &datagram);
+        channel_manager.process_next(&test_addr(), 10);
+        channel_manager.process_next(&test_addr(), 10);
+        assert_eq!(
+            channel_manager.disconnect_counts(),
+            BTreeMap::from([("timeout", 1)])
+        );
+
+        // The channel stays until the client has been sent the disconnect
+        assert!(channel_manager.remove_closed().is_empty());
+        channel_manager.send_next(&test_addr(), 10);
+        assert_eq!(channel_manager.remove_closed(), vec![1]);
+        assert!(channel_manager.get_by_guid(1).is_none());
+    }
+}
@@ -0,0 +1,154 @@
+use std::fs::{create_dir_all, File};
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game_server::game_packet::Pos;
+use crate::game_server::zone::{Character, CharacterType};
+
+#[derive(Clone, Deserialize, PartialEq, Serialize)]
+pub struct SavedCharacter {
+    pub guid: u64,
+    pub instance_guid: u64,
+    pub pos: Pos,
+    pub rot: Pos,
+    pub health: u32,
+    pub power: u32,
+    pub owned_mounts: Vec<u32>,
+    // Saves from before the tutorial existed are treated as new players
+    #[serde(default)]
+    pub tutorial_complete: bool,
+}
+
+impl From<&Character> for SavedCharacter {
+    fn from(character: &Character) -> Self {
+        let (owned_mounts, tutorial_complete) = match &character.character_type {
+            CharacterType::Player(player) => {
+                (player.owned_mounts.clone(), player.tutorial_complete)
+            }
+            _ => (Vec::new(), false),
+        };
+
+        SavedCharacter {
+            guid: character.guid,
+            instance_guid: character.instance_guid,
+            pos: character.pos,
+            rot: character.rot,
+            health: character.health,
+            power: character.power,
+            owned_mounts,
+            tutorial_complete,
+        }
+    }
+}
+
+pub trait CharacterStore: Send + Sync {
+    // Returns None for a character that has never been saved
+    fn load(&self, guid: u64) -> Result<Option<SavedCharacter>, Error>;
+
+    fn save(&self, character: &Character) -> Result<(), Error>;
+}
+
+pub struct JsonCharacterStore {
+    dir: PathBuf,
+}
+
+impl JsonCharacterStore {
+    pub fn new(dir: PathBuf) -> Self {
+        JsonCharacterStore { dir }
+    }
+
+    fn path(&self, guid: u64) -> PathBuf {
+        self.dir.join(format!("{}.json", guid))
+    }
+}
+
+impl CharacterStore for JsonCharacterStore {
+    fn load(&self, guid: u64) -> Result<Option<SavedCharacter>, Error> {
+        match File::open(self.path(guid)) {
+            Ok(mut file) => Ok(Some(serde_json::from_reader(&mut file)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn save(&self, character: &Character) -> Result<(), Error> {
+        create_dir_all(&self.dir)?;
+
+        // Write to a temporary file first so a crash mid-write can't corrupt the last save
+        let temp_path = self.path(character.guid).with_extension("json.tmp");
+        let mut file = File::create(&temp_path)?;
+        serde_json::to_writer(&mut file, &SavedCharacter::from(character))?;
+        file.sync_all()?;
+        std::fs::rename(temp_path, self.path(character.guid))
+    }
+}
+
+// Clones share the same saves so tests can inspect a store after handing it to the server
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct MemoryCharacterStore {
+    pub saves: std::sync::Arc<parking_lot::Mutex<std::collections::BTreeMap<u64, SavedCharacter>>>,
+}
+
+#[cfg(test)]
+impl CharacterStore for MemoryCharacterStore {
+    fn load(&self, guid: u64) -> Result<Option<SavedCharacter>, Error> {
+        Ok(self.saves.lock().get(&guid).cloned())
+    }
+
+    fn save(&self, character: &Character) -> Result<(), Error> {
+        self.saves
+            .lock()
+            .insert(character.guid, SavedCharacter::from(character));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::env::temp_dir;
+    use std::fs::remove_dir_all;
+
+    use super::*;
+    use crate::game_server::player_data::make_test_player;
+    use crate::game_server::zone::PlayerState;
+
+    fn test_store(name: &str) -> JsonCharacterStore {
+        let dir = temp_dir().join(format!("cwa-server-{}-{}", name, std::process::id()));
+        let _ = remove_dir_all(&dir);
+        JsonCharacterStore::new(dir)
+    }
+
+    #[test]
+    fn test_load_missing_character() {
+        let store = test_store("load-missing");
+        assert!(store.load(1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_save_then_load_round_trips() {
+        let store = test_store("round-trip");
+        let mut character = make_test_player(1, &BTreeMap::new()).data.to_character(24);
+        character.pos = Pos {
+            x: 10.0,
+            y: 20.0,
+            z: 30.0,
+            w: 1.0,
+        };
+        character.character_type = CharacterType::Player(PlayerState {
+            owned_mounts: vec![3, 7],
+            ..PlayerState::default()
+        });
+
+        store.save(&character).unwrap();
+        let saved = store.load(character.guid).unwrap().unwrap();
+
+        assert_eq!(saved.pos, character.pos);
+        assert_eq!(saved.owned_mounts, vec![3, 7]);
+        assert_eq!(saved.instance_guid, 24);
+        remove_dir_all(&store.dir).unwrap();
+    }
+}
@@ -8,9 +8,13 @@ use packet_serialize::{
 };
 
 use crate::game_server::game_packet::{GamePacket, OpCode, Pos};
+use crate::game_server::lock_enforcer::CharacterLockRequest;
+use crate::game_server::player_update_packet::hud_message;
+use crate::game_server::server_config::ChatRateLimit;
 use crate::game_server::tunnel::TunneledPacket;
 use crate::game_server::unique_guid::player_guid;
-use crate::game_server::{Broadcast, ProcessPacketError};
+use crate::game_server::zone::{Character, CharacterType, Zone};
+use crate::game_server::{Broadcast, GameServer, ProcessPacketError};
 
 #[derive(Copy, Clone, Debug, TryFromPrimitive)]
 #[repr(u16)]
@@ -76,6 +80,24 @@ pub enum SendMessage {
     MembersOnly(MessagePayload),
 }
 
+impl SendMessage {
+    fn payload_mut(&mut self) -> &mut MessagePayload {
+        match self {
+            SendMessage::World(payload)
+            | SendMessage::Whisper(payload)
+            | SendMessage::System(payload)
+            | SendMessage::ReceivedItems(payload)
+            | SendMessage::Group(payload)
+            | SendMessage::Yell(payload)
+            | SendMessage::Trade(payload)
+            | SendMessage::LookingForGroup(payload)
+            | SendMessage::Area(payload, _)
+            | SendMessage::Guild(payload)
+            | SendMessage::MembersOnly(payload) => payload,
+        }
+    }
+}
+
 impl SerializePacket for SendMessage {
     fn serialize(&self, buffer: &mut Vec<u8>) -> Result<(), SerializePacketError> {
         match self {
@@ -164,67 +186,144 @@ impl GamePacket for SendMessage {
     const HEADER: Self::Header = ChatOpCode::SendMessage;
 }
 
+pub fn system_message(message: String) -> Result<Vec<u8>, SerializePacketError> {
+    GamePacket::serialize(&TunneledPacket {
+        unknown1: true,
+        inner: SendMessage::System(MessagePayload {
+            sender_guid: 0,
+            unknown1: 0,
+            unknown2: 0,
+            unknown3: 0,
+            unknown4: 0,
+            sender_first_name: "".to_string(),
+            sender_last_name: "".to_string(),
+            unknown5: 0,
+            unknown6: 0,
+            unknown7: 0,
+            target_first_name: "".to_string(),
+            target_last_name: "".to_string(),
+            message,
+            pos: Pos {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                w: 0.0,
+            },
+            unknown8: 0,
+            character_type: 0,
+        }),
+    })
+}
+
+// Longer messages are truncated so a client can't fill everyone's chat window
+const MAX_MESSAGE_LENGTH: usize = 256;
+
+fn sanitize_message(message: &str) -> String {
+    message
+        .chars()
+        .filter(|character| !character.is_control())
+        .take(MAX_MESSAGE_LENGTH)
+        .collect()
+}
+
+fn take_chat_token(character: &mut Character, chat_rate_limit: &ChatRateLimit) -> bool {
+    let CharacterType::Player(player) = &mut character.character_type else {
+        return false;
+    };
+
+    if player.chat_tokens_used >= chat_rate_limit.burst {
+        return false;
+    }
+
+    player.chat_tokens_used += 1;
+    true
+}
+
+pub fn refill_chat_tokens(
+    character: &mut Character,
+    chat_rate_limit: &ChatRateLimit,
+    elapsed_millis: u128,
+) {
+    let CharacterType::Player(player) = &mut character.character_type else {
+        return;
+    };
+
+    // Don't save up refill time while the bucket is full
+    if player.chat_tokens_used == 0 || chat_rate_limit.refill_millis == 0 {
+        player.chat_tokens_used = 0;
+        player.chat_refill_elapsed_millis = 0;
+        return;
+    }
+
+    player.chat_refill_elapsed_millis += elapsed_millis;
+    let refilled_tokens = player.chat_refill_elapsed_millis / chat_rate_limit.refill_millis as u128;
+    player.chat_refill_elapsed_millis -= refilled_tokens * chat_rate_limit.refill_millis as u128;
+    player.chat_tokens_used = player
+        .chat_tokens_used
+        .saturating_sub(refilled_tokens.min(u32::MAX as u128) as u32);
+}
+
 pub fn process_chat_packet(
     cursor: &mut Cursor<&[u8]>,
     sender: u32,
+    game_server: &GameServer,
 ) -> Result<Vec<Broadcast>, ProcessPacketError> {
     let raw_op_code = cursor.read_u16::<LittleEndian>()?;
     match ChatOpCode::try_from(raw_op_code) {
         Ok(op_code) => match op_code {
             ChatOpCode::SendMessage => {
-                let message = SendMessage::deserialize(cursor)?;
-                Ok(vec![Broadcast::Single(
-                    sender,
-                    vec![GamePacket::serialize(&TunneledPacket {
-                        unknown1: true,
-                        inner: match message {
-                            SendMessage::World(mut payload) => {
-                                payload.sender_guid = player_guid(sender);
-                                SendMessage::World(payload)
-                            }
-                            SendMessage::Whisper(mut payload) => {
-                                payload.sender_guid = player_guid(sender);
-                                SendMessage::Whisper(payload)
-                            }
-                            SendMessage::System(mut payload) => {
-                                payload.sender_guid = player_guid(sender);
-                                SendMessage::System(payload)
-                            }
-                            SendMessage::ReceivedItems(mut payload) => {
-                                payload.sender_guid = player_guid(sender);
-                                SendMessage::ReceivedItems(payload)
-                            }
-                            SendMessage::Group(mut payload) => {
-                                payload.sender_guid = player_guid(sender);
-                                SendMessage::Group(payload)
-                            }
-                            SendMessage::Yell(mut payload) => {
-                                payload.sender_guid = player_guid(sender);
-                                SendMessage::Yell(payload)
-                            }
-                            SendMessage::Trade(mut payload) => {
-                                payload.sender_guid = player_guid(sender);
-                                SendMessage::Trade(payload)
-                            }
-                            SendMessage::LookingForGroup(mut payload) => {
-                                payload.sender_guid = player_guid(sender);
-                                SendMessage::LookingForGroup(payload)
-                            }
-                            SendMessage::Area(mut payload, unknown) => {
-                                payload.sender_guid = player_guid(sender);
-                                SendMessage::Area(payload, unknown)
-                            }
-                            SendMessage::Guild(mut payload) => {
-                                payload.sender_guid = player_guid(sender);
-                                SendMessage::Guild(payload)
+                let mut message = SendMessage::deserialize(cursor)?;
+                let payload = message.payload_mut();
+                payload.sender_guid = player_guid(sender);
+                payload.message = sanitize_message(&payload.message);
+                if payload.message.is_empty() {
+                    return Ok(Vec::new());
+                }
+
+                let zone_scope = matches!(message, SendMessage::World(_) | SendMessage::Area(..));
+                let packets = vec![GamePacket::serialize(&TunneledPacket {
+                    unknown1: true,
+                    inner: message,
+                })?];
+
+                game_server
+                    .lock_enforcer()
+                    .read_characters(|_| CharacterLockRequest {
+                        read_guids: Vec::new(),
+                        write_guids: vec![player_guid(sender)],
+                        character_consumer: |characters_table_read_handle,
+                                             _,
+                                             mut characters_write,
+                                             _| {
+                            let Some(character_write_handle) =
+                                characters_write.get_mut(&player_guid(sender))
+                            else {
+                                println!("Unknown player {} sent a chat message", sender);
+                                return Err(ProcessPacketError::CorruptedPacket);
+                            };
+
+                            if let Some(chat_rate_limit) = game_server.server_config.chat_rate_limit
+                            {
+                                if !take_chat_token(character_write_handle, &chat_rate_limit) {
+                                    return Ok(vec![Broadcast::Single(
+                                        sender,
+                                        vec![hud_message(chat_rate_limit.throttle_message)?],
+                                    )]);
+                                }
                             }
-                            SendMessage::MembersOnly(mut payload) => {
-                                payload.sender_guid = player_guid(sender);
-                                SendMessage::MembersOnly(payload)
+
+                            if zone_scope {
+                                Ok(Zone::player_broadcast(
+                                    character_write_handle.instance_guid,
+                                    characters_table_read_handle,
+                                    packets,
+                                    None,
+                                ))
+                            } else {
+                                Ok(vec![Broadcast::Single(sender, packets)])
                             }
                         },
-                    })?],
-                )])
+                    })
             }
         },
         Err(_) => {
@@ -233,3 +332,113 @@ pub fn process_chat_packet(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use crate::game_server::guid::GuidTable;
+    use crate::game_server::player_data::make_test_player;
+
+    fn make_payload(message: &str) -> MessagePayload {
+        MessagePayload {
+            sender_guid: 0,
+            unknown1: 0,
+            unknown2: 0,
+            unknown3: 0,
+            unknown4: 0,
+            sender_first_name: "".to_string(),
+            sender_last_name: "".to_string(),
+            unknown5: 0,
+            unknown6: 0,
+            unknown7: 0,
+            target_first_name: "".to_string(),
+            target_last_name: "".to_string(),
+            message: message.to_string(),
+            pos: Pos {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                w: 0.0,
+            },
+            unknown8: 0,
+            character_type: 0,
+        }
+    }
+
+    fn make_game_server(players: &[(u32, u64)]) -> GameServer {
+        let characters = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            for (guid, instance_guid) in players {
+                characters_write_handle.insert(
+                    make_test_player(*guid, &BTreeMap::new())
+                        .data
+                        .to_character(*instance_guid),
+                );
+            }
+        }
+        GameServer::for_test(characters, GuidTable::new())
+    }
+
+    #[test]
+    fn test_zone_chat_reaches_only_players_in_zone() {
+        let game_server = make_game_server(&[(1, 24), (2, 24), (3, 25)]);
+
+        let request =
+            GamePacket::serialize(&SendMessage::World(make_payload("hello\u{7}there"))).unwrap();
+        let mut cursor = Cursor::new(&request[2..]);
+        let broadcasts = process_chat_packet(&mut cursor, 1, &game_server).unwrap();
+
+        let mut expected_payload = make_payload("hellothere");
+        expected_payload.sender_guid = player_guid(1);
+        let expected_packet = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: SendMessage::World(expected_payload),
+        })
+        .unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Multi(guids, packets)] if guids == &vec![1, 2] && packets == &vec![expected_packet]));
+    }
+
+    #[test]
+    fn test_chat_burst_beyond_rate_limit_is_throttled() {
+        let mut game_server = make_game_server(&[(1, 24), (2, 24)]);
+        game_server.server_config.chat_rate_limit = Some(ChatRateLimit {
+            burst: 3,
+            refill_millis: 1000,
+            throttle_message: 1234,
+        });
+
+        let request = GamePacket::serialize(&SendMessage::World(make_payload("spam"))).unwrap();
+        let throttle_notice = hud_message(1234).unwrap();
+        let mut delivered = 0;
+        for _ in 0..10 {
+            let mut cursor = Cursor::new(&request[2..]);
+            match &process_chat_packet(&mut cursor, 1, &game_server).unwrap()[..] {
+                [Broadcast::Multi(guids, _)] if guids == &vec![1, 2] => delivered += 1,
+                [Broadcast::Single(1, packets)] if packets == &vec![throttle_notice.clone()] => {}
+                _ => panic!("Unexpected chat broadcast"),
+            }
+        }
+        assert_eq!(delivered, 3);
+
+        game_server.tick(1000).unwrap();
+        let mut cursor = Cursor::new(&request[2..]);
+        assert!(matches!(
+            &process_chat_packet(&mut cursor, 1, &game_server).unwrap()[..],
+            [Broadcast::Multi(..)]
+        ));
+    }
+
+    #[test]
+    fn test_sanitize_message_strips_control_characters_and_truncates() {
+        assert_eq!(sanitize_message("a\nb\u{0}c"), "abc");
+        assert_eq!(
+            sanitize_message(&"a".repeat(MAX_MESSAGE_LENGTH + 10)).len(),
+            MAX_MESSAGE_LENGTH
+        );
+    }
+}
@@ -11,10 +11,13 @@ use crate::game_server::item::{EquipmentSlot, Item, ItemDefinition};
 pub enum ClientUpdateOpCode {
     Health = 0x1,
     AddItems = 0x2,
+    UpdateItem = 0x3,
+    RemoveItem = 0x4,
     EquipItem = 0x5,
     Position = 0xc,
     Power = 0xd,
     Stats = 0x7,
+    UpdateCurrency = 0x14,
     PreloadCharactersDone = 0x1a,
 }
 
@@ -64,6 +67,36 @@ impl GamePacket for AddItems {
     const HEADER: Self::Header = ClientUpdateOpCode::AddItems;
 }
 
+#[derive(SerializePacket)]
+pub struct UpdateItem {
+    pub item: Item,
+}
+
+impl GamePacket for UpdateItem {
+    type Header = ClientUpdateOpCode;
+    const HEADER: Self::Header = ClientUpdateOpCode::UpdateItem;
+}
+
+#[derive(SerializePacket)]
+pub struct RemoveItem {
+    pub item_guid: u32,
+}
+
+impl GamePacket for RemoveItem {
+    type Header = ClientUpdateOpCode;
+    const HEADER: Self::Header = ClientUpdateOpCode::RemoveItem;
+}
+
+#[derive(SerializePacket)]
+pub struct UpdateCurrency {
+    pub currency: u32,
+}
+
+impl GamePacket for UpdateCurrency {
+    type Header = ClientUpdateOpCode;
+    const HEADER: Self::Header = ClientUpdateOpCode::UpdateCurrency;
+}
+
 #[derive(SerializePacket)]
 pub struct EquipItem {
     pub item_guid: u32,
@@ -105,7 +138,7 @@ impl GamePacket for Power {
     const HEADER: ClientUpdateOpCode = ClientUpdateOpCode::Power;
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub enum StatId {
     MaxHealth = 1,
     Speed = 2,
@@ -1,4 +1,6 @@
+use crate::game_server::emote::play_emote;
 use crate::game_server::game_packet::{GamePacket, OpCode};
+use crate::game_server::unique_guid::player_guid;
 use crate::game_server::zone::interact_with_character;
 use crate::game_server::{Broadcast, GameServer, ProcessPacketError};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
@@ -8,6 +10,7 @@ use std::io::Cursor;
 
 pub fn process_command(
     game_server: &GameServer,
+    sender: u32,
     cursor: &mut Cursor<&[u8]>,
 ) -> Result<Vec<Broadcast>, ProcessPacketError> {
     let raw_op_code = cursor.read_u16::<LittleEndian>()?;
@@ -15,16 +18,33 @@ pub fn process_command(
         Ok(op_code) => match op_code {
             CommandOpCode::SelectPlayer => {
                 let req = SelectPlayer::deserialize(cursor)?;
+                if req.requester != player_guid(sender) {
+                    println!(
+                        "Player {} tried to select a character as {}",
+                        sender, req.requester
+                    );
+                    return Err(ProcessPacketError::CorruptedPacket);
+                }
+
                 interact_with_character(req, game_server)
             }
+            CommandOpCode::PlayEmote => {
+                let req = PlayEmote::deserialize(cursor)?;
+                if req.guid != player_guid(sender) {
+                    println!("Player {} tried to play an emote as {}", sender, req.guid);
+                    return Err(ProcessPacketError::CorruptedPacket);
+                }
+
+                play_emote(req, game_server)
+            }
             _ => {
                 println!("Unimplemented command: {:?}", op_code);
-                Ok(Vec::new())
+                Err(ProcessPacketError::UnknownOpCode)
             }
         },
         Err(_) => {
             println!("Unknown command: {}", raw_op_code);
-            Ok(Vec::new())
+            Err(ProcessPacketError::UnknownOpCode)
         }
     }
 }
@@ -35,6 +55,7 @@ pub enum CommandOpCode {
     InteractionList = 0x9,
     SelectPlayer = 0xf,
     ChatBubbleColor = 0xe,
+    PlayEmote = 0x10,
 }
 
 impl SerializePacket for CommandOpCode {
@@ -116,3 +137,14 @@ impl GamePacket for SelectPlayer {
     type Header = CommandOpCode;
     const HEADER: Self::Header = CommandOpCode::SelectPlayer;
 }
+
+#[derive(SerializePacket, DeserializePacket)]
+pub struct PlayEmote {
+    pub guid: u64,
+    pub animation_slot: i32,
+}
+
+impl GamePacket for PlayEmote {
+    type Header = CommandOpCode;
+    const HEADER: Self::Header = CommandOpCode::PlayEmote;
+}
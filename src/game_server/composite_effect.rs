@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+pub struct CompositeEffects {
+    ids: BTreeMap<String, u32>,
+}
+
+impl CompositeEffects {
+    pub fn id(&self, name: &str) -> Result<u32, Error> {
+        self.ids.get(name).copied().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Unknown composite effect \"{}\"", name),
+            )
+        })
+    }
+}
+
+impl From<BTreeMap<String, u32>> for CompositeEffects {
+    fn from(ids: BTreeMap<String, u32>) -> Self {
+        CompositeEffects { ids }
+    }
+}
+
+pub fn load_composite_effects(config_dir: &Path) -> Result<CompositeEffects, Error> {
+    let mut file = File::open(config_dir.join("composite_effects.json"))?;
+    let ids: BTreeMap<String, u32> = serde_json::from_reader(&mut file)?;
+    Ok(CompositeEffects::from(ids))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_named_effect() {
+        let composite_effects =
+            CompositeEffects::from(BTreeMap::from([("atrt_mount".to_string(), 2696)]));
+        assert_eq!(composite_effects.id("atrt_mount").unwrap(), 2696);
+    }
+
+    #[test]
+    fn test_reject_unknown_effect() {
+        let composite_effects =
+            CompositeEffects::from(BTreeMap::from([("atrt_mount".to_string(), 2696)]));
+        let err = composite_effects.id("atrt_dismount").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("atrt_dismount"));
+    }
+
+    #[test]
+    fn test_config_mounts_use_known_effects() {
+        let composite_effects = load_composite_effects(Path::new("config")).unwrap();
+        assert!(crate::game_server::mount::load_mounts(
+            Path::new("config"),
+            &composite_effects,
+            None
+        )
+        .is_ok());
+    }
+}
@@ -0,0 +1,193 @@
+use crate::game_server::command::PlayEmote;
+use crate::game_server::game_packet::GamePacket;
+use crate::game_server::lock_enforcer::CharacterLockRequest;
+use crate::game_server::player_update_packet::SetAnimation;
+use crate::game_server::tunnel::TunneledPacket;
+use crate::game_server::zone::{Character, Zone};
+use crate::game_server::{Broadcast, GameServer, ProcessPacketError};
+
+pub const NO_ANIMATION_SLOT: i32 = -1;
+
+impl Character {
+    // Spawn packets carry the slot too, so characters that come into view later also see
+    // the animation
+    pub fn set_animation(
+        &mut self,
+        animation_slot: i32,
+    ) -> Result<Vec<Vec<u8>>, ProcessPacketError> {
+        self.animation_slot = animation_slot;
+        Ok(vec![GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: SetAnimation {
+                guid: self.guid,
+                animation_slot,
+            },
+        })?])
+    }
+
+    pub fn play_emote(
+        &mut self,
+        animation_slot: i32,
+        duration_millis: Option<u128>,
+    ) -> Result<Vec<Vec<u8>>, ProcessPacketError> {
+        self.emote_millis_remaining = duration_millis;
+        self.set_animation(animation_slot)
+    }
+
+    // Emotes without a duration keep playing until the character moves
+    pub fn advance_emote(
+        &mut self,
+        elapsed_millis: u128,
+    ) -> Result<Vec<Vec<u8>>, ProcessPacketError> {
+        let Some(emote_millis_remaining) = self.emote_millis_remaining else {
+            return Ok(Vec::new());
+        };
+
+        if emote_millis_remaining > elapsed_millis {
+            self.emote_millis_remaining = Some(emote_millis_remaining - elapsed_millis);
+            return Ok(Vec::new());
+        }
+
+        self.stop_emote()
+    }
+
+    pub fn stop_emote(&mut self) -> Result<Vec<Vec<u8>>, ProcessPacketError> {
+        self.emote_millis_remaining = None;
+        if self.animation_slot == NO_ANIMATION_SLOT {
+            return Ok(Vec::new());
+        }
+
+        self.set_animation(NO_ANIMATION_SLOT)
+    }
+}
+
+pub fn play_emote(
+    request: PlayEmote,
+    game_server: &GameServer,
+) -> Result<Vec<Broadcast>, ProcessPacketError> {
+    let duration_millis = game_server
+        .server_config
+        .emote_duration_secs
+        .map(|duration_secs| duration_secs as u128 * 1000);
+    game_server
+        .lock_enforcer()
+        .read_characters(|_| CharacterLockRequest {
+            read_guids: Vec::new(),
+            write_guids: vec![request.guid],
+            character_consumer: |characters_table_read_handle, _, mut characters_write, _| {
+                if let Some(character_write_handle) = characters_write.get_mut(&request.guid) {
+                    let packets = character_write_handle
+                        .play_emote(request.animation_slot, duration_millis)?;
+                    Ok(Zone::player_broadcast(
+                        character_write_handle.instance_guid,
+                        characters_table_read_handle,
+                        packets,
+                        None,
+                    ))
+                } else {
+                    println!("Unknown character {} tried to play an emote", request.guid);
+                    Err(ProcessPacketError::CorruptedPacket)
+                }
+            },
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_server::guid::GuidTable;
+    use crate::game_server::zone::{
+        make_test_character, make_test_npc_data, make_test_zone, move_to, CharacterType,
+        PlayerState,
+    };
+
+    #[test]
+    fn test_set_animation_broadcasts_and_persists_into_spawn() {
+        let mut character = make_test_character(1, CharacterType::Npc(make_test_npc_data()));
+
+        let packets = character.set_animation(7).unwrap();
+        let expected_packet = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: SetAnimation {
+                guid: 1,
+                animation_slot: 7,
+            },
+        })
+        .unwrap();
+        assert_eq!(packets, vec![expected_packet]);
+
+        let mut expected_character =
+            make_test_character(1, CharacterType::Npc(make_test_npc_data()));
+        expected_character.animation_slot = 7;
+        assert_eq!(
+            character.to_packets().unwrap(),
+            expected_character.to_packets().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_emote_broadcasts_animation_slot_to_zone() {
+        let characters = GuidTable::new();
+        let zones = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            for guid in [1, 2] {
+                characters_write_handle.insert(make_test_character(
+                    guid,
+                    CharacterType::Player(PlayerState::default()),
+                ));
+            }
+            let mut other_zone_player =
+                make_test_character(3, CharacterType::Player(PlayerState::default()));
+            other_zone_player.instance_guid = 25;
+            characters_write_handle.insert(other_zone_player);
+            let zone = make_test_zone(24, &mut characters_write_handle);
+            zones.write().insert(zone);
+        }
+
+        let mut game_server = GameServer::for_test(characters, zones);
+        game_server.server_config.emote_duration_secs = Some(2);
+        let animation_packet = |animation_slot| {
+            GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: SetAnimation {
+                    guid: 1,
+                    animation_slot,
+                },
+            })
+            .unwrap()
+        };
+
+        let broadcasts = play_emote(
+            PlayEmote {
+                guid: 1,
+                animation_slot: 7,
+            },
+            &game_server,
+        )
+        .unwrap();
+        let expected_packets = vec![animation_packet(7)];
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Multi(guids, packets)] if guids == &vec![1, 2] && packets == &expected_packets));
+
+        // The emote keeps playing until its duration has passed
+        assert!(game_server.tick(1000).unwrap().is_empty());
+        let expected_packets = vec![animation_packet(NO_ANIMATION_SLOT)];
+        assert!(matches!(&game_server.tick(1000).unwrap()[..],
+            [Broadcast::Multi(guids, packets)] if guids == &vec![1, 2] && packets == &expected_packets));
+
+        // Moving interrupts an emote before its duration has passed
+        play_emote(
+            PlayEmote {
+                guid: 1,
+                animation_slot: 7,
+            },
+            &game_server,
+        )
+        .unwrap();
+        let broadcasts = Zone::move_character(move_to(5.0), &game_server).unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Multi(guids, packets), ..] if guids == &vec![1, 2] && packets == &expected_packets));
+        assert!(game_server.tick(2000).unwrap().is_empty());
+    }
+}
@@ -1,7 +1,7 @@
 use byteorder::{LittleEndian, WriteBytesExt};
 use num_enum::TryFromPrimitive;
 use packet_serialize::{DeserializePacket, SerializePacket, SerializePacketError};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Copy, Clone, Debug, TryFromPrimitive)]
 #[repr(u16)]
@@ -57,7 +57,9 @@ pub trait GamePacket: SerializePacket {
     }
 }
 
-#[derive(Copy, Clone, SerializePacket, DeserializePacket, Deserialize)]
+#[derive(
+    Copy, Clone, Debug, PartialEq, SerializePacket, DeserializePacket, Deserialize, Serialize,
+)]
 pub struct Pos {
     pub x: f32,
     pub y: f32,
@@ -88,5 +90,208 @@ pub struct Effect {
     pub unknown19: bool,
 }
 
+impl Effect {
+    pub fn from_composite_effect(composite_effect: u32) -> Self {
+        Effect {
+            unknown1: 0,
+            unknown2: 0,
+            unknown3: 0,
+            unknown4: 0,
+            unknown5: 0,
+            unknown6: 0,
+            unknown7: 0,
+            unknown8: false,
+            unknown9: 0,
+            unknown10: 0,
+            unknown11: 0,
+            unknown12: 0,
+            composite_effect,
+            unknown14: 0,
+            unknown15: 0,
+            unknown16: 0,
+            unknown17: false,
+            unknown18: false,
+            unknown19: false,
+        }
+    }
+}
+
 pub type StringId = u32;
 pub type ImageId = u32;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_server::client_update_packet::{
+        AddItems, EquipItem, Health, Position, Power, PreloadCharactersDone, Stats,
+    };
+    use crate::game_server::command::{ChatBubbleColor, InteractionList, PlayEmote, SelectPlayer};
+    use crate::game_server::mount::{DismountReply, MountReply, MountSpawn};
+    use crate::game_server::player_update_packet::{
+        AddNotifications, AddNpc, ClearRail, Freeze, HudMessage, Knockback, LootEvent, MoveOnRail,
+        MoveOnRelativeRail, NameplateImageId, NpcRelevance, RemoveGracefully, RemoveStandard,
+        ReplaceBaseModel, SeekTarget, SeekTargetUpdate, SetAnimation, SetCollision,
+        SetSpawnerActivationEffect, SlotCompositeEffectOverride, UpdateCharacterState, UpdatePower,
+        UpdateRemoveTemporaryAppearance, UpdateTemporaryAppearance, WieldType,
+    };
+
+    fn header<T: GamePacket>() -> Vec<u8> {
+        let mut buffer = Vec::new();
+        SerializePacket::serialize(&T::HEADER, &mut buffer).unwrap();
+        buffer
+    }
+
+    fn assert_headers(headers: Vec<(&str, Vec<u8>, Vec<u8>)>) {
+        for (name, actual, expected) in headers {
+            assert_eq!(actual, expected, "wrong header for {}", name);
+        }
+    }
+
+    #[test]
+    fn test_client_update_headers() {
+        assert_headers(vec![
+            ("Health", header::<Health>(), vec![0x26, 0, 0x1, 0]),
+            ("AddItems", header::<AddItems>(), vec![0x26, 0, 0x2, 0]),
+            ("EquipItem", header::<EquipItem>(), vec![0x26, 0, 0x5, 0]),
+            ("Stats", header::<Stats>(), vec![0x26, 0, 0x7, 0]),
+            ("Position", header::<Position>(), vec![0x26, 0, 0xc, 0]),
+            ("Power", header::<Power>(), vec![0x26, 0, 0xd, 0]),
+            (
+                "PreloadCharactersDone",
+                header::<PreloadCharactersDone>(),
+                vec![0x26, 0, 0x1a, 0],
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_player_update_headers() {
+        assert_headers(vec![
+            ("AddNpc", header::<AddNpc>(), vec![0x23, 0, 0x2, 0]),
+            (
+                "RemoveStandard",
+                header::<RemoveStandard>(),
+                vec![0x23, 0, 0x3, 0, 0x0, 0],
+            ),
+            (
+                "RemoveGracefully",
+                header::<RemoveGracefully>(),
+                vec![0x23, 0, 0x3, 0, 0x1, 0],
+            ),
+            ("Knockback", header::<Knockback>(), vec![0x23, 0, 0x4, 0]),
+            (
+                "SetAnimation",
+                header::<SetAnimation>(),
+                vec![0x23, 0, 0x5, 0],
+            ),
+            (
+                "UpdatePower",
+                header::<UpdatePower>(),
+                vec![0x23, 0, 0x9, 0],
+            ),
+            (
+                "AddNotifications",
+                header::<AddNotifications>(),
+                vec![0x23, 0, 0xa, 0],
+            ),
+            (
+                "NpcRelevance",
+                header::<NpcRelevance>(),
+                vec![0x23, 0, 0xc, 0],
+            ),
+            (
+                "UpdateTemporaryAppearance",
+                header::<UpdateTemporaryAppearance>(),
+                vec![0x23, 0, 0xe, 0],
+            ),
+            (
+                "UpdateRemoveTemporaryAppearance",
+                header::<UpdateRemoveTemporaryAppearance>(),
+                vec![0x23, 0, 0xf, 0],
+            ),
+            (
+                "UpdateCharacterState",
+                header::<UpdateCharacterState>(),
+                vec![0x23, 0, 0x14, 0],
+            ),
+            ("LootEvent", header::<LootEvent>(), vec![0x23, 0, 0x1d, 0]),
+            (
+                "SlotCompositeEffectOverride",
+                header::<SlotCompositeEffectOverride>(),
+                vec![0x23, 0, 0x1f, 0],
+            ),
+            ("Freeze", header::<Freeze>(), vec![0x23, 0, 0x20, 0]),
+            (
+                "SetSpawnerActivationEffect",
+                header::<SetSpawnerActivationEffect>(),
+                vec![0x23, 0, 0x2f, 0],
+            ),
+            (
+                "ReplaceBaseModel",
+                header::<ReplaceBaseModel>(),
+                vec![0x23, 0, 0x31, 0],
+            ),
+            (
+                "SetCollision",
+                header::<SetCollision>(),
+                vec![0x23, 0, 0x32, 0],
+            ),
+            ("MoveOnRail", header::<MoveOnRail>(), vec![0x23, 0, 0x35, 0]),
+            ("ClearRail", header::<ClearRail>(), vec![0x23, 0, 0x36, 0]),
+            (
+                "MoveOnRelativeRail",
+                header::<MoveOnRelativeRail>(),
+                vec![0x23, 0, 0x37, 0],
+            ),
+            ("SeekTarget", header::<SeekTarget>(), vec![0x23, 0, 0x3b, 0]),
+            (
+                "SeekTargetUpdate",
+                header::<SeekTargetUpdate>(),
+                vec![0x23, 0, 0x3c, 0],
+            ),
+            ("WieldType", header::<WieldType>(), vec![0x23, 0, 0x3d, 0]),
+            ("HudMessage", header::<HudMessage>(), vec![0x23, 0, 0x40, 0]),
+            (
+                "NameplateImageId",
+                header::<NameplateImageId>(),
+                vec![0x23, 0, 0x44, 0],
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_mount_headers() {
+        // Mount packets use a single byte for their sub-op code
+        assert_headers(vec![
+            ("MountReply", header::<MountReply>(), vec![0xa7, 0, 0x2]),
+            (
+                "DismountReply",
+                header::<DismountReply>(),
+                vec![0xa7, 0, 0x4],
+            ),
+            ("MountSpawn", header::<MountSpawn>(), vec![0xa7, 0, 0x6]),
+        ]);
+    }
+
+    #[test]
+    fn test_command_headers() {
+        assert_headers(vec![
+            (
+                "InteractionList",
+                header::<InteractionList>(),
+                vec![0x1a, 0, 0x9, 0],
+            ),
+            (
+                "ChatBubbleColor",
+                header::<ChatBubbleColor>(),
+                vec![0x1a, 0, 0xe, 0],
+            ),
+            (
+                "SelectPlayer",
+                header::<SelectPlayer>(),
+                vec![0x1a, 0, 0xf, 0],
+            ),
+            ("PlayEmote", header::<PlayEmote>(), vec![0x1a, 0, 0x10, 0]),
+        ]);
+    }
+}
@@ -19,6 +19,14 @@ impl<T> Lock<T> {
     pub fn write(&self) -> RwLockWriteGuard<T> {
         self.inner.write()
     }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        self.inner.try_read()
+    }
+
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+        self.inner.try_write()
+    }
 }
 
 pub trait Guid<T> {
@@ -245,4 +253,10 @@ impl<K, I, V: IndexedGuid<K, I>> GuidTable<K, V, I> {
             guard: self.data.write(),
         }
     }
+
+    pub fn try_read(&self) -> Option<GuidTableReadHandle<'_, K, V, I>> {
+        self.data
+            .try_read()
+            .map(|guard| GuidTableReadHandle { guard })
+    }
 }
@@ -1,10 +1,15 @@
+use crate::game_server::client_update_packet::{
+    EquipItem, RemoveItem, StatId, UpdateCurrency, UpdateItem,
+};
 use crate::game_server::game_packet::GamePacket;
-use crate::game_server::player_update_packet::PlayerUpdateOpCode;
+use crate::game_server::player_update_packet::{Attachment, PlayerUpdateOpCode};
+use crate::game_server::tunnel::TunneledPacket;
+use crate::game_server::zone::{Character, CharacterType};
 use byteorder::{LittleEndian, WriteBytesExt};
 use packet_serialize::{SerializePacket, SerializePacketError};
 use std::io::Write;
 
-#[derive(SerializePacket)]
+#[derive(Clone, SerializePacket)]
 pub struct Item {
     pub definition_id: u32,
     pub tint: u32,
@@ -16,6 +21,20 @@ pub struct Item {
     pub unknown2: bool,
 }
 
+#[derive(Debug)]
+pub enum InventoryError {
+    NotAPlayer,
+    UnknownItem(u32),
+    NotEnough { held: u32, requested: u32 },
+    SerializeError(SerializePacketError),
+}
+
+impl From<SerializePacketError> for InventoryError {
+    fn from(value: SerializePacketError) -> Self {
+        InventoryError::SerializeError(value)
+    }
+}
+
 #[derive(Clone)]
 pub enum MarketData {
     None,
@@ -42,6 +61,15 @@ pub enum EquipmentSlot {
     CustomBeard = 18,
 }
 
+impl EquipmentSlot {
+    pub fn is_weapon(&self) -> bool {
+        matches!(
+            self,
+            EquipmentSlot::PrimaryWeapon | EquipmentSlot::SecondaryWeapon
+        )
+    }
+}
+
 impl SerializePacket for EquipmentSlot {
     fn serialize(&self, buffer: &mut Vec<u8>) -> Result<(), SerializePacketError> {
         buffer.write_u32::<LittleEndian>(*self as u32)?;
@@ -109,6 +137,17 @@ pub struct ItemDefinition {
     unknown42: Vec<Unknown42>,
 }
 
+impl ItemDefinition {
+    pub fn guid(&self) -> u32 {
+        self.guid
+    }
+
+    // Items that can't stack, like equipment, have a negative max stack size
+    pub fn max_stack(&self) -> u32 {
+        self.max_stack_size.max(1) as u32
+    }
+}
+
 #[derive(SerializePacket)]
 pub struct ItemDefinitionsData {
     pub definitions: Vec<ItemDefinition>,
@@ -582,3 +621,228 @@ pub fn make_item_definitions() -> ItemDefinitionsReply {
         },
     }
 }
+
+impl Character {
+    // Weapons are held in hand rather than replacing part of the base model, so other players
+    // need an attachment for the weapon's slot
+    pub fn equip(&mut self, equip_item: EquipItem) -> Result<Vec<u8>, SerializePacketError> {
+        if let CharacterType::Player(player) = &mut self.character_type {
+            if equip_item.slot.is_weapon() {
+                player
+                    .attachments
+                    .insert(equip_item.slot as u32, Attachment::from(&equip_item));
+            }
+        }
+
+        GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: equip_item,
+        })
+    }
+
+    // Nothing is consumed unless the player holds the whole amount
+    pub fn consume_item(&mut self, item_guid: u32, amount: u32) -> Result<Vec<u8>, InventoryError> {
+        let CharacterType::Player(player) = &mut self.character_type else {
+            return Err(InventoryError::NotAPlayer);
+        };
+
+        let Some(item) = player.inventory.get_mut(&item_guid) else {
+            return Err(InventoryError::UnknownItem(item_guid));
+        };
+
+        if amount > item.quantity {
+            return Err(InventoryError::NotEnough {
+                held: item.quantity,
+                requested: amount,
+            });
+        }
+
+        item.quantity -= amount;
+        if item.quantity == 0 {
+            player.inventory.remove(&item_guid);
+            Ok(GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: RemoveItem { item_guid },
+            })?)
+        } else {
+            Ok(GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: UpdateItem { item: item.clone() },
+            })?)
+        }
+    }
+
+    // Gains are scaled by the player's currency boost. Returns the amount actually added.
+    pub fn add_currency(&mut self, amount: u32) -> Result<(u32, Vec<u8>), InventoryError> {
+        let boosted_amount =
+            (amount as f32 * self.stats.value(StatId::CurrencyBoost)).round() as u32;
+        let CharacterType::Player(player) = &mut self.character_type else {
+            return Err(InventoryError::NotAPlayer);
+        };
+
+        player.currency = player.currency.saturating_add(boosted_amount);
+        Ok((boosted_amount, Character::currency_update(player.currency)?))
+    }
+
+    pub fn spend_currency(&mut self, amount: u32) -> Result<Vec<u8>, InventoryError> {
+        let CharacterType::Player(player) = &mut self.character_type else {
+            return Err(InventoryError::NotAPlayer);
+        };
+
+        if amount > player.currency {
+            return Err(InventoryError::NotEnough {
+                held: player.currency,
+                requested: amount,
+            });
+        }
+
+        player.currency -= amount;
+        Ok(Character::currency_update(player.currency)?)
+    }
+
+    fn currency_update(currency: u32) -> Result<Vec<u8>, SerializePacketError> {
+        GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: UpdateCurrency { currency },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    use crate::game_server::stat::StatSource;
+    use crate::game_server::zone::{make_test_character, PlayerState};
+
+    #[test]
+    fn test_currency_gains_apply_boost() {
+        let mut character = make_test_character(1, CharacterType::Player(PlayerState::default()));
+        character
+            .stats
+            .set(StatId::CurrencyBoost, StatSource::Buff(1), 1.5);
+
+        let (added, packet) = character.add_currency(100).unwrap();
+        assert_eq!(added, 150);
+        let expected_update = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: UpdateCurrency { currency: 150 },
+        })
+        .unwrap();
+        assert_eq!(packet, expected_update);
+
+        assert!(matches!(
+            character.spend_currency(151),
+            Err(InventoryError::NotEnough {
+                held: 150,
+                requested: 151
+            })
+        ));
+        let packet = character.spend_currency(50).unwrap();
+        let expected_update = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: UpdateCurrency { currency: 100 },
+        })
+        .unwrap();
+        assert_eq!(packet, expected_update);
+    }
+
+    #[test]
+    fn test_consume_item_updates_and_removes_stacks() {
+        let item = Item {
+            definition_id: 7,
+            tint: 0,
+            guid: 5,
+            quantity: 10,
+            num_consumed: 0,
+            last_use_time: 0,
+            market_data: MarketData::None,
+            unknown2: false,
+        };
+        let mut character = make_test_character(
+            1,
+            CharacterType::Player(PlayerState {
+                inventory: BTreeMap::from([(5, item.clone())]),
+                ..PlayerState::default()
+            }),
+        );
+        let quantity = |character: &Character| match &character.character_type {
+            CharacterType::Player(player) => player.inventory.get(&5).map(|item| item.quantity),
+            _ => None,
+        };
+
+        let packet = character.consume_item(5, 3).unwrap();
+        let expected_update = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: UpdateItem {
+                item: Item {
+                    quantity: 7,
+                    ..item
+                },
+            },
+        })
+        .unwrap();
+        assert_eq!(packet, expected_update);
+        assert_eq!(quantity(&character), Some(7));
+
+        assert!(matches!(
+            character.consume_item(5, 8),
+            Err(InventoryError::NotEnough {
+                held: 7,
+                requested: 8
+            })
+        ));
+        assert_eq!(quantity(&character), Some(7));
+        assert!(matches!(
+            character.consume_item(6, 1),
+            Err(InventoryError::UnknownItem(6))
+        ));
+
+        let packet = character.consume_item(5, 7).unwrap();
+        let expected_removal = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: RemoveItem { item_guid: 5 },
+        })
+        .unwrap();
+        assert_eq!(packet, expected_removal);
+        assert_eq!(quantity(&character), None);
+    }
+
+    fn make_test_equip_item(slot: EquipmentSlot, model_name: &str) -> EquipItem {
+        EquipItem {
+            item_guid: 1,
+            model_name: model_name.to_string(),
+            texture_alias: "".to_string(),
+            tint_alias: "".to_string(),
+            tint: 0,
+            composite_effect: 0,
+            slot,
+            profile_id: 0,
+            item_def_class: 0,
+            update_gear: true,
+        }
+    }
+
+    #[test]
+    fn test_equipping_weapon_adds_attachment() {
+        let mut player = make_test_character(1, CharacterType::Player(PlayerState::default()));
+        player
+            .equip(make_test_equip_item(EquipmentSlot::Body, "Body_Armor"))
+            .unwrap();
+        player
+            .equip(make_test_equip_item(
+                EquipmentSlot::PrimaryWeapon,
+                "Weapon_Pistol",
+            ))
+            .unwrap();
+
+        let CharacterType::Player(player_state) = &player.character_type else {
+            panic!("Expected player");
+        };
+        assert_eq!(player_state.attachments.len(), 1);
+        let attachment = &player_state.attachments[&(EquipmentSlot::PrimaryWeapon as u32)];
+        assert_eq!(attachment.model_name, "Weapon_Pistol");
+        assert_eq!(attachment.slot, EquipmentSlot::PrimaryWeapon as u32);
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use serde::Deserialize;
+
+// Assets are only checked against the lists that are present, so a manifest can cover only
+// models or only textures
+#[derive(Deserialize)]
+pub struct KnownAssets {
+    #[serde(default)]
+    model_ids: Option<BTreeSet<u32>>,
+    #[serde(default)]
+    textures: Option<BTreeSet<String>>,
+}
+
+impl KnownAssets {
+    pub fn unknown_references(&self, model_id: u32, texture: &str) -> Vec<String> {
+        let mut references = Vec::new();
+
+        if let Some(model_ids) = &self.model_ids {
+            if !model_ids.contains(&model_id) {
+                references.push(format!("unknown model ID {}", model_id));
+            }
+        }
+
+        if let Some(textures) = &self.textures {
+            if !texture.is_empty() && !textures.contains(texture) {
+                references.push(format!("unknown texture \"{}\"", texture));
+            }
+        }
+
+        references
+    }
+}
+
+pub fn load_known_assets(config_dir: &Path) -> Result<Option<KnownAssets>, Error> {
+    match File::open(config_dir.join("known_assets.json")) {
+        Ok(mut file) => Ok(Some(serde_json::from_reader(&mut file)?)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
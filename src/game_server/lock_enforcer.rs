@@ -1,3 +1,4 @@
+use std::cell::Cell;
 use std::collections::{BTreeMap, BTreeSet};
 
 use parking_lot::{RwLockReadGuard, RwLockWriteGuard};
@@ -7,15 +8,57 @@ use crate::game_server::zone::{Character, CharacterCategory, Zone};
 
 use super::guid::{GuidTableHandle, GuidTableReadHandle, GuidTableWriteHandle};
 
+// Locks are always acquired in this order, so no two threads can each hold a lock the other needs:
+//   1. The characters table
+//   2. Individual characters, in ascending GUID order
+//   3. The zones table
+//   4. Individual zones, in ascending GUID order
+// The enforcers acquire the individual locks in GUID order themselves. The levels below catch a
+// thread that asks for an earlier lock while it still holds a later one, like a zone consumer
+// that locks characters, and recursive table locks, which deadlock when a writer is waiting.
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum LockLevel {
+    None,
+    Characters,
+    Zones,
+}
+
+thread_local! {
+    static LOCK_LEVEL: Cell<LockLevel> = const { Cell::new(LockLevel::None) };
+}
+
+struct LockLevelGuard {
+    previous_level: LockLevel,
+}
+
+impl LockLevelGuard {
+    fn enter(level: LockLevel) -> Self {
+        let previous_level = LOCK_LEVEL.with(|current_level| current_level.replace(level));
+        debug_assert!(
+            previous_level < level,
+            "Tried to acquire {:?} locks while holding {:?} locks",
+            level,
+            previous_level
+        );
+        LockLevelGuard { previous_level }
+    }
+}
+
+impl Drop for LockLevelGuard {
+    fn drop(&mut self) {
+        LOCK_LEVEL.with(|current_level| current_level.set(self.previous_level));
+    }
+}
+
+// Returned by the try_ variants instead of waiting for a lock that another thread holds
+#[derive(Debug, Eq, PartialEq)]
+pub struct WouldBlock;
+
 pub struct TableReadHandleWrapper<'a, K, V, I = ()> {
     handle: GuidTableReadHandle<'a, K, V, I>,
 }
 
 impl<K: Copy + Ord, V, I: Copy + Ord> TableReadHandleWrapper<'_, K, V, I> {
-    pub fn contains(&self, guid: K) -> bool {
-        self.handle.get(guid).is_some()
-    }
-
     pub fn index(&self, guid: K) -> Option<I> {
         self.handle.index(guid)
     }
@@ -76,6 +119,7 @@ impl ZoneLockEnforcer<'_> {
         &self,
         table_consumer: T,
     ) -> R {
+        let _level = LockLevelGuard::enter(LockLevel::Zones);
         let zones_table_read_handle = self.zones.read().into();
         let zone_lock_request = table_consumer(&zones_table_read_handle);
 
@@ -99,6 +143,47 @@ impl ZoneLockEnforcer<'_> {
         (zone_lock_request.zone_consumer)(&zones_table_read_handle, zones_read_map, zones_write_map)
     }
 
+    pub fn try_read_zones<
+        R,
+        Z: FnOnce(
+            &ZoneTableReadHandle<'_>,
+            BTreeMap<u64, ZoneReadGuard<'_>>,
+            BTreeMap<u64, ZoneWriteGuard<'_>>,
+        ) -> R,
+        T: FnOnce(&ZoneTableReadHandle<'_>) -> ZoneLockRequest<R, Z>,
+    >(
+        &self,
+        table_consumer: T,
+    ) -> Result<R, WouldBlock> {
+        let _level = LockLevelGuard::enter(LockLevel::Zones);
+        let zones_table_read_handle: ZoneTableReadHandle =
+            self.zones.try_read().ok_or(WouldBlock)?.into();
+        let zone_lock_request = table_consumer(&zones_table_read_handle);
+
+        let mut combined_guids = BTreeSet::from_iter(zone_lock_request.read_guids);
+        combined_guids.extend(zone_lock_request.write_guids.iter());
+
+        let write_set = BTreeSet::from_iter(zone_lock_request.write_guids);
+
+        let mut zones_read_map = BTreeMap::new();
+        let mut zones_write_map = BTreeMap::new();
+        for guid in combined_guids {
+            if write_set.contains(&guid) {
+                if let Some(lock) = zones_table_read_handle.handle.get(guid) {
+                    zones_write_map.insert(guid, lock.try_write().ok_or(WouldBlock)?);
+                }
+            } else if let Some(lock) = zones_table_read_handle.handle.get(guid) {
+                zones_read_map.insert(guid, lock.try_read().ok_or(WouldBlock)?);
+            }
+        }
+
+        Ok((zone_lock_request.zone_consumer)(
+            &zones_table_read_handle,
+            zones_read_map,
+            zones_write_map,
+        ))
+    }
+
     // This thread can access individual zones if and only if it holds the table read or write lock.
     // If this thread holds the table write lock, then no other threads may hold a table lock.
     // Therefore, if this thread holds the table write lock, it is the only thread that can hold any
@@ -107,6 +192,7 @@ impl ZoneLockEnforcer<'_> {
         &self,
         table_consumer: T,
     ) -> R {
+        let _level = LockLevelGuard::enter(LockLevel::Zones);
         let mut zones_table_write_handle = self.zones.write();
         table_consumer(&mut zones_table_write_handle)
     }
@@ -145,6 +231,7 @@ impl LockEnforcer<'_> {
         &self,
         table_consumer: T,
     ) -> R {
+        let _level = LockLevelGuard::enter(LockLevel::Characters);
         let characters_table_read_handle = self.characters.read().into();
         let character_lock_request: CharacterLockRequest<R, C> =
             table_consumer(&characters_table_read_handle);
@@ -186,6 +273,7 @@ impl LockEnforcer<'_> {
         &self,
         table_consumer: T,
     ) -> R {
+        let _level = LockLevelGuard::enter(LockLevel::Characters);
         let mut characters_table_write_handle = self.characters.write();
         let zones_enforcer = ZoneLockEnforcer { zones: self.zones };
         table_consumer(&mut characters_table_write_handle, &zones_enforcer)
@@ -211,10 +299,45 @@ impl LockEnforcerSource {
         LockEnforcerSource { characters, zones }
     }
 
-    pub fn lock_enforcer(&self) -> LockEnforcer {
+    pub fn lock_enforcer(&self) -> LockEnforcer<'_> {
         LockEnforcer {
             characters: &self.characters,
             zones: &self.zones,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_read_zones_would_block_on_held_write_lock() {
+        let source = LockEnforcerSource::from(GuidTable::new(), GuidTable::new());
+        let zones_lock_enforcer = ZoneLockEnforcer::from(source.lock_enforcer());
+        let read_nothing = |_: &ZoneTableReadHandle<'_>| ZoneLockRequest {
+            read_guids: Vec::new(),
+            write_guids: Vec::new(),
+            zone_consumer: |_, _, _| (),
+        };
+
+        {
+            let _zones_write_handle = source.zones.write();
+            assert_eq!(
+                zones_lock_enforcer.try_read_zones(read_nothing),
+                Err(WouldBlock)
+            );
+        }
+
+        assert_eq!(zones_lock_enforcer.try_read_zones(read_nothing), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Tried to acquire Characters locks while holding Zones locks")]
+    fn test_locking_characters_inside_zones_panics() {
+        let source = LockEnforcerSource::from(GuidTable::new(), GuidTable::new());
+        ZoneLockEnforcer::from(source.lock_enforcer()).write_zones(|_| {
+            source.lock_enforcer().write_characters(|_, _| ());
+        });
+    }
+}
@@ -4,11 +4,43 @@ use std::io::Write;
 use packet_serialize::{
     DeserializePacket, NullTerminatedString, SerializePacket, SerializePacketError,
 };
+use serde::Deserialize;
 
 use crate::game_server::game_packet::{GamePacket, ImageId, OpCode, Pos, StringId};
 use crate::game_server::tunnel::TunneledPacket;
 use crate::game_server::GameServer;
 
+// Only the leading session ID is read. The fields after it are unknown.
+#[derive(SerializePacket, DeserializePacket)]
+pub struct LoginRequest {
+    pub session_id: String,
+}
+
+impl GamePacket for LoginRequest {
+    type Header = OpCode;
+    const HEADER: OpCode = OpCode::LoginRequest;
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum AuthError {
+    InvalidCredentials,
+    GuidTooLarge,
+}
+
+// Returns the player's character GUID
+pub trait Authenticator: Send + Sync {
+    fn authenticate(&self, credentials: &LoginRequest) -> Result<u64, AuthError>;
+}
+
+// Lets anyone in as the same player until accounts exist
+pub struct SinglePlayerAuthenticator;
+
+impl Authenticator for SinglePlayerAuthenticator {
+    fn authenticate(&self, _: &LoginRequest) -> Result<u64, AuthError> {
+        Ok(1)
+    }
+}
+
 #[derive(SerializePacket, DeserializePacket)]
 pub struct LoginReply {
     pub logged_in: bool,
@@ -46,7 +78,7 @@ impl GamePacket for ZoneDetails {
     const HEADER: OpCode = OpCode::ZoneDetails;
 }
 
-#[derive(SerializePacket, DeserializePacket)]
+#[derive(Clone, SerializePacket, DeserializePacket, Deserialize)]
 pub struct GameSettings {
     pub unknown1: u32,
     pub unknown2: u32,
@@ -55,6 +87,18 @@ pub struct GameSettings {
     pub time_scale: f32,
 }
 
+impl Default for GameSettings {
+    fn default() -> Self {
+        GameSettings {
+            unknown1: 4,
+            unknown2: 7,
+            unknown3: 268,
+            unknown4: true,
+            time_scale: 1.0,
+        }
+    }
+}
+
 impl GamePacket for GameSettings {
     type Header = OpCode;
     const HEADER: OpCode = OpCode::ClientGameSettings;
@@ -1,12 +1,14 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::{Cursor, Error};
 use std::path::Path;
 use std::vec;
 
 use byteorder::{LittleEndian, ReadBytesExt};
 use lock_enforcer::{
-    CharacterLockRequest, LockEnforcer, LockEnforcerSource, ZoneLockRequest, ZoneTableReadHandle,
+    CharacterLockRequest, CharacterTableReadHandle, CharacterWriteGuard, LockEnforcer,
+    LockEnforcerSource, ZoneLockRequest, ZoneTableReadHandle,
 };
+use parking_lot::Mutex;
 use rand::Rng;
 
 use packet_serialize::{
@@ -15,54 +17,69 @@ use packet_serialize::{
 use unique_guid::{shorten_zone_template_guid, zone_instance_guid};
 use zone::CharacterCategory;
 
-use crate::game_server::chat::process_chat_packet;
-use crate::game_server::client_update_packet::{
-    Health, Power, PreloadCharactersDone, Stat, StatId, Stats,
-};
+use crate::game_server::character_store::{CharacterStore, SavedCharacter};
+use crate::game_server::chat::{process_chat_packet, refill_chat_tokens, system_message};
+use crate::game_server::client_update_packet::{Health, Power, PreloadCharactersDone, StatId};
 use crate::game_server::command::process_command;
+use crate::game_server::composite_effect::load_composite_effects;
 use crate::game_server::game_packet::{GamePacket, OpCode};
-use crate::game_server::guid::{GuidTable, GuidTableWriteHandle};
+use crate::game_server::guid::{GuidTable, GuidTableWriteHandle, Lock};
 use crate::game_server::housing::{
     process_housing_packet, HouseDescription, HouseInstanceEntry, HouseInstanceList,
 };
 use crate::game_server::item::make_item_definitions;
+use crate::game_server::known_assets::load_known_assets;
 use crate::game_server::login::{
-    send_points_of_interest, DeploymentEnv, GameSettings, LoginReply, WelcomeScreen,
-    ZoneDetailsDone,
+    send_points_of_interest, AuthError, Authenticator, DeploymentEnv, LoginReply, LoginRequest,
+    WelcomeScreen, ZoneDetailsDone,
 };
-use crate::game_server::mount::{load_mounts, process_mount_packet, MountConfig};
+use crate::game_server::mount::{load_mounts, process_mount_packet, reply_dismount, MountConfig};
 use crate::game_server::player_data::{
     make_test_nameplate_image, make_test_player, make_test_wield_type,
 };
-use crate::game_server::player_update_packet::make_test_npc;
+use crate::game_server::player_update_packet::{hud_message, make_test_npc, RemoveGracefully};
 use crate::game_server::reference_data::{
     CategoryDefinition, CategoryDefinitions, CategoryRelation, ItemGroupDefinitions,
     ItemGroupDefinitionsData,
 };
+use crate::game_server::server_config::{
+    load_server_config, ServerConfig, ServerInfo, StaleGapConfig,
+};
 use crate::game_server::time::make_game_time_sync;
 use crate::game_server::tunnel::{TunneledPacket, TunneledWorldPacket};
-use crate::game_server::unique_guid::player_guid;
+use crate::game_server::unique_guid::{player_guid, shorten_player_guid};
 use crate::game_server::update_position::UpdatePlayerPosition;
 use crate::game_server::zone::{
-    load_zones, teleport_within_zone, Character, Zone, ZoneTeleportRequest, ZoneTemplate,
+    close_return_doors, load_zones, players_in_zone, stop_seeking, teleport_within_zone, Character,
+    CharacterType, Zone, ZoneLoadState, ZoneTeleportRequest, ZoneTemplate,
 };
+use crate::protocol::DisconnectReason;
 use crate::teleport_to_zone;
 
+pub mod character_store;
 mod chat;
 mod client_update_packet;
 mod combat_update_packet;
 mod command;
+mod composite_effect;
+mod emote;
 mod game_packet;
 mod guid;
 mod housing;
 mod item;
+mod known_assets;
 mod lock_enforcer;
-mod login;
+pub mod login;
 mod mount;
 mod player_data;
 mod player_update_packet;
+mod pos;
 mod purchase;
+mod rail;
 mod reference_data;
+mod respawn;
+mod server_config;
+mod stat;
 mod store;
 mod time;
 mod tunnel;
@@ -75,6 +92,7 @@ mod zone;
 pub enum Broadcast {
     Single(u32, Vec<Vec<u8>>),
     Multi(Vec<u32>, Vec<Vec<u8>>),
+    Disconnect(u32, DisconnectReason),
 }
 
 #[non_exhaustive]
@@ -82,6 +100,8 @@ pub enum Broadcast {
 pub enum ProcessPacketError {
     CorruptedPacket,
     SerializeError(SerializePacketError),
+    // Unknown and unimplemented op codes, which the channel counts to catch misbehaving clients
+    UnknownOpCode,
 }
 
 impl From<Error> for ProcessPacketError {
@@ -102,37 +122,115 @@ impl From<SerializePacketError> for ProcessPacketError {
     }
 }
 
+const DEFAULT_PLAYER_ZONE: u64 = 24;
+
+pub enum LoginResult {
+    Accepted(u32, Vec<Broadcast>),
+    Rejected(Vec<Vec<u8>>),
+}
+
 pub struct GameServer {
+    authenticator: Box<dyn Authenticator>,
+    character_store: Box<dyn CharacterStore>,
+    saved_characters: Mutex<BTreeMap<u64, SavedCharacter>>,
+    store_lock: Mutex<()>,
     lock_enforcer_source: LockEnforcerSource,
     mounts: BTreeMap<u32, MountConfig>,
     zone_templates: BTreeMap<u8, ZoneTemplate>,
+    server_config: ServerConfig,
 }
 
 impl GameServer {
-    pub fn new(config_dir: &Path) -> Result<Self, Error> {
+    pub fn new(
+        config_dir: &Path,
+        authenticator: Box<dyn Authenticator>,
+        character_store: Box<dyn CharacterStore>,
+    ) -> Result<Self, Error> {
         let characters = GuidTable::new();
         let (templates, zones) = load_zones(config_dir, characters.write())?;
         Ok(GameServer {
+            authenticator,
+            character_store,
+            saved_characters: Mutex::new(BTreeMap::new()),
+            store_lock: Mutex::new(()),
             lock_enforcer_source: LockEnforcerSource::from(characters, zones),
-            mounts: load_mounts(config_dir)?,
+            mounts: load_mounts(
+                config_dir,
+                &load_composite_effects(config_dir)?,
+                load_known_assets(config_dir)?.as_ref(),
+            )?,
             zone_templates: templates,
+            server_config: load_server_config(config_dir)?,
         })
     }
 
-    pub fn login(&self, data: Vec<u8>) -> Result<(u32, Vec<Broadcast>), ProcessPacketError> {
+    #[cfg(test)]
+    pub fn for_test(
+        characters: GuidTable<u64, Character, (u64, CharacterCategory)>,
+        zones: GuidTable<u64, Zone, u8>,
+    ) -> Self {
+        GameServer {
+            authenticator: Box::new(login::SinglePlayerAuthenticator),
+            character_store: Box::new(character_store::MemoryCharacterStore::default()),
+            saved_characters: Mutex::new(BTreeMap::new()),
+            store_lock: Mutex::new(()),
+            lock_enforcer_source: LockEnforcerSource::from(characters, zones),
+            mounts: BTreeMap::new(),
+            zone_templates: BTreeMap::new(),
+            server_config: ServerConfig::default(),
+        }
+    }
+
+    pub fn login(&self, data: Vec<u8>) -> Result<LoginResult, ProcessPacketError> {
         let mut cursor = Cursor::new(&data[..]);
         let raw_op_code = cursor.read_u16::<LittleEndian>()?;
 
         match OpCode::try_from(raw_op_code) {
             Ok(op_code) => match op_code {
                 OpCode::LoginRequest => {
+                    let login_request = LoginRequest::deserialize(&mut cursor)?;
+                    // Channels and broadcasts address players by the 32-bit ID, so a GUID that
+                    // doesn't fit is rejected here instead of being truncated to another player's
+                    let authenticated_guid = self
+                        .authenticator
+                        .authenticate(&login_request)
+                        .and_then(|guid| {
+                            shorten_player_guid(guid).map_err(|_| AuthError::GuidTooLarge)
+                        });
+                    let guid = match authenticated_guid {
+                        Ok(guid) => guid,
+                        Err(err) => {
+                            println!("Rejected login: {:?}", err);
+                            return Ok(LoginResult::Rejected(vec![GamePacket::serialize(
+                                &TunneledPacket {
+                                    unknown1: true,
+                                    inner: LoginReply { logged_in: false },
+                                },
+                            )?]));
+                        }
+                    };
+
+                    // New players have no saved character yet, but a store that can't be
+                    // read must not let the player overwrite their save with defaults
+                    let saved_character = match self.character_store.load(player_guid(guid)) {
+                        Ok(saved_character) => saved_character,
+                        Err(err) => {
+                            println!("Unable to load character for player {}: {}", guid, err);
+                            return Ok(LoginResult::Rejected(vec![GamePacket::serialize(
+                                &TunneledPacket {
+                                    unknown1: true,
+                                    inner: LoginReply { logged_in: false },
+                                },
+                            )?]));
+                        }
+                    };
+
                     self.lock_enforcer().write_characters(
                         |characters_write_handle, zone_lock_enforcer| {
-                            // TODO: validate and get GUID from login request
-                            let guid = 1;
-
-                            // TODO: get player's zone
-                            let player_zone = 24;
+                            let saved_zone = saved_character
+                                .as_ref()
+                                .map(|saved_character| saved_character.instance_guid)
+                                .unwrap_or(DEFAULT_PLAYER_ZONE);
 
                             let mut packets = Vec::new();
 
@@ -145,30 +243,36 @@ impl GameServer {
                             let deployment_env = TunneledPacket {
                                 unknown1: true,
                                 inner: DeploymentEnv {
-                                    environment: NullTerminatedString("prod".to_string()),
+                                    environment: NullTerminatedString(
+                                        self.server_config.environment().to_string(),
+                                    ),
                                 },
                             };
                             packets.push(GamePacket::serialize(&deployment_env)?);
 
-                            packets.append(&mut zone_lock_enforcer.read_zones(|_| {
-                                ZoneLockRequest {
-                                    read_guids: vec![player_zone],
+                            // The saved zone may be a house instance that no longer exists
+                            let (player_zone, mut zone_packets) =
+                                zone_lock_enforcer.read_zones(|_| ZoneLockRequest {
+                                    read_guids: vec![saved_zone, DEFAULT_PLAYER_ZONE],
                                     write_guids: Vec::new(),
                                     zone_consumer: |_, zones_read, _| {
-                                        zones_read.get(&player_zone).unwrap().send_self()
+                                        let player_zone = if zones_read.contains_key(&saved_zone) {
+                                            saved_zone
+                                        } else {
+                                            DEFAULT_PLAYER_ZONE
+                                        };
+                                        zones_read
+                                            .get(&player_zone)
+                                            .unwrap()
+                                            .send_self()
+                                            .map(|packets| (player_zone, packets))
                                     },
-                                }
-                            })?);
+                                })?;
+                            packets.append(&mut zone_packets);
 
                             let settings = TunneledPacket {
                                 unknown1: true,
-                                inner: GameSettings {
-                                    unknown1: 4,
-                                    unknown2: 7,
-                                    unknown3: 268,
-                                    unknown4: true,
-                                    time_scale: 1.0,
-                                },
+                                inner: self.server_config.game_settings(),
                             };
                             packets.push(GamePacket::serialize(&settings)?);
 
@@ -178,16 +282,53 @@ impl GameServer {
                             };
                             packets.push(GamePacket::serialize(&item_defs)?);
 
-                            let player = TunneledPacket {
+                            let mut player = TunneledPacket {
                                 unknown1: true,
-                                inner: make_test_player(guid, self.mounts()),
+                                inner: match &saved_character {
+                                    Some(saved_character) => {
+                                        make_test_player(guid, &self.owned_mounts(saved_character))
+                                    }
+                                    None => make_test_player(guid, self.mounts()),
+                                },
                             };
+                            if let Some(saved_character) = &saved_character {
+                                if saved_character.instance_guid == player_zone {
+                                    player.inner.data.pos = saved_character.pos;
+                                    player.inner.data.rot = saved_character.rot;
+                                }
+                            }
                             packets.push(GamePacket::serialize(&player)?);
 
-                            characters_write_handle
-                                .insert(player.inner.data.to_character(player_zone));
+                            packets.push(system_message(format!(
+                                "Connected to {} {}",
+                                self.server_info().name,
+                                self.server_info().version
+                            ))?);
+                            if let Some(motd) = self.server_config.motd {
+                                packets.push(hud_message(motd)?);
+                            }
+
+                            let mut character = player.inner.data.to_character(player_zone);
+                            if let Some(saved_character) = &saved_character {
+                                character.health = saved_character.health.min(character.max_health);
+                                character.power = saved_character.power.min(character.max_power);
+                                if let CharacterType::Player(player_state) =
+                                    &mut character.character_type
+                                {
+                                    player_state.tutorial_complete =
+                                        saved_character.tutorial_complete;
+                                }
+                                self.saved_characters
+                                    .lock()
+                                    .insert(character.guid, SavedCharacter::from(&character));
+                            }
+                            character.zone_load_state = ZoneLoadState::DetailsSent;
+                            characters_write_handle.insert(character);
 
-                            Ok((guid, vec![Broadcast::Single(guid, packets)]))
+                            Ok(LoginResult::Accepted(
+                                guid,
+                                vec![Broadcast::Single(guid, packets)],
+                            ))
                         },
                     )
                 }
@@ -203,6 +344,445 @@ impl GameServer {
         }
     }
 
+    pub fn tick(&self, elapsed_millis: u128) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        let mut broadcasts = self.tick_players(elapsed_millis)?;
+        broadcasts.append(&mut self.tick_npcs(elapsed_millis)?);
+        Ok(broadcasts)
+    }
+
+    fn tick_players(&self, elapsed_millis: u128) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        self.lock_enforcer()
+            .read_characters(|characters_table_read_handle| CharacterLockRequest {
+                read_guids: Vec::new(),
+                write_guids: GameServer::player_guids(characters_table_read_handle),
+                character_consumer: |characters_table_read_handle, _, mut characters_write, _| {
+                    let mut broadcasts = Vec::new();
+                    for character_write_handle in characters_write.values_mut() {
+                        broadcasts
+                            .append(&mut character_write_handle.regenerate_power(elapsed_millis)?);
+                        broadcasts.append(&mut character_write_handle.regenerate_health(
+                            elapsed_millis,
+                            self.server_config.health_regen_delay_secs as u128 * 1000,
+                        )?);
+
+                        broadcasts.append(
+                            &mut self.dismount_if_idle(character_write_handle, elapsed_millis)?,
+                        );
+
+                        if let Some(chat_rate_limit) = &self.server_config.chat_rate_limit {
+                            refill_chat_tokens(
+                                character_write_handle,
+                                chat_rate_limit,
+                                elapsed_millis,
+                            );
+                        }
+
+                        let mut zone_packets =
+                            character_write_handle.advance_emote(elapsed_millis)?;
+                        zone_packets
+                            .append(&mut character_write_handle.advance_rail(elapsed_millis)?);
+                        if !zone_packets.is_empty() {
+                            broadcasts.append(&mut Zone::player_broadcast(
+                                character_write_handle.instance_guid,
+                                characters_table_read_handle,
+                                zone_packets,
+                                None,
+                            ));
+                        }
+                    }
+
+                    Ok(broadcasts)
+                },
+            })
+    }
+
+    fn tick_npcs(&self, elapsed_millis: u128) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        // Most NPCs are idle, so only the ones with something to advance are write-locked
+        let active_npc_guids: Vec<u64> =
+            self.lock_enforcer()
+                .read_characters(|characters_table_read_handle| CharacterLockRequest {
+                    read_guids: GameServer::npc_guids(characters_table_read_handle),
+                    write_guids: Vec::new(),
+                    character_consumer: |_, characters_read, _, _| {
+                        characters_read
+                            .values()
+                            .filter(|character_read_handle| character_read_handle.can_advance())
+                            .map(|character_read_handle| character_read_handle.guid)
+                            .collect()
+                    },
+                });
+        if active_npc_guids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (mut broadcasts, respawns) =
+            self.lock_enforcer()
+                .read_characters(|_| CharacterLockRequest {
+                    read_guids: Vec::new(),
+                    write_guids: active_npc_guids,
+                    character_consumer: |characters_table_read_handle,
+                                         _,
+                                         mut characters_write,
+                                         _|
+                     -> Result<_, ProcessPacketError> {
+                        let mut broadcasts = Vec::new();
+                        let mut respawns = Vec::new();
+                        for character_write_handle in characters_write.values_mut() {
+                            broadcasts.append(&mut character_write_handle.regenerate_health(
+                                elapsed_millis,
+                                self.server_config.health_regen_delay_secs as u128 * 1000,
+                            )?);
+
+                            let zone_packets =
+                                character_write_handle.advance_rail(elapsed_millis)?;
+                            if !zone_packets.is_empty() {
+                                broadcasts.append(&mut Zone::player_broadcast(
+                                    character_write_handle.instance_guid,
+                                    characters_table_read_handle,
+                                    zone_packets,
+                                    None,
+                                ));
+                            }
+
+                            let respawn_packets =
+                                character_write_handle.advance_respawn(elapsed_millis)?;
+                            if !respawn_packets.is_empty() {
+                                respawns.push((
+                                    character_write_handle.guid,
+                                    character_write_handle.instance_guid,
+                                    character_write_handle.pos,
+                                    character_write_handle.despawned_millis.is_none(),
+                                    respawn_packets,
+                                ));
+                            }
+                        }
+
+                        Ok((broadcasts, respawns))
+                    },
+                })?;
+        if respawns.is_empty() {
+            return Ok(broadcasts);
+        }
+
+        // Players' visible characters are only locked when an NPC in their zone respawned or
+        // despawned
+        self.lock_enforcer()
+            .read_characters(|characters_table_read_handle| CharacterLockRequest {
+                read_guids: Vec::new(),
+                write_guids: respawns
+                    .iter()
+                    .map(|(_, instance_guid, _, _, _)| *instance_guid)
+                    .collect::<BTreeSet<u64>>()
+                    .into_iter()
+                    .flat_map(|instance_guid| {
+                        characters_table_read_handle
+                            .keys_by_index((instance_guid, CharacterCategory::Player))
+                    })
+                    .collect(),
+                character_consumer: |_, _, mut characters_write, zones_lock_enforcer| {
+                    let respawned_zone_guids = respawns
+                        .iter()
+                        .filter(|(_, _, _, spawned, _)| *spawned)
+                        .map(|(_, instance_guid, _, _, _)| *instance_guid)
+                        .collect();
+                    zones_lock_enforcer.read_zones(|_| ZoneLockRequest {
+                        read_guids: respawned_zone_guids,
+                        write_guids: Vec::new(),
+                        zone_consumer: |_, zones_read, _| {
+                            for (guid, instance_guid, pos, spawned, packets) in respawns {
+                                let players = characters_write
+                                    .values_mut()
+                                    .filter(|character| character.instance_guid == instance_guid)
+                                    .map(|character| &mut **character);
+                                let player_guids = if spawned {
+                                    match zones_read.get(&instance_guid) {
+                                        Some(zone_read_handle) => {
+                                            zone_read_handle.show_character(players, guid, &pos)
+                                        }
+                                        None => Vec::new(),
+                                    }
+                                } else {
+                                    Zone::hide_character(players, guid)
+                                };
+
+                                if !player_guids.is_empty() {
+                                    broadcasts.push(Broadcast::Multi(player_guids, packets));
+                                }
+                            }
+                        },
+                    });
+                },
+            });
+
+        Ok(broadcasts)
+    }
+
+    // Idle time only counts while mounted so that mounting after standing still doesn't
+    // immediately dismount the player
+    fn dismount_if_idle(
+        &self,
+        character: &mut CharacterWriteGuard,
+        elapsed_millis: u128,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        let Some(idle_dismount_secs) = self.server_config.idle_dismount_secs else {
+            return Ok(Vec::new());
+        };
+        if !matches!(character.character_type, CharacterType::Player(_)) {
+            return Ok(Vec::new());
+        }
+
+        if character.mount_id.is_none() {
+            character.idle_millis = 0;
+            return Ok(Vec::new());
+        }
+
+        character.idle_millis += elapsed_millis;
+        if character.idle_millis < idle_dismount_secs as u128 * 1000 {
+            return Ok(Vec::new());
+        }
+
+        character.idle_millis = 0;
+        reply_dismount(
+            shorten_player_guid(character.guid)?,
+            character,
+            self.mounts(),
+        )
+    }
+
+    // Autosave may run on its own thread, so it holds the store lock from the copy until the last
+    // write. Otherwise, a player who logs out in between could have their final save overwritten
+    // with this older copy.
+    pub fn autosave(&self) {
+        let _store_guard = self.store_lock.lock();
+
+        // Copy the players so that the store is written to after the character locks are released
+        let players: Vec<Character> =
+            self.lock_enforcer()
+                .read_characters(|characters_table_read_handle| CharacterLockRequest {
+                    read_guids: GameServer::player_guids(characters_table_read_handle),
+                    write_guids: Vec::new(),
+                    character_consumer: |_, characters_read, _, _| {
+                        characters_read
+                            .values()
+                            .map(|character_read_handle| (*character_read_handle).clone())
+                            .collect()
+                    },
+                });
+
+        // Players whose state is the same as their last save don't need to be written again
+        let dirty_players: Vec<(Character, SavedCharacter)> = {
+            let saved_characters = self.saved_characters.lock();
+            players
+                .into_iter()
+                .map(|character| {
+                    let saved_character = SavedCharacter::from(&character);
+                    (character, saved_character)
+                })
+                .filter(|(character, saved_character)| {
+                    saved_characters.get(&character.guid) != Some(saved_character)
+                })
+                .collect()
+        };
+
+        for (character, saved_character) in dirty_players {
+            if self.save_character(&character) {
+                self.saved_characters
+                    .lock()
+                    .insert(character.guid, saved_character);
+            }
+        }
+    }
+
+    pub fn online_players(&self) -> Vec<(u64, u64)> {
+        self.lock_enforcer()
+            .read_characters(|characters_table_read_handle| {
+                let players = characters_table_read_handle
+                    .keys()
+                    .filter_map(|guid| match characters_table_read_handle.index(guid) {
+                        Some((instance_guid, CharacterCategory::Player)) => {
+                            Some((guid, instance_guid))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+
+                CharacterLockRequest {
+                    read_guids: Vec::new(),
+                    write_guids: Vec::new(),
+                    character_consumer: move |_, _, _, _| players,
+                }
+            })
+    }
+
+    pub fn broadcast_global(&self, packets: Vec<Vec<u8>>) -> Vec<Broadcast> {
+        self.lock_enforcer()
+            .read_characters(|_| CharacterLockRequest {
+                read_guids: Vec::new(),
+                write_guids: Vec::new(),
+                character_consumer: move |characters_table_read_handle,
+                                          _,
+                                          _,
+                                          zones_lock_enforcer| {
+                    zones_lock_enforcer.read_zones(|zones_table_read_handle| {
+                        let broadcasts = zones_table_read_handle
+                            .keys()
+                            .flat_map(|instance_guid| {
+                                Zone::player_broadcast(
+                                    instance_guid,
+                                    characters_table_read_handle,
+                                    packets.clone(),
+                                    None,
+                                )
+                            })
+                            .collect();
+
+                        ZoneLockRequest {
+                            read_guids: Vec::new(),
+                            write_guids: Vec::new(),
+                            zone_consumer: move |_, _, _| broadcasts,
+                        }
+                    })
+                },
+            })
+    }
+
+    pub fn kick_player(
+        &self,
+        guid: u32,
+        reason: DisconnectReason,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        let (removed_character, broadcasts) = self.lock_enforcer().write_characters(
+            |characters_table_write_handle, _| -> Result<_, ProcessPacketError> {
+                if let Some((character, (instance_guid, _))) =
+                    characters_table_write_handle.remove(player_guid(guid))
+                {
+                    println!("Kicking player {}: {}", guid, reason);
+                    close_return_doors(characters_table_write_handle, instance_guid);
+                    let mut broadcasts = vec![Broadcast::Disconnect(guid, reason)];
+                    broadcasts.append(&mut stop_seeking(
+                        characters_table_write_handle,
+                        instance_guid,
+                        player_guid(guid),
+                    )?);
+                    broadcasts.append(&mut players_in_zone(
+                        characters_table_write_handle,
+                        instance_guid,
+                        vec![GamePacket::serialize(&TunneledPacket {
+                            unknown1: true,
+                            inner: RemoveGracefully {
+                                guid: player_guid(guid),
+                                unknown1: false,
+                                unknown2: 0,
+                                unknown3: 0,
+                                unknown4: 0,
+                                timer: 1000,
+                            },
+                        })?],
+                    ));
+                    Ok((Some(character), broadcasts))
+                } else {
+                    Ok((None, Vec::new()))
+                }
+            },
+        )?;
+
+        self.save_removed_character(removed_character);
+        Ok(broadcasts)
+    }
+
+    pub fn set_in_combat(
+        &self,
+        guid: u32,
+        in_combat: bool,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        self.lock_enforcer()
+            .read_characters(|_| CharacterLockRequest {
+                read_guids: Vec::new(),
+                write_guids: vec![player_guid(guid)],
+                character_consumer: |characters_table_read_handle, _, mut characters_write, _| {
+                    if let Some(character_write_handle) =
+                        characters_write.get_mut(&player_guid(guid))
+                    {
+                        let mut broadcasts = character_write_handle
+                            .set_in_combat(in_combat, characters_table_read_handle)?;
+
+                        // Players can't stay mounted in combat
+                        if in_combat {
+                            broadcasts.append(&mut reply_dismount(
+                                guid,
+                                character_write_handle,
+                                self.mounts(),
+                            )?);
+                        }
+
+                        Ok(broadcasts)
+                    } else {
+                        println!(
+                            "Tried to change combat state of non-existent player {}",
+                            guid
+                        );
+                        Err(ProcessPacketError::CorruptedPacket)
+                    }
+                },
+            })
+    }
+
+    pub fn logout(&self, guid: u32) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        let (removed_character, broadcasts) = self.lock_enforcer().write_characters(
+            |characters_table_write_handle, _| -> Result<_, ProcessPacketError> {
+                if let Some((character, (instance_guid, _))) =
+                    characters_table_write_handle.remove(player_guid(guid))
+                {
+                    close_return_doors(characters_table_write_handle, instance_guid);
+                    let broadcasts = stop_seeking(
+                        characters_table_write_handle,
+                        instance_guid,
+                        player_guid(guid),
+                    )?;
+                    Ok((Some(character), broadcasts))
+                } else {
+                    Ok((None, Vec::new()))
+                }
+            },
+        )?;
+
+        self.save_removed_character(removed_character);
+        Ok(broadcasts)
+    }
+
+    // The character was removed from the table, so the store is written to after the characters
+    // lock is released
+    fn save_removed_character(&self, removed_character: Option<Lock<Character>>) {
+        if let Some(character) = removed_character {
+            let _store_guard = self.store_lock.lock();
+            let character = character.read();
+            self.save_character(&character);
+            self.saved_characters.lock().remove(&character.guid);
+        }
+    }
+
+    fn save_character(&self, character: &Character) -> bool {
+        match self.character_store.save(character) {
+            Ok(_) => true,
+            Err(err) => {
+                println!("Unable to save character {}: {}", character.guid, err);
+                false
+            }
+        }
+    }
+
+    fn owned_mounts(&self, saved_character: &SavedCharacter) -> BTreeMap<u32, MountConfig> {
+        saved_character
+            .owned_mounts
+            .iter()
+            .filter_map(|mount_id| {
+                self.mounts
+                    .get(mount_id)
+                    .map(|mount| (*mount_id, mount.clone()))
+            })
+            .collect()
+    }
+
     pub fn process_packet(
         &self,
         sender: u32,
@@ -285,55 +865,48 @@ impl GameServer {
                     };
                     //packets.push(GamePacket::serialize(&npc)?);
 
-                    let (stat_packet, character_guids) = self.lock_enforcer().read_characters(|_| CharacterLockRequest {
+                    let (stat_packet, character_guids, health, power, show_tutorial) = self.lock_enforcer().read_characters(|_| CharacterLockRequest {
                         read_guids: Vec::new(),
-                        write_guids: Vec::new(),
-                        character_consumer: |characters_table_read_handle, _, _, zones_lock_enforcer| {
-                            if let Some((instance_guid, _)) = characters_table_read_handle.index(player_guid(sender)) {
+                        write_guids: vec![player_guid(sender)],
+                        character_consumer: |characters_table_read_handle, _, mut characters_write, zones_lock_enforcer| {
+                            if let Some(character_write_handle) = characters_write.get_mut(&player_guid(sender)) {
+                                let instance_guid = character_write_handle.instance_guid;
                                 zones_lock_enforcer.read_zones(|_| ZoneLockRequest {
                                     read_guids: vec![instance_guid],
                                     write_guids: Vec::new(),
                                     zone_consumer: |_, zones_read, _| {
                                         if let Some(zone) = zones_read.get(&instance_guid) {
+                                            zone.apply_stats(character_write_handle);
                                             let stats = TunneledPacket {
                                                 unknown1: true,
-                                                inner: Stats {
-                                                    stats: vec![
-                                                        Stat {
-                                                            id: StatId::Speed,
-                                                            multiplier: 1,
-                                                            value1: 0.0,
-                                                            value2: zone.speed,
-                                                        },
-                                                        Stat {
-                                                            id: StatId::PowerRegen,
-                                                            multiplier: 1,
-                                                            value1: 0.0,
-                                                            value2: 1.0,
-                                                        },
-                                                        Stat {
-                                                            id: StatId::PowerRegen,
-                                                            multiplier: 1,
-                                                            value1: 0.0,
-                                                            value2: 1.0,
-                                                        },
-                                                        Stat {
-                                                            id: StatId::GravityMultiplier,
-                                                            multiplier: 1,
-                                                            value1: 0.0,
-                                                            value2: zone.gravity_multiplier,
-                                                        },
-                                                        Stat {
-                                                            id: StatId::JumpHeightMultiplier,
-                                                            multiplier: 1,
-                                                            value1: 0.0,
-                                                            value2: zone.jump_height_multiplier,
-                                                        },
-                                                    ],
-                                                },
+                                                inner: character_write_handle.stats.full_update(&[
+                                                    StatId::Speed,
+                                                    StatId::PowerRegen,
+                                                    StatId::GravityMultiplier,
+                                                    StatId::JumpHeightMultiplier,
+                                                ]),
+                                            };
+
+                                            // Send the player's current values so that a repeated ready packet
+                                            // does not reset them on the client
+                                            let health = Health {
+                                                current: character_write_handle.health,
+                                                max: character_write_handle.max_health,
+                                            };
+                                            let power = Power {
+                                                current: character_write_handle.power,
+                                                max: character_write_handle.max_power,
+                                            };
+
+                                            // Only new players see the tutorial, and only once
+                                            let show_tutorial = match &mut character_write_handle.character_type {
+                                                CharacterType::Player(player_state) => {
+                                                    !std::mem::replace(&mut player_state.tutorial_complete, true)
+                                                }
+                                                _ => false,
                                             };
 
-                                            Ok((GamePacket::serialize(&stats)?, Zone::character_guids(instance_guid, characters_table_read_handle)))
+                                            Ok((GamePacket::serialize(&stats)?, Zone::character_guids(instance_guid, characters_table_read_handle), health, power, show_tutorial))
                                         } else {
                                             println!(
                                                 "Player {} sent a ready packet from unknown zone {}",
@@ -354,40 +927,61 @@ impl GameServer {
                     })?;
                     packets.push(stat_packet);
 
+                    // Start from an empty set so a repeated ready packet resends nearby characters
                     let mut character_packets =
                         self.lock_enforcer()
                             .read_characters(|_| CharacterLockRequest {
-                                read_guids: character_guids.clone(),
-                                write_guids: Vec::new(),
-                                character_consumer: |_, characters_read, _, _| {
-                                    let mut packets = Vec::new();
-
-                                    for guid in character_guids {
-                                        if let Some(character) = characters_read.get(&guid) {
-                                            packets.append(&mut character.to_packets()?);
+                                read_guids: character_guids
+                                    .into_iter()
+                                    .filter(|guid| *guid != player_guid(sender))
+                                    .collect(),
+                                write_guids: vec![player_guid(sender)],
+                                character_consumer:
+                                    |_,
+                                     characters_read,
+                                     mut characters_write,
+                                     zones_lock_enforcer| {
+                                        let Some(character_write_handle) =
+                                            characters_write.get_mut(&player_guid(sender))
+                                        else {
+                                            return Ok(Vec::new());
+                                        };
+                                        if let CharacterType::Player(player_state) =
+                                            &mut character_write_handle.character_type
+                                        {
+                                            player_state.visible_characters.clear();
                                         }
-                                    }
 
-                                    Ok::<Vec<Vec<u8>>, ProcessPacketError>(packets)
-                                },
+                                        let instance_guid = character_write_handle.instance_guid;
+                                        zones_lock_enforcer.read_zones(|_| ZoneLockRequest {
+                                            read_guids: vec![instance_guid],
+                                            write_guids: Vec::new(),
+                                            zone_consumer: |_, zones_read, _| {
+                                                if let Some(zone_read_handle) =
+                                                    zones_read.get(&instance_guid)
+                                                {
+                                                    zone_read_handle.update_interest(
+                                                        character_write_handle,
+                                                        &characters_read,
+                                                    )
+                                                } else {
+                                                    Ok(Vec::new())
+                                                }
+                                            },
+                                        })
+                                    },
                             })?;
                     packets.append(&mut character_packets);
 
                     let health = TunneledPacket {
                         unknown1: true,
-                        inner: Health {
-                            current: 25000,
-                            max: 25000,
-                        },
+                        inner: health,
                     };
                     packets.push(GamePacket::serialize(&health)?);
 
                     let power = TunneledPacket {
                         unknown1: true,
-                        inner: Power {
-                            current: 300,
-                            max: 300,
-                        },
+                        inner: power,
                     };
                     packets.push(GamePacket::serialize(&power)?);
 
@@ -395,17 +989,19 @@ impl GameServer {
 
                     packets.append(&mut make_test_nameplate_image(sender)?);
 
-                    let welcome_screen = TunneledPacket {
-                        unknown1: true,
-                        inner: WelcomeScreen {
-                            show_ui: true,
-                            unknown1: vec![],
-                            unknown2: vec![],
-                            unknown3: 0,
-                            unknown4: 0,
-                        },
-                    };
-                    packets.push(GamePacket::serialize(&welcome_screen)?);
+                    if show_tutorial {
+                        let welcome_screen = TunneledPacket {
+                            unknown1: true,
+                            inner: WelcomeScreen {
+                                show_ui: true,
+                                unknown1: vec![],
+                                unknown2: vec![],
+                                unknown3: 0,
+                                unknown4: 0,
+                            },
+                        };
+                        packets.push(GamePacket::serialize(&welcome_screen)?);
+                    }
 
                     let zone_details_done = TunneledPacket {
                         unknown1: true,
@@ -421,6 +1017,68 @@ impl GameServer {
 
                     broadcasts.push(Broadcast::Single(sender, packets));
                 }
+                OpCode::ZoneDetailsDone => {
+                    let character_packets = self.lock_enforcer().read_characters(
+                        |characters_table_read_handle| {
+                            let character_guids = characters_table_read_handle
+                                .index(player_guid(sender))
+                                .map(|(instance_guid, _)| {
+                                    Zone::character_guids(
+                                        instance_guid,
+                                        characters_table_read_handle,
+                                    )
+                                })
+                                .unwrap_or_default();
+
+                            CharacterLockRequest {
+                                read_guids: character_guids
+                                    .into_iter()
+                                    .filter(|guid| *guid != player_guid(sender))
+                                    .collect(),
+                                write_guids: vec![player_guid(sender)],
+                                character_consumer:
+                                    |_, characters_read, mut characters_write, zones_lock_enforcer| {
+                                        let Some(character_write_handle) =
+                                            characters_write.get_mut(&player_guid(sender))
+                                        else {
+                                            return Ok(Vec::new());
+                                        };
+
+                                        if !character_write_handle.finish_loading_zone_details() {
+                                            println!(
+                                                "Player {} finished loading zone details that were not sent, state: {:?}",
+                                                sender, character_write_handle.zone_load_state
+                                            );
+                                            return Ok(Vec::new());
+                                        }
+
+                                        // Characters were held back while the zone loaded
+                                        let instance_guid = character_write_handle.instance_guid;
+                                        zones_lock_enforcer.read_zones(|_| ZoneLockRequest {
+                                            read_guids: vec![instance_guid],
+                                            write_guids: Vec::new(),
+                                            zone_consumer: |_, zones_read, _| {
+                                                if let Some(zone_read_handle) =
+                                                    zones_read.get(&instance_guid)
+                                                {
+                                                    zone_read_handle.update_interest(
+                                                        character_write_handle,
+                                                        &characters_read,
+                                                    )
+                                                } else {
+                                                    Ok(Vec::new())
+                                                }
+                                            },
+                                        })
+                                    },
+                            }
+                        },
+                    )?;
+
+                    if !character_packets.is_empty() {
+                        broadcasts.push(Broadcast::Single(sender, character_packets));
+                    }
+                }
                 OpCode::GameTimeSync => {
                     let game_time_sync = TunneledPacket {
                         unknown1: true,
@@ -432,7 +1090,7 @@ impl GameServer {
                     ));
                 }
                 OpCode::Command => {
-                    broadcasts.append(&mut process_command(self, &mut cursor)?);
+                    broadcasts.append(&mut process_command(self, sender, &mut cursor)?);
                 }
                 OpCode::UpdatePlayerPosition => {
                     let pos_update: UpdatePlayerPosition =
@@ -504,7 +1162,7 @@ impl GameServer {
                                             let spawn_pos = zone.default_spawn_pos;
                                             let spawn_rot = zone.default_spawn_rot;
 
-                                            teleport_within_zone(sender, spawn_pos, spawn_rot)
+                                            teleport_within_zone(sender, spawn_pos, spawn_rot, None)
                                         } else {
                                             println!("Player {} outside zone tried to teleport to safety", sender);
                                             Err(ProcessPacketError::CorruptedPacket)
@@ -558,11 +1216,25 @@ impl GameServer {
                     ));
                 }
                 OpCode::Chat => {
-                    broadcasts.append(&mut process_chat_packet(&mut cursor, sender)?);
+                    broadcasts.append(&mut process_chat_packet(&mut cursor, sender, self)?);
+                }
+                OpCode::Player => {
+                    // The server sends this op code with the player's data. It has no known
+                    // sub-op codes for player actions, which arrive through their own op codes.
+                    println!(
+                        "Player {} sent a server-only Player packet: {:x?}",
+                        sender, data
+                    );
+                }
+                _ => {
+                    println!("Unimplemented: {:?}, {:x?}", op_code, data);
+                    return Err(ProcessPacketError::UnknownOpCode);
                 }
-                _ => println!("Unimplemented: {:?}, {:x?}", op_code, data),
             },
-            Err(_) => println!("Unknown op code: {}, {:x?}", raw_op_code, data),
+            Err(_) => {
+                println!("Unknown op code: {}, {:x?}", raw_op_code, data);
+                return Err(ProcessPacketError::UnknownOpCode);
+            }
         }
 
         Ok(broadcasts)
@@ -576,6 +1248,22 @@ impl GameServer {
         &self.mounts
     }
 
+    pub fn max_unknown_op_codes(&self) -> Option<u32> {
+        self.server_config.max_unknown_op_codes
+    }
+
+    pub fn stale_gap(&self) -> Option<StaleGapConfig> {
+        self.server_config.stale_gap
+    }
+
+    pub fn adopt_first_client_sequence(&self) -> bool {
+        self.server_config.adopt_first_client_sequence
+    }
+
+    pub fn server_info(&self) -> &ServerInfo {
+        &self.server_config.server_info
+    }
+
     pub fn lock_enforcer(&self) -> LockEnforcer {
         self.lock_enforcer_source.lock_enforcer()
     }
@@ -596,4 +1284,544 @@ impl GameServer {
     pub fn zones_by_template(zones: &ZoneTableReadHandle<'_>, template_guid: u8) -> Vec<u64> {
         zones.keys_by_index(template_guid).collect()
     }
+
+    fn npc_guids(characters: &CharacterTableReadHandle<'_>) -> Vec<u64> {
+        characters
+            .keys()
+            .filter(|guid| {
+                !matches!(
+                    characters.index(*guid),
+                    Some((_, CharacterCategory::Player)) | None
+                )
+            })
+            .collect()
+    }
+
+    fn player_guids(characters: &CharacterTableReadHandle<'_>) -> Vec<u64> {
+        characters
+            .keys()
+            .filter(|guid| {
+                matches!(
+                    characters.index(*guid),
+                    Some((_, CharacterCategory::Player))
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_server::character_store::MemoryCharacterStore;
+    use crate::game_server::game_packet::Pos;
+    use crate::game_server::guid::GuidTableHandle;
+    use crate::game_server::lock_enforcer::ZoneLockEnforcer;
+    use crate::game_server::login::{GameSettings, SinglePlayerAuthenticator};
+    use crate::game_server::mount::{MountSpawn, MOUNT_IN_COMBAT_MESSAGE};
+    use crate::game_server::player_data::{PLAYER_MAX_HEALTH, PLAYER_MAX_POWER};
+    use byteorder::WriteBytesExt;
+
+    #[test]
+    fn test_online_players_lists_zone_of_each_player() {
+        let characters = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            characters_write_handle
+                .insert(make_test_player(1, &BTreeMap::new()).data.to_character(24));
+            characters_write_handle
+                .insert(make_test_player(2, &BTreeMap::new()).data.to_character(25));
+        }
+
+        let game_server = GameServer::for_test(characters, GuidTable::new());
+        assert_eq!(
+            game_server.online_players(),
+            vec![(player_guid(1), 24), (player_guid(2), 25)]
+        );
+    }
+
+    #[test]
+    fn test_global_broadcast_reaches_players_in_every_zone() {
+        let game_server = GameServer::new(
+            Path::new("config"),
+            Box::new(SinglePlayerAuthenticator),
+            Box::new(MemoryCharacterStore::default()),
+        )
+        .unwrap();
+        let zone_guids: Vec<u64> = ZoneLockEnforcer::from(game_server.lock_enforcer()).read_zones(
+            |zones_table_read_handle| {
+                let zone_guids = zones_table_read_handle.keys().take(2).collect();
+                ZoneLockRequest {
+                    read_guids: Vec::new(),
+                    write_guids: Vec::new(),
+                    zone_consumer: move |_, _, _| zone_guids,
+                }
+            },
+        );
+        assert_eq!(zone_guids.len(), 2);
+
+        game_server
+            .lock_enforcer()
+            .write_characters(|characters_table_write_handle, _| {
+                for (guid, zone_guid) in [(1, zone_guids[0]), (2, zone_guids[1])] {
+                    characters_table_write_handle.insert(
+                        make_test_player(guid, game_server.mounts())
+                            .data
+                            .to_character(zone_guid),
+                    );
+                }
+            });
+
+        let announcement = hud_message(1).unwrap();
+        let broadcasts = game_server.broadcast_global(vec![announcement.clone()]);
+        assert_eq!(broadcasts.len(), 2);
+        for expected_guid in [1, 2] {
+            assert!(broadcasts.iter().any(|broadcast| matches!(broadcast,
+                Broadcast::Multi(guids, packets)
+                    if guids == &vec![expected_guid] && packets == &vec![announcement.clone()])));
+        }
+    }
+
+    #[test]
+    fn test_kick_player_removes_character_and_disconnects() {
+        let characters = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            characters_write_handle
+                .insert(make_test_player(1, &BTreeMap::new()).data.to_character(24));
+            characters_write_handle
+                .insert(make_test_player(2, &BTreeMap::new()).data.to_character(24));
+        }
+
+        let game_server = GameServer::for_test(characters, GuidTable::new());
+        let broadcasts = game_server
+            .kick_player(1, DisconnectReason::Application)
+            .unwrap();
+        assert!(matches!(
+            &broadcasts[..],
+            [
+                Broadcast::Disconnect(1, DisconnectReason::Application),
+                Broadcast::Multi(guids, _)
+            ] if guids == &vec![2]
+        ));
+        assert_eq!(game_server.online_players(), vec![(player_guid(2), 24)]);
+
+        // Kicking a player who is not online does nothing
+        assert!(game_server
+            .kick_player(1, DisconnectReason::Application)
+            .unwrap()
+            .is_empty());
+    }
+
+    fn client_is_ready(game_server: &GameServer, sender: u32) -> Vec<Vec<u8>> {
+        let mut data = Vec::new();
+        data.write_u16::<LittleEndian>(OpCode::ClientIsReady as u16)
+            .unwrap();
+
+        let mut broadcasts = game_server.process_packet(sender, data).unwrap();
+        assert_eq!(broadcasts.len(), 1);
+        match broadcasts.remove(0) {
+            Broadcast::Single(guid, packets) if guid == sender => packets,
+            _ => panic!("Ready packets were not sent only to the player"),
+        }
+    }
+
+    #[test]
+    fn test_client_is_ready_sends_world_in_order() {
+        let game_server = GameServer::new(
+            Path::new("config"),
+            Box::new(SinglePlayerAuthenticator),
+            Box::new(MemoryCharacterStore::default()),
+        )
+        .unwrap();
+        let zone_guid = ZoneLockEnforcer::from(game_server.lock_enforcer()).read_zones(
+            |zones_table_read_handle| {
+                let zone_guid = zones_table_read_handle.keys().next().unwrap();
+                ZoneLockRequest {
+                    read_guids: Vec::new(),
+                    write_guids: Vec::new(),
+                    zone_consumer: move |_, _, _| zone_guid,
+                }
+            },
+        );
+
+        let mut character = make_test_player(1, game_server.mounts())
+            .data
+            .to_character(zone_guid);
+        character.health = 100;
+        if let CharacterType::Player(player_state) = &mut character.character_type {
+            player_state.tutorial_complete = true;
+        }
+        game_server
+            .lock_enforcer()
+            .write_characters(|characters_table_write_handle, _| {
+                characters_table_write_handle.insert(character);
+            });
+
+        let packets = client_is_ready(&game_server, 1);
+        let position = |expected: Vec<u8>| packets.iter().position(|packet| *packet == expected);
+
+        let health_position = position(
+            GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: Health {
+                    current: 100,
+                    max: PLAYER_MAX_HEALTH,
+                },
+            })
+            .unwrap(),
+        )
+        .expect("Ready packets are missing the player's current health");
+        let power_position = position(
+            GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: Power {
+                    current: PLAYER_MAX_POWER,
+                    max: PLAYER_MAX_POWER,
+                },
+            })
+            .unwrap(),
+        )
+        .expect("Ready packets are missing the player's current power");
+        assert_eq!(power_position, health_position + 1);
+
+        let zone_details_done_position = position(
+            GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: ZoneDetailsDone {},
+            })
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(health_position < zone_details_done_position);
+        assert_eq!(zone_details_done_position, packets.len() - 2);
+
+        // Sending the ready packet again must not change what the player receives
+        assert_eq!(client_is_ready(&game_server, 1), packets);
+    }
+
+    struct StubAuthenticator;
+
+    impl Authenticator for StubAuthenticator {
+        fn authenticate(&self, credentials: &LoginRequest) -> Result<u64, AuthError> {
+            if credentials.session_id == "valid" {
+                Ok(5)
+            } else if credentials.session_id == "too_large" {
+                Ok(u32::MAX as u64 + 1)
+            } else {
+                Err(AuthError::InvalidCredentials)
+            }
+        }
+    }
+
+    fn login_request(session_id: &str) -> Vec<u8> {
+        GamePacket::serialize(&LoginRequest {
+            session_id: session_id.to_string(),
+        })
+        .unwrap()
+    }
+
+    fn login_reply(logged_in: bool) -> Vec<u8> {
+        GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: LoginReply { logged_in },
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_login_uses_authenticator() {
+        let game_server = GameServer::new(
+            Path::new("config"),
+            Box::new(StubAuthenticator),
+            Box::new(MemoryCharacterStore::default()),
+        )
+        .unwrap();
+
+        match game_server.login(login_request("valid")).unwrap() {
+            LoginResult::Accepted(5, broadcasts) => {
+                assert!(matches!(&broadcasts[..],
+                    [Broadcast::Single(5, packets)] if packets[0] == login_reply(true)));
+            }
+            _ => panic!("Valid login was not accepted for the authenticated player"),
+        }
+        assert_eq!(game_server.online_players().len(), 1);
+
+        match game_server.login(login_request("invalid")).unwrap() {
+            LoginResult::Rejected(packets) => assert_eq!(packets, vec![login_reply(false)]),
+            _ => panic!("Invalid login was accepted"),
+        }
+        assert_eq!(game_server.online_players().len(), 1);
+
+        match game_server.login(login_request("too_large")).unwrap() {
+            LoginResult::Rejected(packets) => assert_eq!(packets, vec![login_reply(false)]),
+            _ => panic!("Login with a GUID too large for a player ID was accepted"),
+        }
+        assert_eq!(game_server.online_players().len(), 1);
+    }
+
+    fn login_packets(game_server: &GameServer) -> Vec<Vec<u8>> {
+        match game_server.login(login_request("")).unwrap() {
+            LoginResult::Accepted(1, mut broadcasts) => match broadcasts.pop() {
+                Some(Broadcast::Single(1, packets)) => packets,
+                _ => panic!("Login did not send packets to the player"),
+            },
+            _ => panic!("Login was not accepted"),
+        }
+    }
+
+    #[test]
+    fn test_login_sends_configured_motd() {
+        let mut game_server = GameServer::new(
+            Path::new("config"),
+            Box::new(SinglePlayerAuthenticator),
+            Box::new(MemoryCharacterStore::default()),
+        )
+        .unwrap();
+        assert!(!login_packets(&game_server).contains(&hud_message(1234).unwrap()));
+
+        game_server.logout(1).unwrap();
+        game_server.server_config.motd = Some(1234);
+        assert!(login_packets(&game_server).contains(&hud_message(1234).unwrap()));
+    }
+
+    #[test]
+    fn test_login_sends_game_settings_for_configured_environment() {
+        let mut game_server = GameServer::new(
+            Path::new("config"),
+            Box::new(SinglePlayerAuthenticator),
+            Box::new(MemoryCharacterStore::default()),
+        )
+        .unwrap();
+        let test_settings = GameSettings {
+            unknown1: 1,
+            unknown2: 2,
+            unknown3: 3,
+            unknown4: false,
+            time_scale: 2.0,
+        };
+        let settings_packet = |settings: &GameSettings| {
+            GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: settings.clone(),
+            })
+            .unwrap()
+        };
+        game_server.server_config.environment = Some("prod".to_string());
+        game_server
+            .server_config
+            .game_settings
+            .insert("test".to_string(), test_settings.clone());
+        assert!(login_packets(&game_server).contains(&settings_packet(&GameSettings::default())));
+
+        game_server.logout(1).unwrap();
+        game_server.server_config.environment = Some("test".to_string());
+        let packets = login_packets(&game_server);
+        assert!(packets.contains(&settings_packet(&test_settings)));
+        assert!(!packets.contains(&settings_packet(&GameSettings::default())));
+    }
+
+    #[test]
+    fn test_login_advertises_configured_server_info() {
+        let mut game_server = GameServer::new(
+            Path::new("config"),
+            Box::new(SinglePlayerAuthenticator),
+            Box::new(MemoryCharacterStore::default()),
+        )
+        .unwrap();
+        game_server.server_config.server_info = ServerInfo {
+            name: "Test Server".to_string(),
+            version: "1.2.3".to_string(),
+        };
+
+        assert_eq!(game_server.server_info().version, "1.2.3");
+        assert!(login_packets(&game_server)
+            .contains(&system_message("Connected to Test Server 1.2.3".to_string()).unwrap()));
+    }
+
+    #[test]
+    fn test_logout_saves_character_for_next_login() {
+        let game_server = GameServer::new(
+            Path::new("config"),
+            Box::new(SinglePlayerAuthenticator),
+            Box::new(MemoryCharacterStore::default()),
+        )
+        .unwrap();
+        let saved_pos = Pos {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+            w: 1.0,
+        };
+
+        game_server.login(login_request("")).unwrap();
+        game_server
+            .lock_enforcer()
+            .write_characters(|characters_table_write_handle, _| {
+                let mut character = characters_table_write_handle
+                    .get(player_guid(1))
+                    .unwrap()
+                    .write();
+                character.pos = saved_pos;
+                character.health = 7;
+            });
+        game_server.logout(1).unwrap();
+        assert!(game_server.online_players().is_empty());
+
+        game_server.login(login_request("")).unwrap();
+        game_server
+            .lock_enforcer()
+            .write_characters(|characters_table_write_handle, _| {
+                let character = characters_table_write_handle
+                    .get(player_guid(1))
+                    .unwrap()
+                    .read();
+                assert_eq!(character.pos, saved_pos);
+                assert_eq!(character.health, 7);
+            });
+    }
+
+    #[test]
+    fn test_tutorial_is_shown_only_to_new_players() {
+        let game_server = GameServer::new(
+            Path::new("config"),
+            Box::new(SinglePlayerAuthenticator),
+            Box::new(MemoryCharacterStore::default()),
+        )
+        .unwrap();
+        let welcome_screen = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: WelcomeScreen {
+                show_ui: true,
+                unknown1: vec![],
+                unknown2: vec![],
+                unknown3: 0,
+                unknown4: 0,
+            },
+        })
+        .unwrap();
+
+        game_server.login(login_request("")).unwrap();
+        assert!(client_is_ready(&game_server, 1).contains(&welcome_screen));
+        assert!(!client_is_ready(&game_server, 1).contains(&welcome_screen));
+        game_server.logout(1).unwrap();
+
+        game_server.login(login_request("")).unwrap();
+        assert!(!client_is_ready(&game_server, 1).contains(&welcome_screen));
+    }
+
+    #[test]
+    fn test_autosave_skips_unmodified_players() {
+        let character_store = MemoryCharacterStore::default();
+        let characters = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            characters_write_handle
+                .insert(make_test_player(1, &BTreeMap::new()).data.to_character(24));
+            characters_write_handle
+                .insert(make_test_player(2, &BTreeMap::new()).data.to_character(24));
+        }
+        let mut game_server = GameServer::for_test(characters, GuidTable::new());
+        game_server.character_store = Box::new(character_store.clone());
+
+        game_server.autosave();
+        assert_eq!(character_store.saves.lock().len(), 2);
+        character_store.saves.lock().clear();
+
+        game_server
+            .lock_enforcer()
+            .write_characters(|characters_table_write_handle, _| {
+                characters_table_write_handle
+                    .get(player_guid(1))
+                    .unwrap()
+                    .write()
+                    .health = 7;
+            });
+        game_server.autosave();
+
+        let saves = character_store.saves.lock();
+        assert_eq!(saves.get(&player_guid(1)).unwrap().health, 7);
+        assert!(!saves.contains_key(&player_guid(2)));
+    }
+
+    fn mount_id(game_server: &GameServer, guid: u32) -> Option<u32> {
+        game_server
+            .lock_enforcer()
+            .write_characters(|characters_table_write_handle, _| {
+                characters_table_write_handle
+                    .get(player_guid(guid))
+                    .unwrap()
+                    .read()
+                    .mount_id
+            })
+    }
+
+    #[test]
+    fn test_combat_prevents_mounting_and_dismounts() {
+        let game_server = GameServer::new(
+            Path::new("config"),
+            Box::new(SinglePlayerAuthenticator),
+            Box::new(MemoryCharacterStore::default()),
+        )
+        .unwrap();
+        game_server.login(login_request("")).unwrap();
+        let mount_spawn = GamePacket::serialize(&MountSpawn { mount_id: 1 }).unwrap();
+
+        game_server.set_in_combat(1, true).unwrap();
+        let broadcasts = game_server.process_packet(1, mount_spawn.clone()).unwrap();
+        let expected_packets = vec![system_message(MOUNT_IN_COMBAT_MESSAGE.to_string()).unwrap()];
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)] if packets == &expected_packets));
+        assert_eq!(mount_id(&game_server, 1), None);
+
+        game_server.set_in_combat(1, false).unwrap();
+        game_server.process_packet(1, mount_spawn).unwrap();
+        assert_eq!(mount_id(&game_server, 1), Some(1));
+
+        game_server.set_in_combat(1, true).unwrap();
+        assert_eq!(mount_id(&game_server, 1), None);
+    }
+
+    #[test]
+    fn test_idle_mounted_player_is_dismounted() {
+        let mut game_server = GameServer::new(
+            Path::new("config"),
+            Box::new(SinglePlayerAuthenticator),
+            Box::new(MemoryCharacterStore::default()),
+        )
+        .unwrap();
+        game_server.server_config.idle_dismount_secs = Some(60);
+        game_server.login(login_request("")).unwrap();
+        game_server
+            .process_packet(
+                1,
+                GamePacket::serialize(&MountSpawn { mount_id: 1 }).unwrap(),
+            )
+            .unwrap();
+
+        game_server.tick(59_000).unwrap();
+        assert_eq!(mount_id(&game_server, 1), Some(1));
+
+        // Moving resets the timer
+        Zone::move_character(
+            UpdatePlayerPosition {
+                guid: player_guid(1),
+                pos_x: 1.0,
+                pos_y: 2.0,
+                pos_z: 3.0,
+                rot_x: 0.0,
+                rot_y: 0.0,
+                rot_z: 0.0,
+                character_state: 0,
+                unknown: 0,
+            },
+            &game_server,
+        )
+        .unwrap();
+        game_server.tick(59_000).unwrap();
+        assert_eq!(mount_id(&game_server, 1), Some(1));
+
+        game_server.tick(1_000).unwrap();
+        assert_eq!(mount_id(&game_server, 1), None);
+    }
 }
@@ -5,25 +5,30 @@ use std::path::Path;
 
 use byteorder::{ReadBytesExt, WriteBytesExt};
 use num_enum::TryFromPrimitive;
-use parking_lot::{RwLockReadGuard, RwLockWriteGuard};
+use parking_lot::RwLockWriteGuard;
 use serde::Deserialize;
 
 use packet_serialize::{DeserializePacket, SerializePacket, SerializePacketError};
 
-use crate::game_server::client_update_packet::{Stat, StatId, Stats};
+use crate::game_server::chat::system_message;
+use crate::game_server::client_update_packet::{Position, StatId};
+use crate::game_server::composite_effect::CompositeEffects;
 use crate::game_server::game_packet::{Effect, GamePacket, OpCode, Pos};
 use crate::game_server::guid::Guid;
+use crate::game_server::known_assets::KnownAssets;
 use crate::game_server::player_update_packet::{
-    AddNpc, BaseAttachmentGroup, Icon, RemoveGracefully, WeaponAnimation,
+    wield_type_reset, AddNpc, Attachment, BaseAttachmentGroup, Icon, RemoveGracefully,
+    SlotCompositeEffectOverride, WeaponAnimation,
 };
+use crate::game_server::stat::StatSource;
 use crate::game_server::tunnel::TunneledPacket;
-use crate::game_server::unique_guid::{mount_guid, player_guid};
-use crate::game_server::zone::{Character, Zone};
+use crate::game_server::unique_guid::{player_guid, MountGuid};
+use crate::game_server::zone::Character;
 use crate::game_server::{Broadcast, GameServer, ProcessPacketError};
 
 use super::lock_enforcer::{CharacterLockRequest, ZoneLockRequest};
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct MountConfig {
     id: u32,
     speed_multiplier: f32,
@@ -33,8 +38,33 @@ pub struct MountConfig {
     texture: String,
     pub name_id: u32,
     pub icon_set_id: u32,
+    #[serde(rename = "mount_composite_effect")]
+    mount_composite_effect_name: String,
+    #[serde(skip)]
     mount_composite_effect: u32,
+    #[serde(rename = "dismount_composite_effect")]
+    dismount_composite_effect_name: String,
+    #[serde(skip)]
     dismount_composite_effect: u32,
+    // Played on the rider, such as a seated pose, for as long as they ride the mount
+    #[serde(default, rename = "rider_composite_effect")]
+    rider_composite_effect_name: Option<String>,
+    #[serde(skip)]
+    rider_composite_effect: Option<u32>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    #[serde(default)]
+    name_override: Option<String>,
+    // Mount names are hidden unless the config opts in to showing them
+    #[serde(default = "default_hide_name")]
+    hide_name: bool,
+    // Raises or lowers the rider so that they sit on the mount's seat
+    #[serde(default)]
+    seat_offset_y: f32,
+}
+
+fn default_hide_name() -> bool {
+    true
 }
 
 impl Guid<u32> for MountConfig {
@@ -43,12 +73,42 @@ impl Guid<u32> for MountConfig {
     }
 }
 
-pub fn load_mounts(config_dir: &Path) -> Result<BTreeMap<u32, MountConfig>, Error> {
+impl MountConfig {
+    // Unknown assets are only warned about because the client may have assets the list is missing
+    fn asset_warnings(&self, known_assets: &KnownAssets) -> Vec<String> {
+        known_assets
+            .unknown_references(self.model_id, &self.texture)
+            .into_iter()
+            .map(|reference| format!("Mount {} has {}", self.id, reference))
+            .collect()
+    }
+}
+
+pub fn load_mounts(
+    config_dir: &Path,
+    composite_effects: &CompositeEffects,
+    known_assets: Option<&KnownAssets>,
+) -> Result<BTreeMap<u32, MountConfig>, Error> {
     let mut file = File::open(config_dir.join("mounts.json"))?;
     let mounts: Vec<MountConfig> = serde_json::from_reader(&mut file)?;
 
     let mut mount_table = BTreeMap::new();
-    for mount in mounts {
+    for mut mount in mounts {
+        mount.mount_composite_effect = composite_effects.id(&mount.mount_composite_effect_name)?;
+        mount.dismount_composite_effect =
+            composite_effects.id(&mount.dismount_composite_effect_name)?;
+        mount.rider_composite_effect = mount
+            .rider_composite_effect_name
+            .as_deref()
+            .map(|name| composite_effects.id(name))
+            .transpose()?;
+
+        if let Some(known_assets) = known_assets {
+            for warning in mount.asset_warnings(known_assets) {
+                println!("Warning: {}", warning);
+            }
+        }
+
         let guid = mount.guid();
         let previous = mount_table.insert(guid, mount);
 
@@ -111,7 +171,7 @@ impl GamePacket for MountReply {
 
 #[derive(SerializePacket, DeserializePacket)]
 pub struct MountSpawn {
-    mount_id: u32,
+    pub mount_id: u32,
 }
 
 impl GamePacket for MountSpawn {
@@ -119,63 +179,89 @@ impl GamePacket for MountSpawn {
     const HEADER: Self::Header = MountOpCode::MountSpawn;
 }
 
+// Kept separate from slot 0 so that one-off effects played on the rider don't replace the
+// mount's effect
+const RIDER_EFFECT_SLOT: u32 = 1;
+
+fn rider_effect(rider_guid: u64, composite_effect: u32) -> Result<Vec<u8>, SerializePacketError> {
+    GamePacket::serialize(&TunneledPacket {
+        unknown1: true,
+        inner: SlotCompositeEffectOverride {
+            guid: rider_guid,
+            slot_id: RIDER_EFFECT_SLOT,
+            composite_effect,
+        },
+    })
+}
+
+fn seat_rider(
+    mount_pos: Pos,
+    mount_rot: Pos,
+    seat_offset_y: f32,
+) -> Result<Vec<u8>, SerializePacketError> {
+    GamePacket::serialize(&TunneledPacket {
+        unknown1: true,
+        inner: Position {
+            player_pos: Pos {
+                x: mount_pos.x,
+                y: mount_pos.y + seat_offset_y,
+                z: mount_pos.z,
+                w: mount_pos.w,
+            },
+            rot: mount_rot,
+            is_teleport: true,
+            unknown2: true,
+        },
+    })
+}
+
+const MOUNT_STATS: [StatId; 3] = [
+    StatId::Speed,
+    StatId::JumpHeightMultiplier,
+    StatId::GravityMultiplier,
+];
+
 pub fn reply_dismount(
     sender: u32,
-    zone: &RwLockReadGuard<Zone>,
     character: &mut RwLockWriteGuard<Character>,
     mounts: &BTreeMap<u32, MountConfig>,
 ) -> Result<Vec<Broadcast>, ProcessPacketError> {
     if let Some(mount_id) = character.mount_id {
         character.mount_id = None;
+        character.stats.remove_source(StatSource::Mount);
         if let Some(mount) = mounts.get(&mount_id) {
-            Ok(vec![Broadcast::Single(
-                sender,
-                vec![
-                    GamePacket::serialize(&TunneledPacket {
-                        unknown1: true,
-                        inner: DismountReply {
-                            rider_guid: player_guid(sender),
-                            composite_effect: mount.dismount_composite_effect,
-                        },
-                    })?,
-                    GamePacket::serialize(&TunneledPacket {
-                        unknown1: true,
-                        inner: RemoveGracefully {
-                            guid: mount_guid(sender, mount_id),
-                            unknown1: false,
-                            unknown2: 0,
-                            unknown3: 0,
-                            unknown4: 0,
-                            timer: 1000,
-                        },
-                    })?,
-                    GamePacket::serialize(&TunneledPacket {
-                        unknown1: true,
-                        inner: Stats {
-                            stats: vec![
-                                Stat {
-                                    id: StatId::Speed,
-                                    multiplier: 1,
-                                    value1: 0.0,
-                                    value2: zone.speed,
-                                },
-                                Stat {
-                                    id: StatId::JumpHeightMultiplier,
-                                    multiplier: 1,
-                                    value1: 0.0,
-                                    value2: zone.jump_height_multiplier,
-                                },
-                                Stat {
-                                    id: StatId::GravityMultiplier,
-                                    multiplier: 1,
-                                    value1: 0.0,
-                                    value2: zone.gravity_multiplier,
-                                },
-                            ],
-                        },
-                    })?,
-                ],
-            )])
+            let mut packets = vec![
+                GamePacket::serialize(&TunneledPacket {
+                    unknown1: true,
+                    inner: DismountReply {
+                        rider_guid: character.guid,
+                        composite_effect: mount.dismount_composite_effect,
+                    },
+                })?,
+                GamePacket::serialize(&TunneledPacket {
+                    unknown1: true,
+                    inner: RemoveGracefully {
+                        guid: MountGuid::from_rider(character.guid)?.into(),
+                        unknown1: false,
+                        unknown2: 0,
+                        unknown3: 0,
+                        unknown4: 0,
+                        timer: 1000,
+                    },
+                })?,
+            ];
+            if mount.rider_composite_effect.is_some() {
+                packets.push(rider_effect(character.guid, 0)?);
+            }
+            if let Some(stats) = character.stats.changed_update(&MOUNT_STATS) {
+                packets.push(GamePacket::serialize(&TunneledPacket {
+                    unknown1: true,
+                    inner: stats,
+                })?);
+            }
+            packets.push(wield_type_reset(character.guid)?);
+
+            Ok(vec![Broadcast::Single(sender, packets)])
         } else {
             println!(
                 "Player {} tried to dismount from non-existent mount",
@@ -198,28 +284,10 @@ fn process_dismount(
         .read_characters(|_| CharacterLockRequest {
             read_guids: Vec::new(),
             write_guids: vec![player_guid(sender)],
-            character_consumer: |_, _, mut characters_write, zones_lock_enforcer| {
+            character_consumer: |_, _, mut characters_write, _| {
                 if let Some(character_write_handle) = characters_write.get_mut(&player_guid(sender))
                 {
-                    zones_lock_enforcer.read_zones(|_| ZoneLockRequest {
-                        read_guids: vec![character_write_handle.instance_guid],
-                        write_guids: Vec::new(),
-                        zone_consumer: |_, zones_read, _| {
-                            if let Some(zone_read_handle) =
-                                zones_read.get(&character_write_handle.instance_guid)
-                            {
-                                reply_dismount(
-                                    sender,
-                                    zone_read_handle,
-                                    character_write_handle,
-                                    game_server.mounts(),
-                                )
-                            } else {
-                                println!("Player {} tried to enter unknown zone", sender);
-                                Err(ProcessPacketError::CorruptedPacket)
-                            }
-                        },
-                    })
+                    reply_dismount(sender, character_write_handle, game_server.mounts())
                 } else {
                     println!("Non-existent player {} tried to dismount", sender);
                     Err(ProcessPacketError::CorruptedPacket)
@@ -228,13 +296,14 @@ fn process_dismount(
         })
 }
 
+pub const MOUNT_IN_COMBAT_MESSAGE: &str = "You can't mount while in combat.";
+
 fn process_mount_spawn(
     cursor: &mut Cursor<&[u8]>,
     sender: u32,
     game_server: &GameServer,
 ) -> Result<Vec<Broadcast>, ProcessPacketError> {
     let mount_spawn = MountSpawn::deserialize(cursor)?;
-    let mount_guid = mount_guid(sender, mount_spawn.mount_id);
 
     if let Some(mount) = game_server.mounts().get(&mount_spawn.mount_id) {
         let packets = game_server.lock_enforcer().read_characters(|_| CharacterLockRequest {
@@ -248,7 +317,34 @@ fn process_mount_spawn(
                         zone_consumer: |_, zones_read, _| {
                             let mut packets = Vec::new();
 
-                            if let Some(zone_read_handle) = zones_read.get(&character_write_handle.instance_guid) {
+                            if zones_read.contains_key(&character_write_handle.instance_guid) {
+                                if character_write_handle.in_combat {
+                                    return Ok(vec![system_message(MOUNT_IN_COMBAT_MESSAGE.to_string())?]);
+                                }
+
+                                if let Some(mount_id) = character_write_handle.mount_id {
+                                    println!(
+                                        "Player {} tried to mount while already mounted on mount ID {}",
+                                        sender, mount_id
+                                    );
+                                    return Err(ProcessPacketError::CorruptedPacket);
+                                }
+
+                                let stats = &mut character_write_handle.stats;
+                                stats.set(StatId::Speed, StatSource::Mount, mount.speed_multiplier);
+                                stats.set(
+                                    StatId::JumpHeightMultiplier,
+                                    StatSource::Mount,
+                                    mount.jump_height_multiplier,
+                                );
+                                stats.set(
+                                    StatId::GravityMultiplier,
+                                    StatSource::Mount,
+                                    mount.gravity_multiplier,
+                                );
+
+                                let rider_guid = character_write_handle.guid;
+                                let mount_guid = MountGuid::from_rider(rider_guid)?.into();
                                 packets.append(&mut spawn_mount_npc(
                                     mount_guid,
                                     mount,
@@ -258,7 +354,7 @@ fn process_mount_spawn(
                                 packets.push(GamePacket::serialize(&TunneledPacket {
                                     unknown1: true,
                                     inner: MountReply {
-                                        rider_guid: player_guid(sender),
+                                        rider_guid,
                                         mount_guid,
                                         seat: 0,
                                         queue_pos: 1,
@@ -267,41 +363,22 @@ fn process_mount_spawn(
                                         unknown5: 0,
                                     },
                                 })?);
+                                if mount.seat_offset_y != 0.0 {
+                                    packets.push(seat_rider(
+                                        character_write_handle.pos,
+                                        character_write_handle.rot,
+                                        mount.seat_offset_y,
+                                    )?);
+                                }
+                                if let Some(rider_composite_effect) = mount.rider_composite_effect {
+                                    packets.push(rider_effect(rider_guid, rider_composite_effect)?);
+                                }
 
-                                packets.push(GamePacket::serialize(&TunneledPacket {
-                                    unknown1: true,
-                                    inner: Stats {
-                                        stats: vec![
-                                            Stat {
-                                                id: StatId::Speed,
-                                                multiplier: 1,
-                                                value1: 0.0,
-                                                value2: zone_read_handle.speed * mount.speed_multiplier,
-                                            },
-                                            Stat {
-                                                id: StatId::JumpHeightMultiplier,
-                                                multiplier: 1,
-                                                value1: 0.0,
-                                                value2: zone_read_handle.jump_height_multiplier
-                                                    * mount.jump_height_multiplier,
-                                            },
-                                            Stat {
-                                                id: StatId::GravityMultiplier,
-                                                multiplier: 1,
-                                                value1: 0.0,
-                                                value2: zone_read_handle.gravity_multiplier
-                                                    * mount.gravity_multiplier,
-                                            },
-                                        ],
-                                    },
-                                })?);
-
-                                if let Some(mount_id) = character_write_handle.mount_id {
-                                    println!(
-                                        "Player {} tried to mount while already mounted on mount ID {}",
-                                        sender, mount_id
-                                    );
-                                    return Err(ProcessPacketError::CorruptedPacket);
+                                if let Some(stats) = character_write_handle.stats.changed_update(&MOUNT_STATS) {
+                                    packets.push(GamePacket::serialize(&TunneledPacket {
+                                        unknown1: true,
+                                        inner: stats,
+                                    })?);
                                 }
 
                                 character_write_handle.mount_id = Some(mount.guid());
@@ -338,12 +415,12 @@ pub fn process_mount_packet(
             MountOpCode::MountSpawn => process_mount_spawn(cursor, sender, game_server),
             _ => {
                 println!("Unimplemented mount op code: {:?}", op_code);
-                Ok(Vec::new())
+                Err(ProcessPacketError::UnknownOpCode)
             }
         },
         Err(_) => {
             println!("Unknown mount op code: {}", raw_op_code);
-            Err(ProcessPacketError::CorruptedPacket)
+            Err(ProcessPacketError::UnknownOpCode)
         }
     }
 }
@@ -368,7 +445,7 @@ fn spawn_mount_npc(
             pos: spawn_pos,
             rot: spawn_rot,
             unknown8: 0,
-            attachments: vec![],
+            attachments: mount.attachments.clone(),
             is_not_targetable: 1,
             unknown10: 0,
             texture_name: mount.texture.clone(),
@@ -378,8 +455,8 @@ fn spawn_mount_npc(
             offset_y: 0.0,
             composite_effect: 0,
             weapon_animation: WeaponAnimation::None,
-            name_override: "".to_string(),
-            hide_name: true,
+            name_override: mount.name_override.clone().unwrap_or_default(),
+            hide_name: mount.hide_name,
             name_offset_x: 0.0,
             name_offset_y: 0.0,
             name_offset_z: 0.0,
@@ -396,27 +473,7 @@ fn spawn_mount_npc(
             sub_title_id: 0,
             active_animation_slot2: 1,
             head_model_id: 0,
-            effects: vec![Effect {
-                unknown1: 0,
-                unknown2: 0,
-                unknown3: 0,
-                unknown4: 0,
-                unknown5: 0,
-                unknown6: 0,
-                unknown7: 0,
-                unknown8: false,
-                unknown9: 0,
-                unknown10: 0,
-                unknown11: 0,
-                unknown12: 0,
-                composite_effect: mount.mount_composite_effect,
-                unknown14: 0,
-                unknown15: 0,
-                unknown16: 0,
-                unknown17: false,
-                unknown18: false,
-                unknown19: false,
-            }],
+            effects: vec![Effect::from_composite_effect(mount.mount_composite_effect)],
             disable_interact_popup: true,
             unknown33: 0,
             unknown34: false,
@@ -475,3 +532,297 @@ fn spawn_mount_npc(
         },
     })?])
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::game_server::character_store::MemoryCharacterStore;
+    use crate::game_server::client_update_packet::{Stat, Stats};
+    use crate::game_server::login::SinglePlayerAuthenticator;
+    use crate::game_server::player_data::make_test_player;
+
+    fn test_mount_config() -> MountConfig {
+        serde_json::from_str(
+            r#"{
+                "id": 1,
+                "speed_multiplier": 1.0,
+                "jump_height_multiplier": 1.0,
+                "gravity_multiplier": 1.0,
+                "model_id": 1,
+                "texture": "Rust",
+                "name_id": 0,
+                "icon_set_id": 0,
+                "mount_composite_effect": "atrt_mount",
+                "dismount_composite_effect": "atrt_dismount"
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_unknown_mount_model_warns() {
+        let mut mount = test_mount_config();
+        mount.model_id = 999;
+        let known_assets: KnownAssets =
+            serde_json::from_str(r#"{"model_ids": [1], "textures": ["Rust"]}"#).unwrap();
+
+        assert_eq!(
+            mount.asset_warnings(&known_assets),
+            vec!["Mount 1 has unknown model ID 999".to_string()]
+        );
+        assert!(mount
+            .asset_warnings(&serde_json::from_str(r#"{"textures": ["Rust"]}"#).unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_mount_name_override_is_serialized() {
+        let default_mount = test_mount_config();
+        assert!(default_mount.hide_name);
+        assert!(default_mount.name_override.is_none());
+
+        let mut mount = default_mount;
+        mount.name_override = Some("Sir Speedy".to_string());
+        mount.hide_name = false;
+        let pos = Pos {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        };
+        let packets = spawn_mount_npc(1, &mount, pos, pos).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert!(packets[0]
+            .windows("Sir Speedy".len())
+            .any(|window| window == "Sir Speedy".as_bytes()));
+    }
+
+    #[test]
+    fn test_mount_seat_offset_raises_rider() {
+        let mut game_server = GameServer::new(
+            Path::new("config"),
+            Box::new(SinglePlayerAuthenticator),
+            Box::new(MemoryCharacterStore::default()),
+        )
+        .unwrap();
+        game_server.mounts.get_mut(&1).unwrap().seat_offset_y = 0.75;
+        game_server
+            .lock_enforcer()
+            .write_characters(|characters_table_write_handle, _| {
+                let mut character = make_test_player(1, game_server.mounts())
+                    .data
+                    .to_character(24);
+                character.pos = Pos {
+                    x: 1.0,
+                    y: 2.0,
+                    z: 3.0,
+                    w: 1.0,
+                };
+                character.rot = Pos {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 1.0,
+                    w: 0.0,
+                };
+                characters_table_write_handle.insert(character);
+            });
+
+        let broadcasts = game_server
+            .process_packet(
+                1,
+                GamePacket::serialize(&MountSpawn { mount_id: 1 }).unwrap(),
+            )
+            .unwrap();
+        // The mount stays where it spawned while the rider is raised onto its seat
+        let expected_position = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: Position {
+                player_pos: Pos {
+                    x: 1.0,
+                    y: 2.75,
+                    z: 3.0,
+                    w: 1.0,
+                },
+                rot: Pos {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 1.0,
+                    w: 0.0,
+                },
+                is_teleport: true,
+                unknown2: true,
+            },
+        })
+        .unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)] if packets.contains(&expected_position)));
+    }
+
+    #[test]
+    fn test_dismount_resets_wield_type() {
+        let game_server = GameServer::new(
+            Path::new("config"),
+            Box::new(SinglePlayerAuthenticator),
+            Box::new(MemoryCharacterStore::default()),
+        )
+        .unwrap();
+        game_server
+            .lock_enforcer()
+            .write_characters(|characters_table_write_handle, _| {
+                characters_table_write_handle.insert(
+                    make_test_player(1, game_server.mounts())
+                        .data
+                        .to_character(24),
+                );
+            });
+        game_server
+            .process_packet(
+                1,
+                GamePacket::serialize(&MountSpawn { mount_id: 1 }).unwrap(),
+            )
+            .unwrap();
+
+        let mut dismount_request = Vec::new();
+        MountOpCode::DismountRequest
+            .serialize(&mut dismount_request)
+            .unwrap();
+        let broadcasts = game_server.process_packet(1, dismount_request).unwrap();
+
+        // The default mount only changes speed, so the other stats don't need to be resent
+        let expected_stats = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: Stats {
+                stats: vec![Stat {
+                    id: StatId::Speed,
+                    multiplier: 1,
+                    value1: 0.0,
+                    value2: 1.0,
+                }],
+            },
+        })
+        .unwrap();
+        let expected_reset = wield_type_reset(player_guid(1)).unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)]
+                if packets.contains(&expected_stats) && packets.contains(&expected_reset)));
+    }
+
+    #[test]
+    fn test_dismount_clears_rider_effect() {
+        let mut game_server = GameServer::new(
+            Path::new("config"),
+            Box::new(SinglePlayerAuthenticator),
+            Box::new(MemoryCharacterStore::default()),
+        )
+        .unwrap();
+        game_server
+            .mounts
+            .get_mut(&1)
+            .unwrap()
+            .rider_composite_effect = Some(1234);
+        game_server
+            .lock_enforcer()
+            .write_characters(|characters_table_write_handle, _| {
+                characters_table_write_handle.insert(
+                    make_test_player(1, game_server.mounts())
+                        .data
+                        .to_character(24),
+                );
+            });
+
+        let broadcasts = game_server
+            .process_packet(
+                1,
+                GamePacket::serialize(&MountSpawn { mount_id: 1 }).unwrap(),
+            )
+            .unwrap();
+        let expected_effect = rider_effect(player_guid(1), 1234).unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)] if packets.contains(&expected_effect)));
+
+        let mut dismount_request = Vec::new();
+        MountOpCode::DismountRequest
+            .serialize(&mut dismount_request)
+            .unwrap();
+        let broadcasts = game_server.process_packet(1, dismount_request).unwrap();
+
+        let expected_remove = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: RemoveGracefully {
+                guid: MountGuid::from_rider(player_guid(1)).unwrap().into(),
+                unknown1: false,
+                unknown2: 0,
+                unknown3: 0,
+                unknown4: 0,
+                timer: 1000,
+            },
+        })
+        .unwrap();
+        let expected_clear = rider_effect(player_guid(1), 0).unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)]
+                if packets.contains(&expected_remove) && packets.contains(&expected_clear)));
+    }
+
+    #[test]
+    fn test_mount_and_equipment_combine_speed() {
+        let game_server = GameServer::new(
+            Path::new("config"),
+            Box::new(SinglePlayerAuthenticator),
+            Box::new(MemoryCharacterStore::default()),
+        )
+        .unwrap();
+        game_server
+            .lock_enforcer()
+            .write_characters(|characters_table_write_handle, _| {
+                let mut character = make_test_player(1, game_server.mounts())
+                    .data
+                    .to_character(24);
+                character.stats.set(StatId::Speed, StatSource::Zone, 2.0);
+                character
+                    .stats
+                    .set(StatId::Speed, StatSource::Equipment(10), 1.2);
+                characters_table_write_handle.insert(character);
+            });
+
+        let broadcasts = game_server
+            .process_packet(
+                1,
+                GamePacket::serialize(&MountSpawn { mount_id: 1 }).unwrap(),
+            )
+            .unwrap();
+
+        let mount = game_server.mounts().get(&1).unwrap();
+        let expected_stats = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: Stats {
+                stats: vec![
+                    Stat {
+                        id: StatId::Speed,
+                        multiplier: 1,
+                        value1: 0.0,
+                        value2: 2.0 * mount.speed_multiplier * 1.2,
+                    },
+                    Stat {
+                        id: StatId::JumpHeightMultiplier,
+                        multiplier: 1,
+                        value1: 0.0,
+                        value2: mount.jump_height_multiplier,
+                    },
+                    Stat {
+                        id: StatId::GravityMultiplier,
+                        multiplier: 1,
+                        value1: 0.0,
+                        value2: mount.gravity_multiplier,
+                    },
+                ],
+            },
+        })
+        .unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)]
+                if packets.iter().filter(|packet| **packet == expected_stats).count() == 1));
+    }
+}
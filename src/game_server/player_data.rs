@@ -1,23 +1,30 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::Write;
 
 use byteorder::{LittleEndian, WriteBytesExt};
 
 use packet_serialize::{LengthlessVec, SerializePacket, SerializePacketError};
 
+use crate::game_server::emote::NO_ANIMATION_SLOT;
 use crate::game_server::game_packet::{Effect, GamePacket, ImageId, OpCode, Pos, StringId};
 use crate::game_server::guid::Guid;
-use crate::game_server::item::{EquipmentSlot, Item, MarketData};
+use crate::game_server::item::{EquipmentSlot, Item, ItemDefinition, MarketData};
 use crate::game_server::mount::MountConfig;
 use crate::game_server::player_update_packet::{
     NameplateImage, NameplateImageId, Wield, WieldType,
 };
+use crate::game_server::stat::StatEngine;
 use crate::game_server::tunnel::TunneledPacket;
-use crate::game_server::unique_guid::{mount_guid, player_guid};
-use crate::game_server::zone::CharacterType;
+use crate::game_server::unique_guid::{player_guid, MountGuid};
+use crate::game_server::zone::{CharacterType, PlayerState, ZoneLoadState};
 
 use super::zone::Character;
 
+pub const PLAYER_MAX_HEALTH: u32 = 25000;
+pub const PLAYER_MAX_POWER: u32 = 300;
+pub const PLAYER_POWER_REGEN_PER_SECOND: u32 = 1;
+pub const PLAYER_HEALTH_REGEN_PER_SECOND: u32 = 100;
+
 #[derive(SerializePacket)]
 pub struct EquippedVehicle {}
 
@@ -495,7 +502,9 @@ pub fn make_test_player(guid: u32, mounts: &BTreeMap<u32, MountConfig>) -> Playe
             mount_id: mount.guid(),
             name_id: mount.name_id,
             icon_set_id: mount.icon_set_id,
-            guid: mount_guid(guid, mount.guid()),
+            guid: MountGuid::from_rider(player_guid(guid))
+                .expect("Player GUIDs always fit in mount GUIDs")
+                .into(),
             unknown5: false,
             unknown6: 0,
             unknown7: "".to_string(),
@@ -909,17 +918,101 @@ pub fn make_test_player(guid: u32, mounts: &BTreeMap<u32, MountConfig>) -> Playe
 }
 
 impl PlayerData {
+    // Fills existing stacks of the item before creating new ones. Returns the GUIDs of every
+    // stack that changed.
+    pub fn add_item(&mut self, definition: &ItemDefinition, mut quantity: u32) -> Vec<u32> {
+        let max_stack = definition.max_stack();
+        let mut changed_guids = Vec::new();
+
+        for inventory_item in self.inventory.iter_mut() {
+            if quantity == 0 {
+                break;
+            }
+
+            let item = &mut inventory_item.item;
+            if item.definition_id != definition.guid() || item.quantity >= max_stack {
+                continue;
+            }
+
+            let added = quantity.min(max_stack - item.quantity);
+            item.quantity += added;
+            quantity -= added;
+            changed_guids.push(item.guid);
+        }
+
+        let mut next_guid = self
+            .inventory
+            .iter()
+            .map(|inventory_item| inventory_item.item.guid)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        while quantity > 0 {
+            let stack_quantity = quantity.min(max_stack);
+            self.inventory.push(InventoryItem {
+                definition_id: definition.guid(),
+                item: Item {
+                    definition_id: definition.guid(),
+                    tint: 0,
+                    guid: next_guid,
+                    quantity: stack_quantity,
+                    num_consumed: 0,
+                    last_use_time: 0,
+                    market_data: MarketData::None,
+                    unknown2: false,
+                },
+            });
+            changed_guids.push(next_guid);
+            quantity -= stack_quantity;
+            next_guid += 1;
+        }
+
+        changed_guids
+    }
+
     pub fn to_character(&self, instance_guid: u64) -> Character {
         Character {
             guid: self.player_guid,
             pos: self.pos,
             rot: self.rot,
-            character_type: CharacterType::Player,
+            character_type: CharacterType::Player(PlayerState {
+                owned_mounts: self.mounts.iter().map(|mount| mount.mount_id).collect(),
+                visible_characters: BTreeSet::new(),
+                attachments: BTreeMap::new(),
+                inventory: self
+                    .inventory
+                    .iter()
+                    .map(|inventory_item| (inventory_item.item.guid, inventory_item.item.clone()))
+                    .collect(),
+                currency: self.currency,
+                chat_tokens_used: 0,
+                chat_refill_elapsed_millis: 0,
+                tutorial_complete: false,
+            }),
             state: 0,
             mount_id: None,
             interact_radius: 0.0,
             auto_interact_radius: 0.0,
             instance_guid,
+            health: PLAYER_MAX_HEALTH,
+            max_health: PLAYER_MAX_HEALTH,
+            power: PLAYER_MAX_POWER,
+            max_power: PLAYER_MAX_POWER,
+            power_regen_per_second: PLAYER_POWER_REGEN_PER_SECOND,
+            power_regen_elapsed_millis: 0,
+            health_regen_per_second: PLAYER_HEALTH_REGEN_PER_SECOND,
+            health_regen_elapsed_millis: 0,
+            millis_since_damage: 0,
+            zone_load_state: ZoneLoadState::Loading,
+            in_combat: false,
+            stats: StatEngine::default(),
+            seek_target: None,
+            idle_millis: 0,
+            rail: None,
+            animation_slot: NO_ANIMATION_SLOT,
+            emote_millis_remaining: None,
+            despawned_millis: None,
+            interactable: true,
         }
     }
 }
@@ -942,3 +1035,45 @@ pub fn make_test_nameplate_image(guid: u32) -> Result<Vec<Vec<u8>>, SerializePac
         },
     })?])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_server::item::make_item_definitions;
+
+    #[test]
+    fn test_adding_past_max_stack_splits_into_new_stack() {
+        let mut player = make_test_player(1, &BTreeMap::new());
+        let definitions = make_item_definitions().data.definitions;
+        let definition = definitions
+            .iter()
+            .find(|definition| definition.guid() == 7)
+            .unwrap();
+        assert_eq!(definition.max_stack(), 1000);
+
+        assert_eq!(player.data.add_item(definition, 900), vec![7]);
+        assert_eq!(player.data.add_item(definition, 300), vec![7, 8]);
+
+        let stacks: Vec<(u32, u32)> = player
+            .data
+            .inventory
+            .iter()
+            .filter(|inventory_item| inventory_item.definition_id == 7)
+            .map(|inventory_item| (inventory_item.item.guid, inventory_item.item.quantity))
+            .collect();
+        assert_eq!(stacks, vec![(7, 1000), (8, 200)]);
+    }
+
+    #[test]
+    fn test_unstackable_items_get_separate_stacks() {
+        let mut player = make_test_player(1, &BTreeMap::new());
+        let definitions = make_item_definitions().data.definitions;
+        let definition = definitions
+            .iter()
+            .find(|definition| definition.guid() == 8)
+            .unwrap();
+        assert_eq!(definition.max_stack(), 1);
+
+        assert_eq!(player.data.add_item(definition, 2), vec![7, 8]);
+    }
+}
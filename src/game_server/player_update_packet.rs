@@ -1,8 +1,11 @@
 use byteorder::{LittleEndian, WriteBytesExt};
 
 use packet_serialize::{DeserializePacket, SerializePacket, SerializePacketError};
+use serde::Deserialize;
 
+use crate::game_server::client_update_packet::EquipItem;
 use crate::game_server::game_packet::{Effect, GamePacket, OpCode, Pos, StringId};
+use crate::game_server::tunnel::TunneledPacket;
 
 #[derive(Copy, Clone, Debug)]
 pub enum PlayerUpdateOpCode {
@@ -10,6 +13,7 @@ pub enum PlayerUpdateOpCode {
     AddNpc = 0x2,
     Remove = 0x3,
     Knockback = 0x4,
+    SetAnimation = 0x5,
     UpdatePower = 0x9,
     AddNotifications = 0xa,
     NpcRelevance = 0xc,
@@ -184,16 +188,27 @@ impl GamePacket for LootEvent {
     const HEADER: Self::Header = PlayerUpdateOpCode::LootEvent;
 }
 
+#[derive(SerializePacket, DeserializePacket)]
+pub struct SetAnimation {
+    pub guid: u64,
+    pub animation_slot: i32,
+}
+
+impl GamePacket for SetAnimation {
+    type Header = PlayerUpdateOpCode;
+    const HEADER: Self::Header = PlayerUpdateOpCode::SetAnimation;
+}
+
 #[derive(SerializePacket, DeserializePacket)]
 pub struct HudMessage {
-    unknown1: u64,
-    unknown2: u64,
-    name_id: u32,
-    image_id: u32,
-    message_id: u32,
-    unknown3: u32,
-    unknown4: u32,
-    unknown5: u32,
+    pub unknown1: u64,
+    pub unknown2: u64,
+    pub name_id: u32,
+    pub image_id: u32,
+    pub message_id: StringId,
+    pub unknown3: u32,
+    pub unknown4: u32,
+    pub unknown5: u32,
 }
 
 impl GamePacket for HudMessage {
@@ -201,11 +216,39 @@ impl GamePacket for HudMessage {
     const HEADER: Self::Header = PlayerUpdateOpCode::HudMessage;
 }
 
+// Neutral wield type so the client doesn't keep the mount's wield animation after dismounting.
+// Weapons can't be unequipped yet, so disarming doesn't send this.
+pub fn wield_type_reset(guid: u64) -> Result<Vec<u8>, SerializePacketError> {
+    GamePacket::serialize(&TunneledPacket {
+        unknown1: true,
+        inner: WieldType {
+            guid,
+            wield_type: Wield::Misc,
+        },
+    })
+}
+
+pub fn hud_message(message_id: StringId) -> Result<Vec<u8>, SerializePacketError> {
+    GamePacket::serialize(&TunneledPacket {
+        unknown1: true,
+        inner: HudMessage {
+            unknown1: 0,
+            unknown2: 0,
+            name_id: 0,
+            image_id: 0,
+            message_id,
+            unknown3: 0,
+            unknown4: 0,
+            unknown5: 0,
+        },
+    })
+}
+
 #[derive(SerializePacket, DeserializePacket)]
 pub struct SlotCompositeEffectOverride {
-    guid: u64,
-    slot_id: u32,
-    composite_effect: u32,
+    pub guid: u64,
+    pub slot_id: u32,
+    pub composite_effect: u32,
 }
 
 impl GamePacket for SlotCompositeEffectOverride {
@@ -264,7 +307,7 @@ impl GamePacket for MoveOnRelativeRail {
 
 #[derive(SerializePacket, DeserializePacket)]
 pub struct ClearRail {
-    guid: u64,
+    pub guid: u64,
 }
 
 impl GamePacket for ClearRail {
@@ -274,10 +317,10 @@ impl GamePacket for ClearRail {
 
 #[derive(SerializePacket, DeserializePacket)]
 pub struct MoveOnRail {
-    guid: u64,
-    unknown1: u32,
-    unknown2: u32,
-    pos: Pos,
+    pub guid: u64,
+    pub unknown1: u32,
+    pub unknown2: u32,
+    pub pos: Pos,
 }
 
 impl GamePacket for MoveOnRail {
@@ -287,8 +330,8 @@ impl GamePacket for MoveOnRail {
 
 #[derive(SerializePacket, DeserializePacket)]
 pub struct SeekTargetUpdate {
-    guid: u64,
-    target_id: u64,
+    pub guid: u64,
+    pub target_id: u64,
 }
 
 impl GamePacket for SeekTargetUpdate {
@@ -298,14 +341,14 @@ impl GamePacket for SeekTargetUpdate {
 
 #[derive(SerializePacket, DeserializePacket)]
 pub struct SeekTarget {
-    guid: u64,
-    target_id: u64,
-    init_speed: f32,
-    acceleration: f32,
-    speed: f32,
-    unknown1: f32,
-    rot_y: f32,
-    rot: Pos,
+    pub guid: u64,
+    pub target_id: u64,
+    pub init_speed: f32,
+    pub acceleration: f32,
+    pub speed: f32,
+    pub unknown1: f32,
+    pub rot_y: f32,
+    pub rot: Pos,
 }
 
 impl GamePacket for SeekTarget {
@@ -378,7 +421,7 @@ impl GamePacket for WieldType {
 
 #[derive(SerializePacket, DeserializePacket)]
 pub struct Freeze {
-    freeze: bool,
+    pub freeze: bool,
 }
 
 impl GamePacket for Freeze {
@@ -477,14 +520,28 @@ impl GamePacket for NpcRelevance {
     const HEADER: Self::Header = PlayerUpdateOpCode::NpcRelevance;
 }
 
-#[derive(SerializePacket, DeserializePacket)]
+// The slot determines which bone the model is attached to, using the same slot IDs as equipment
+#[derive(Clone, SerializePacket, DeserializePacket, Deserialize)]
 pub struct Attachment {
-    pub unknown1: String,
-    pub unknown2: String,
-    pub unknown3: String,
-    pub unknown4: u32,
-    pub unknown5: u32,
-    pub unknown6: u32,
+    pub model_name: String,
+    pub texture_alias: String,
+    pub tint_alias: String,
+    pub tint_id: u32,
+    pub composite_effect: u32,
+    pub slot: u32,
+}
+
+impl From<&EquipItem> for Attachment {
+    fn from(value: &EquipItem) -> Self {
+        Attachment {
+            model_name: value.model_name.clone(),
+            texture_alias: value.texture_alias.clone(),
+            tint_alias: value.tint_alias.clone(),
+            tint_id: value.tint,
+            composite_effect: value.composite_effect,
+            slot: value.slot as u32,
+        }
+    }
 }
 
 #[derive(SerializePacket, DeserializePacket)]
@@ -503,8 +560,9 @@ pub struct Variable {
     pub unknown3: u32,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq)]
 pub enum Icon {
+    #[default]
     None = 0,
     Member = 1,
     Enforcer = 2,
@@ -0,0 +1,73 @@
+use crate::game_server::game_packet::Pos;
+
+// The w component is not part of the position, so these functions ignore it
+
+pub fn distance_squared(a: &Pos, b: &Pos) -> f32 {
+    let diff_x = b.x - a.x;
+    let diff_y = b.y - a.y;
+    let diff_z = b.z - a.z;
+    diff_x * diff_x + diff_y * diff_y + diff_z * diff_z
+}
+
+pub fn distance(a: &Pos, b: &Pos) -> f32 {
+    distance_squared(a, b).sqrt()
+}
+
+// Returns a zero vector when the positions are the same because there is no direction
+pub fn normalize_direction(from: &Pos, to: &Pos) -> Pos {
+    let length = distance(from, to);
+    if length == 0.0 {
+        return Pos {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 0.0,
+        };
+    }
+
+    Pos {
+        x: (to.x - from.x) / length,
+        y: (to.y - from.y) / length,
+        z: (to.z - from.z) / length,
+        w: 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(x: f32, y: f32, z: f32, w: f32) -> Pos {
+        Pos { x, y, z, w }
+    }
+
+    #[test]
+    fn test_zero_distance() {
+        let a = pos(1.0, 2.0, 3.0, 1.0);
+        let b = pos(1.0, 2.0, 3.0, 5.0);
+        assert_eq!(distance_squared(&a, &b), 0.0);
+        assert_eq!(distance(&a, &b), 0.0);
+        assert_eq!(normalize_direction(&a, &b), pos(0.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_axis_aligned_distance() {
+        let a = pos(0.0, 0.0, 0.0, 0.0);
+        let b = pos(0.0, -4.0, 0.0, 1.0);
+        assert_eq!(distance_squared(&a, &b), 16.0);
+        assert_eq!(distance(&a, &b), 4.0);
+        assert_eq!(normalize_direction(&a, &b), pos(0.0, -1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_diagonal_distance() {
+        let a = pos(1.0, 1.0, 1.0, 0.0);
+        let b = pos(3.0, 4.0, 7.0, 0.0);
+        assert_eq!(distance_squared(&a, &b), 49.0);
+        assert_eq!(distance(&a, &b), 7.0);
+        assert_eq!(
+            normalize_direction(&a, &b),
+            pos(2.0 / 7.0, 3.0 / 7.0, 6.0 / 7.0, 0.0)
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use serde::Deserialize;
+
+use packet_serialize::SerializePacketError;
+
+use crate::game_server::game_packet::{GamePacket, Pos};
+use crate::game_server::player_update_packet::{ClearRail, MoveOnRail};
+use crate::game_server::pos::distance;
+use crate::game_server::tunnel::TunneledPacket;
+
+#[derive(Clone, Deserialize)]
+pub struct RailConfig {
+    pub waypoints: Vec<Pos>,
+    pub speed: f32,
+    #[serde(rename = "loop", default)]
+    pub loop_rail: bool,
+}
+
+#[derive(Clone)]
+pub struct Rail {
+    config: RailConfig,
+    next_waypoint: usize,
+    elapsed_millis: u128,
+}
+
+impl From<RailConfig> for Rail {
+    fn from(config: RailConfig) -> Self {
+        Rail {
+            config,
+            next_waypoint: 0,
+            elapsed_millis: 0,
+        }
+    }
+}
+
+impl Rail {
+    pub fn move_packet(&self, guid: u64) -> Result<Option<Vec<u8>>, SerializePacketError> {
+        self.config
+            .waypoints
+            .get(self.next_waypoint)
+            .map(|waypoint| {
+                GamePacket::serialize(&TunneledPacket {
+                    unknown1: true,
+                    inner: MoveOnRail {
+                        guid,
+                        unknown1: 0,
+                        unknown2: 0,
+                        pos: *waypoint,
+                    },
+                })
+            })
+            .transpose()
+    }
+
+    // Moves the character toward its next waypoint. Returns the packets for every waypoint
+    // reached and whether the character reached the end of a rail that doesn't loop.
+    pub fn advance(
+        &mut self,
+        guid: u64,
+        pos: &mut Pos,
+        elapsed_millis: u128,
+    ) -> Result<(Vec<Vec<u8>>, bool), SerializePacketError> {
+        if self.next_waypoint >= self.config.waypoints.len() {
+            return Ok((Vec::new(), true));
+        }
+        self.elapsed_millis += elapsed_millis;
+
+        let mut packets = Vec::new();
+
+        // Waypoints at the same position take no time to reach, so limit how many can be
+        // reached at once to keep a looping rail from spinning forever
+        for _ in 0..self.config.waypoints.len() {
+            let waypoint = self.config.waypoints[self.next_waypoint];
+            let required_millis = distance(pos, &waypoint) / self.config.speed * 1000.0;
+            if (self.elapsed_millis as f32) < required_millis {
+                break;
+            }
+
+            *pos = waypoint;
+            self.elapsed_millis -= (required_millis as u128).min(self.elapsed_millis);
+            self.next_waypoint += 1;
+
+            if self.next_waypoint == self.config.waypoints.len() {
+                if !self.config.loop_rail {
+                    packets.push(GamePacket::serialize(&TunneledPacket {
+                        unknown1: true,
+                        inner: ClearRail { guid },
+                    })?);
+                    return Ok((packets, true));
+                }
+
+                self.next_waypoint = 0;
+            }
+
+            if let Some(packet) = self.move_packet(guid)? {
+                packets.push(packet);
+            }
+        }
+
+        Ok((packets, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(x: f32) -> Pos {
+        Pos {
+            x,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        }
+    }
+
+    fn clear_rail_packet(guid: u64) -> Vec<u8> {
+        GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: ClearRail { guid },
+        })
+        .unwrap()
+    }
+
+    fn traverse(loop_rail: bool) -> (Vec<Vec<u8>>, bool) {
+        let mut rail = Rail::from(RailConfig {
+            waypoints: vec![pos(10.0), pos(20.0)],
+            speed: 10.0,
+            loop_rail,
+        });
+        let mut current_pos = pos(0.0);
+
+        let mut all_packets = Vec::new();
+        let mut finished = false;
+        for _ in 0..4 {
+            let (mut packets, tick_finished) = rail.advance(1, &mut current_pos, 1000).unwrap();
+            all_packets.append(&mut packets);
+            finished |= tick_finished;
+        }
+
+        (all_packets, finished)
+    }
+
+    #[test]
+    fn test_rail_clears_at_end() {
+        let (packets, finished) = traverse(false);
+        assert!(finished);
+        assert_eq!(packets.last(), Some(&clear_rail_packet(1)));
+    }
+
+    #[test]
+    fn test_looping_rail_does_not_clear() {
+        let (packets, finished) = traverse(true);
+        assert!(!finished);
+        assert!(!packets.contains(&clear_rail_packet(1)));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::game_server::game_packet::GamePacket;
+use crate::game_server::player_update_packet::RemoveGracefully;
+use crate::game_server::tunnel::TunneledPacket;
+use crate::game_server::zone::{Character, CharacterType};
+use crate::game_server::ProcessPacketError;
+
+impl Character {
+    // Dead NPCs despawn, then return at full health once their respawn time has passed
+    pub fn advance_respawn(
+        &mut self,
+        elapsed_millis: u128,
+    ) -> Result<Vec<Vec<u8>>, ProcessPacketError> {
+        let CharacterType::Npc(npc) = &self.character_type else {
+            return Ok(Vec::new());
+        };
+        if !self.is_dead() {
+            return Ok(Vec::new());
+        }
+        let respawn_seconds = npc.respawn_seconds;
+
+        let Some(despawned_millis) = self.despawned_millis else {
+            self.despawned_millis = Some(0);
+            return Ok(vec![GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: RemoveGracefully {
+                    guid: self.guid,
+                    unknown1: false,
+                    unknown2: 0,
+                    unknown3: 0,
+                    unknown4: 0,
+                    timer: 1000,
+                },
+            })?]);
+        };
+
+        let Some(respawn_seconds) = respawn_seconds else {
+            return Ok(Vec::new());
+        };
+
+        let despawned_millis = despawned_millis.saturating_add(elapsed_millis);
+        if despawned_millis < respawn_seconds as u128 * 1000 {
+            self.despawned_millis = Some(despawned_millis);
+            return Ok(Vec::new());
+        }
+
+        self.despawned_millis = None;
+        self.health = self.max_health;
+        Ok(self.to_packets()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_server::guid::GuidTable;
+    use crate::game_server::lock_enforcer::{CharacterLockRequest, CharacterTableReadHandle};
+    use crate::game_server::unique_guid::{npc_guid, AMBIENT_NPC_DISCRIMINANT};
+    use crate::game_server::zone::{
+        make_test_character, make_test_npc_data, make_test_players, make_test_zone, move_to,
+        PlayerState, Zone,
+    };
+    use crate::game_server::{Broadcast, GameServer};
+
+    #[test]
+    fn test_killed_npc_respawns_after_timer() {
+        let characters = make_test_players(&[]);
+        let characters_table_read_handle = CharacterTableReadHandle::from(characters.read());
+        let mut npc = make_test_npc_data();
+        npc.respawn_seconds = Some(5);
+        let mut character = make_test_character(1, CharacterType::Npc(npc));
+        let spawn_packets = character.to_packets().unwrap();
+
+        character
+            .apply_damage(1000, &characters_table_read_handle)
+            .unwrap();
+        let despawn_packet = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: RemoveGracefully {
+                guid: 1,
+                unknown1: false,
+                unknown2: 0,
+                unknown3: 0,
+                unknown4: 0,
+                timer: 1000,
+            },
+        })
+        .unwrap();
+        assert_eq!(character.advance_respawn(0).unwrap(), vec![despawn_packet]);
+        assert!(character.to_packets().unwrap().is_empty());
+
+        assert!(character.advance_respawn(4999).unwrap().is_empty());
+        assert!(character.is_dead());
+
+        assert_eq!(character.advance_respawn(1).unwrap(), spawn_packets);
+        assert_eq!(character.health, 100);
+        assert!(character.advance_respawn(60000).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_killed_npc_without_respawn_time_stays_despawned() {
+        let characters = make_test_players(&[]);
+        let characters_table_read_handle = CharacterTableReadHandle::from(characters.read());
+        let mut character = make_test_character(1, CharacterType::Npc(make_test_npc_data()));
+
+        character
+            .apply_damage(1000, &characters_table_read_handle)
+            .unwrap();
+        assert_eq!(character.advance_respawn(0).unwrap().len(), 1);
+        assert!(character
+            .advance_respawn(u32::MAX as u128)
+            .unwrap()
+            .is_empty());
+        assert!(character.is_dead());
+        assert!(character.to_packets().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_respawned_npc_is_sent_once() {
+        let npc_guid = npc_guid(AMBIENT_NPC_DISCRIMINANT, 24, 0);
+        let characters = GuidTable::new();
+        let zones = GuidTable::new();
+        let npc_packets;
+        {
+            let mut characters_write_handle = characters.write();
+            characters_write_handle.insert(make_test_character(
+                1,
+                CharacterType::Player(PlayerState::default()),
+            ));
+            let mut npc_data = make_test_npc_data();
+            npc_data.respawn_seconds = Some(5);
+            let npc = make_test_character(npc_guid, CharacterType::Npc(npc_data));
+            npc_packets = npc.to_packets().unwrap();
+            characters_write_handle.insert(npc);
+            zones
+                .write()
+                .insert(make_test_zone(24, &mut characters_write_handle));
+        }
+        let game_server = GameServer::for_test(characters, zones);
+
+        let broadcasts = Zone::move_character(move_to(2.0), &game_server).unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)] if packets == &npc_packets));
+
+        game_server
+            .lock_enforcer()
+            .read_characters(|_| CharacterLockRequest {
+                read_guids: Vec::new(),
+                write_guids: vec![npc_guid],
+                character_consumer: |characters_table_read_handle, _, mut characters_write, _| {
+                    characters_write
+                        .get_mut(&npc_guid)
+                        .unwrap()
+                        .apply_damage(1000, characters_table_read_handle)
+                        .unwrap();
+                },
+            });
+        let remove_packet = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: RemoveGracefully {
+                guid: npc_guid,
+                unknown1: false,
+                unknown2: 0,
+                unknown3: 0,
+                unknown4: 0,
+                timer: 1000,
+            },
+        })
+        .unwrap();
+        let broadcasts = game_server.tick(0).unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Multi(players, packets)] if players == &vec![1] && packets == &vec![remove_packet]));
+
+        // The dead NPC is not sent or recorded as visible while it is despawned
+        assert!(Zone::move_character(move_to(2.0), &game_server)
+            .unwrap()
+            .is_empty());
+
+        let broadcasts = game_server.tick(5000).unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Multi(players, packets)] if players == &vec![1] && packets == &npc_packets));
+        assert!(Zone::move_character(move_to(2.0), &game_server)
+            .unwrap()
+            .is_empty());
+    }
+}
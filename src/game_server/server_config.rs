@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Error;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::game_server::game_packet::StringId;
+use crate::game_server::login::GameSettings;
+use crate::protocol::StaleGapPolicy;
+
+const DEFAULT_ENVIRONMENT: &str = "prod";
+
+#[derive(Default, Deserialize)]
+pub struct ServerConfig {
+    #[serde(default)]
+    pub motd: Option<StringId>,
+    #[serde(default)]
+    pub idle_dismount_secs: Option<u64>,
+    #[serde(default)]
+    pub health_regen_delay_secs: u64,
+    #[serde(default)]
+    pub chat_rate_limit: Option<ChatRateLimit>,
+    #[serde(default)]
+    pub emote_duration_secs: Option<u64>,
+    #[serde(default)]
+    pub environment: Option<String>,
+    #[serde(default)]
+    pub game_settings: BTreeMap<String, GameSettings>,
+    #[serde(default)]
+    pub server_info: ServerInfo,
+    // Consecutive unknown or unimplemented op codes a client may send before it is disconnected
+    #[serde(default)]
+    pub max_unknown_op_codes: Option<u32>,
+    #[serde(default)]
+    pub stale_gap: Option<StaleGapConfig>,
+    // Channels start from the client's first data sequence number instead of assuming 0
+    #[serde(default)]
+    pub adopt_first_client_sequence: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct ServerInfo {
+    pub name: String,
+    pub version: String,
+}
+
+impl Default for ServerInfo {
+    fn default() -> Self {
+        ServerInfo {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn environment(&self) -> &str {
+        self.environment.as_deref().unwrap_or(DEFAULT_ENVIRONMENT)
+    }
+
+    // Environments without their own settings use the client's usual defaults
+    pub fn game_settings(&self) -> GameSettings {
+        self.game_settings
+            .get(self.environment())
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+// Token bucket that allows a burst of messages, then one more message per refill interval
+#[derive(Copy, Clone, Deserialize)]
+pub struct ChatRateLimit {
+    pub burst: u32,
+    pub refill_millis: u64,
+    pub throttle_message: StringId,
+}
+
+// How long packets may wait behind a missing reliable packet before the gap is given up on
+#[derive(Copy, Clone, Deserialize)]
+pub struct StaleGapConfig {
+    pub max_age_millis: u64,
+    pub policy: StaleGapPolicy,
+}
+
+pub fn load_server_config(config_dir: &Path) -> Result<ServerConfig, Error> {
+    let mut file = File::open(config_dir.join("server.json"))?;
+    Ok(serde_json::from_reader(&mut file)?)
+}
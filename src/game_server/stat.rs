@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+
+use crate::game_server::client_update_packet::{Stat, StatId, Stats};
+
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum StatSource {
+    Zone,
+    Mount,
+    Equipment(u32),
+    Buff(u32),
+}
+
+// The effective value of a stat is the product of every source's multiplier
+#[derive(Clone, Default)]
+struct StatLayers {
+    multipliers: BTreeMap<StatSource, f32>,
+}
+
+impl StatLayers {
+    fn set(&mut self, source: StatSource, multiplier: f32) -> bool {
+        self.multipliers.insert(source, multiplier) != Some(multiplier)
+    }
+
+    fn remove(&mut self, source: StatSource) -> bool {
+        self.multipliers.remove(&source).is_some()
+    }
+
+    fn value(&self) -> f32 {
+        self.multipliers.values().product()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct StatEngine {
+    layers: BTreeMap<StatId, StatLayers>,
+    // Values the client already has, so updates only need to include stats that changed
+    last_sent: BTreeMap<StatId, f32>,
+}
+
+impl StatEngine {
+    pub fn set(&mut self, stat: StatId, source: StatSource, multiplier: f32) -> bool {
+        self.layers.entry(stat).or_default().set(source, multiplier)
+    }
+
+    pub fn remove(&mut self, stat: StatId, source: StatSource) -> bool {
+        self.layers
+            .get_mut(&stat)
+            .map(|layers| layers.remove(source))
+            .unwrap_or(false)
+    }
+
+    // Returns the stats that the source contributed to
+    pub fn remove_source(&mut self, source: StatSource) -> Vec<StatId> {
+        self.layers
+            .iter_mut()
+            .filter_map(|(stat, layers)| layers.remove(source).then_some(*stat))
+            .collect()
+    }
+
+    pub fn value(&self, stat: StatId) -> f32 {
+        self.layers
+            .get(&stat)
+            .map(|layers| layers.value())
+            .unwrap_or(1.0)
+    }
+
+    pub fn stat(&self, stat: StatId) -> Stat {
+        Stat {
+            id: stat,
+            multiplier: 1,
+            value1: 0.0,
+            value2: self.value(stat),
+        }
+    }
+
+    pub fn stats(&self, stats: &[StatId]) -> Stats {
+        Stats {
+            stats: stats.iter().map(|stat| self.stat(*stat)).collect(),
+        }
+    }
+
+    // Use when the client has lost its previous values, such as after loading a zone
+    pub fn full_update(&mut self, stats: &[StatId]) -> Stats {
+        for stat in stats {
+            self.last_sent.insert(*stat, self.value(*stat));
+        }
+
+        self.stats(stats)
+    }
+
+    pub fn changed_update(&mut self, stats: &[StatId]) -> Option<Stats> {
+        let changed_stats: Vec<StatId> = stats
+            .iter()
+            .copied()
+            .filter(|stat| self.last_sent.get(stat) != Some(&self.value(*stat)))
+            .collect();
+
+        if changed_stats.is_empty() {
+            None
+        } else {
+            Some(self.full_update(&changed_stats))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_is_product_of_sources() {
+        let mut stats = StatEngine::default();
+        assert_eq!(stats.value(StatId::Speed), 1.0);
+
+        assert!(stats.set(StatId::Speed, StatSource::Zone, 1.5));
+        assert!(stats.set(StatId::Speed, StatSource::Mount, 2.0));
+        assert!(!stats.set(StatId::Speed, StatSource::Mount, 2.0));
+        assert!(stats.set(StatId::JumpHeightMultiplier, StatSource::Mount, 4.0));
+        assert_eq!(stats.value(StatId::Speed), 3.0);
+
+        assert!(stats.remove(StatId::Speed, StatSource::Zone));
+        assert!(!stats.remove(StatId::Speed, StatSource::Zone));
+        assert_eq!(stats.value(StatId::Speed), 2.0);
+
+        assert_eq!(
+            stats.remove_source(StatSource::Mount),
+            vec![StatId::Speed, StatId::JumpHeightMultiplier]
+        );
+        assert_eq!(stats.value(StatId::Speed), 1.0);
+        assert_eq!(stats.value(StatId::JumpHeightMultiplier), 1.0);
+    }
+
+    #[test]
+    fn test_changed_update_only_includes_changed_stats() {
+        let mut stats = StatEngine::default();
+        stats.set(StatId::Speed, StatSource::Zone, 1.5);
+        let all_stats = [StatId::Speed, StatId::JumpHeightMultiplier];
+        assert_eq!(stats.full_update(&all_stats).stats.len(), 2);
+
+        // Re-applying identical values leaves nothing to send
+        assert!(!stats.set(StatId::Speed, StatSource::Zone, 1.5));
+        assert!(stats.changed_update(&all_stats).is_none());
+
+        stats.set(StatId::JumpHeightMultiplier, StatSource::Mount, 2.0);
+        let update = stats.changed_update(&all_stats).unwrap();
+        assert_eq!(update.stats.len(), 1);
+        assert_eq!(update.stats[0].id, StatId::JumpHeightMultiplier);
+        assert_eq!(update.stats[0].value2, 2.0);
+        assert!(stats.changed_update(&all_stats).is_none());
+
+        // A change that cancels out before it was sent doesn't need to be sent either
+        stats.set(StatId::Speed, StatSource::Mount, 2.0);
+        stats.remove(StatId::Speed, StatSource::Mount);
+        assert!(stats.changed_update(&all_stats).is_none());
+    }
+}
@@ -10,7 +10,7 @@ use crate::game_server::ProcessPacketError;
 // Player characters, mounts, and pets are exceptions as they include no zone data in their GUID.
 // They always have the special character type discriminant 0x00, 0x01, or 0x2.
 
-pub fn zone_instance_guid(index: u32, template_guid: u8) -> u64 {
+pub const fn zone_instance_guid(index: u32, template_guid: u8) -> u64 {
     ((index as u64) << 8) | (template_guid as u64)
 }
 
@@ -28,11 +28,18 @@ pub fn shorten_zone_template_guid(point_of_interest_id: u32) -> Result<u8, Proce
 
 pub const AMBIENT_NPC_DISCRIMINANT: u8 = 0x10;
 pub const FIXTURE_DISCRIMINANT: u8 = 0x20;
+pub const TRANSIENT_NPC_DISCRIMINANT: u8 = 0x40;
 
 pub fn npc_guid(discriminant: u8, zone_guid: u64, index: u16) -> u64 {
     ((discriminant as u64) << 56) | (index as u64) << 40 | zone_guid
 }
 
+pub fn npc_discriminant(guid: u64) -> u8 {
+    (guid >> 56) as u8
+}
+
+// Players are addressed by a 32-bit ID in channels and broadcasts. Login rejects player GUIDs
+// that don't fit, so shortening a player's GUID only fails for GUIDs that aren't players'.
 pub fn player_guid(player_guid: u32) -> u64 {
     player_guid as u64
 }
@@ -45,6 +52,61 @@ pub fn shorten_player_guid(player_guid: u64) -> Result<u32, ProcessPacketError>
     }
 }
 
-pub fn mount_guid(rider: u32, mount_id: u32) -> u64 {
-    0x0100000000000000u64 | (mount_id as u64) << 32 | (rider as u64)
+// Mount GUIDs are structured as, from left to right:
+// * 1-byte mount discriminant
+// * 7-byte rider GUID
+//
+// A rider only has one mount spawned at a time, so the mount ID is stored on the rider rather
+// than in the GUID. This leaves room for the full rider GUID, which is why only characters
+// without a discriminant (players) can ride mounts.
+pub const MOUNT_DISCRIMINANT: u8 = 0x01;
+const RIDER_MASK: u64 = 0x00ffffffffffffff;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MountGuid(u64);
+
+impl MountGuid {
+    pub fn from_rider(character: u64) -> Result<Self, ProcessPacketError> {
+        if character & !RIDER_MASK != 0 {
+            Err(ProcessPacketError::CorruptedPacket)
+        } else {
+            Ok(MountGuid((MOUNT_DISCRIMINANT as u64) << 56 | character))
+        }
+    }
+
+    pub fn character(&self) -> u64 {
+        self.0 & RIDER_MASK
+    }
+}
+
+impl From<MountGuid> for u64 {
+    fn from(value: MountGuid) -> Self {
+        value.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mount_guid_round_trips() {
+        let guid = MountGuid::from_rider(player_guid(12345)).unwrap();
+        assert_eq!(u64::from(guid), 0x0100000000003039);
+        assert_eq!(guid.character(), player_guid(12345));
+    }
+
+    #[test]
+    fn test_mount_guid_keeps_rider_above_u32() {
+        let rider = 0x0000000100000002;
+        let other_rider = 0x0000000200000002;
+        let guid = MountGuid::from_rider(rider).unwrap();
+        assert_eq!(guid.character(), rider);
+        assert_ne!(guid, MountGuid::from_rider(other_rider).unwrap());
+    }
+
+    #[test]
+    fn test_mount_guid_rejects_npc_rider() {
+        assert!(MountGuid::from_rider(npc_guid(AMBIENT_NPC_DISCRIMINANT, 24, 0)).is_err());
+    }
 }
@@ -15,6 +15,17 @@ pub struct UpdatePlayerPosition {
     pub unknown: u8,
 }
 
+impl UpdatePlayerPosition {
+    // NaN and infinite coordinates would break every distance check that uses the position
+    pub fn is_finite(&self) -> bool {
+        [
+            self.pos_x, self.pos_y, self.pos_z, self.rot_x, self.rot_y, self.rot_z,
+        ]
+        .iter()
+        .all(|value| value.is_finite())
+    }
+}
+
 impl GamePacket for UpdatePlayerPosition {
     type Header = OpCode;
     const HEADER: Self::Header = OpCode::UpdatePlayerPosition;
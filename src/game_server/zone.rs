@@ -1,7 +1,8 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::File;
 use std::io::Error;
 use std::path::Path;
+use std::sync::atomic::{AtomicU16, Ordering};
 
 use parking_lot::RwLockReadGuard;
 use serde::Deserialize;
@@ -10,16 +11,25 @@ use packet_serialize::{DeserializePacket, SerializePacket, SerializePacketError}
 
 use strum::{EnumIter, IntoEnumIterator};
 
-use crate::game_server::client_update_packet::Position;
+use crate::game_server::chat::system_message;
+use crate::game_server::client_update_packet::{Health, Position, Power, StatId};
 use crate::game_server::command::SelectPlayer;
-use crate::game_server::game_packet::{GamePacket, OpCode, Pos};
-use crate::game_server::guid::{Guid, GuidTable, GuidTableWriteHandle, IndexedGuid};
+use crate::game_server::emote::NO_ANIMATION_SLOT;
+use crate::game_server::game_packet::{Effect, GamePacket, OpCode, Pos, StringId};
+use crate::game_server::guid::{
+    Guid, GuidTable, GuidTableHandle, GuidTableWriteHandle, IndexedGuid,
+};
 use crate::game_server::housing::{prepare_init_house_packets, BuildArea};
+use crate::game_server::item::Item;
 use crate::game_server::login::{ClientBeginZoning, ZoneDetails};
 use crate::game_server::player_update_packet::{
-    AddNotifications, AddNpc, BaseAttachmentGroup, Icon, NotificationData, NpcRelevance,
-    SingleNotification, SingleNpcRelevance, WeaponAnimation,
+    AddNotifications, AddNpc, Attachment, BaseAttachmentGroup, Freeze, Icon, NotificationData,
+    NpcRelevance, RemoveGracefully, SeekTarget, SeekTargetUpdate, SingleNotification,
+    SingleNpcRelevance, SlotCompositeEffectOverride, UpdateCharacterState, WeaponAnimation,
 };
+use crate::game_server::pos::distance;
+use crate::game_server::rail::{Rail, RailConfig};
+use crate::game_server::stat::{StatEngine, StatSource};
 use crate::game_server::tunnel::TunneledPacket;
 use crate::game_server::ui::ExecuteScriptWithParams;
 use crate::game_server::unique_guid::{npc_guid, player_guid, shorten_player_guid};
@@ -27,9 +37,12 @@ use crate::game_server::update_position::UpdatePlayerPosition;
 use crate::game_server::{Broadcast, GameServer, ProcessPacketError};
 
 use super::lock_enforcer::{
-    CharacterLockRequest, CharacterTableReadHandle, CharacterTableWriteHandle, ZoneLockRequest,
+    CharacterLockRequest, CharacterReadGuard, CharacterTableReadHandle, CharacterTableWriteHandle,
+    ZoneLockRequest,
+};
+use super::unique_guid::{
+    npc_discriminant, zone_instance_guid, AMBIENT_NPC_DISCRIMINANT, TRANSIENT_NPC_DISCRIMINANT,
 };
-use super::unique_guid::{zone_instance_guid, AMBIENT_NPC_DISCRIMINANT};
 
 #[derive(Clone, Deserialize)]
 pub struct Door {
@@ -48,6 +61,63 @@ pub struct Door {
     destination_rot_w: f32,
     destination_zone_template: Option<u8>,
     destination_zone: Option<u64>,
+    composite_effect: Option<u32>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    #[serde(default)]
+    return_door: bool,
+    #[serde(default)]
+    name_override: Option<String>,
+    #[serde(default)]
+    hide_name: bool,
+    // Played on the player when they leave through the door and when they arrive, respectively
+    #[serde(default)]
+    enter_composite_effect: Option<u32>,
+    #[serde(default)]
+    exit_composite_effect: Option<u32>,
+    // Teleports within the same zone fade through this effect instead of snapping
+    #[serde(default)]
+    fade_composite_effect: Option<u32>,
+    // The door that spawned this return door, which is never set from config
+    #[serde(skip)]
+    return_from: Option<u64>,
+}
+
+impl Door {
+    fn to_return_door(
+        &self,
+        source_door_guid: u64,
+        source_zone_guid: u64,
+        pos: Pos,
+        rot: Pos,
+    ) -> Door {
+        Door {
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+            w: pos.w,
+            terrain_object_id: 0,
+            destination_pos_x: self.x,
+            destination_pos_y: self.y,
+            destination_pos_z: self.z,
+            destination_pos_w: self.w,
+            destination_rot_x: rot.x,
+            destination_rot_y: rot.y,
+            destination_rot_z: rot.z,
+            destination_rot_w: rot.w,
+            destination_zone_template: None,
+            destination_zone: Some(source_zone_guid),
+            composite_effect: self.composite_effect,
+            attachments: self.attachments.clone(),
+            return_door: false,
+            name_override: self.name_override.clone(),
+            hide_name: self.hide_name,
+            enter_composite_effect: self.enter_composite_effect,
+            exit_composite_effect: self.exit_composite_effect,
+            fade_composite_effect: self.fade_composite_effect,
+            return_from: Some(source_door_guid),
+        }
+    }
 }
 
 #[derive(Clone, Deserialize)]
@@ -72,6 +142,88 @@ pub struct Transport {
     show_icon: bool,
     large_icon: bool,
     show_hover_description: bool,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    #[serde(default)]
+    rail: Option<RailConfig>,
+    #[serde(default)]
+    name_override: Option<String>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct NpcData {
+    model_id: u32,
+    #[serde(default)]
+    name_id: u32,
+    #[serde(default)]
+    name_override: Option<String>,
+    #[serde(default)]
+    hide_name: bool,
+    #[serde(default)]
+    texture: String,
+    scale: Option<f32>,
+    pos_x: f32,
+    pos_y: f32,
+    pos_z: f32,
+    pos_w: f32,
+    rot_x: f32,
+    rot_y: f32,
+    rot_z: f32,
+    rot_w: f32,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    // NPCs without a cursor can't be interacted with
+    #[serde(default)]
+    cursor: Option<u8>,
+    #[serde(default)]
+    interact_message: Option<String>,
+    #[serde(default)]
+    rail: Option<RailConfig>,
+    // NPCs that die without a respawn time stay despawned
+    #[serde(default)]
+    pub respawn_seconds: Option<u32>,
+    #[serde(default)]
+    fly_over_effect: u32,
+    #[serde(default)]
+    hover_glow: u32,
+    #[serde(default)]
+    hover_description: u32,
+    // Shown under the NPC's name, like a title
+    #[serde(default)]
+    sub_title_id: StringId,
+    #[serde(default)]
+    icon: Icon,
+    // Defaults to showing a health bar only for NPCs that can be damaged
+    #[serde(default)]
+    show_health: Option<bool>,
+}
+
+const MAX_FLY_OVER_EFFECT: u32 = 3;
+const MAX_HOVER_DESCRIPTION: u32 = 7;
+
+impl NpcData {
+    // The client only supports a few values for these fields
+    fn clamp_to_packet_limits(&mut self) {
+        self.fly_over_effect =
+            self.clamp_field("fly-over effect", self.fly_over_effect, MAX_FLY_OVER_EFFECT);
+        self.hover_description = self.clamp_field(
+            "hover description",
+            self.hover_description,
+            MAX_HOVER_DESCRIPTION,
+        );
+    }
+
+    fn clamp_field(&self, field_name: &str, value: u32, max: u32) -> u32 {
+        if value > max {
+            println!(
+                "Warning: NPC model {} has {} {}, which is above the maximum of {}",
+                self.model_id, field_name, value, max
+            );
+            max
+        } else {
+            value
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -99,13 +251,32 @@ struct ZoneConfig {
     interact_radius: f32,
     door_auto_interact_radius: f32,
     transports: Vec<Transport>,
+    #[serde(default)]
+    npcs: Vec<NpcData>,
+    #[serde(default)]
+    interest_radius: Option<f32>,
+    #[serde(default)]
+    interest_hysteresis: f32,
+}
+
+#[derive(Clone, Default)]
+pub struct PlayerState {
+    pub owned_mounts: Vec<u32>,
+    pub visible_characters: BTreeSet<u64>,
+    pub attachments: BTreeMap<u32, Attachment>,
+    pub inventory: BTreeMap<u32, Item>,
+    pub currency: u32,
+    pub chat_tokens_used: u32,
+    pub chat_refill_elapsed_millis: u128,
+    pub tutorial_complete: bool,
 }
 
 #[derive(Clone)]
 pub enum CharacterType {
     Door(Door),
     Transport(Transport),
-    Player,
+    Npc(NpcData),
+    Player(PlayerState),
 }
 
 #[derive(Copy, Clone, Eq, EnumIter, PartialOrd, PartialEq, Ord)]
@@ -126,6 +297,18 @@ pub struct NpcTemplate {
     pub mount_id: Option<u32>,
     pub interact_radius: f32,
     pub auto_interact_radius: f32,
+    pub rail: Option<RailConfig>,
+}
+
+// An NPC spawned at runtime rather than from the zone config, so its GUID is allocated on spawn
+#[derive(Clone)]
+pub struct TransientNpc {
+    pub pos: Pos,
+    pub rot: Pos,
+    pub state: u8,
+    pub character_type: CharacterType,
+    pub interact_radius: f32,
+    pub auto_interact_radius: f32,
 }
 
 impl NpcTemplate {
@@ -140,6 +323,25 @@ impl NpcTemplate {
             interact_radius: self.interact_radius,
             auto_interact_radius: self.auto_interact_radius,
             instance_guid,
+            health: 0,
+            max_health: 0,
+            power: 0,
+            max_power: 0,
+            power_regen_per_second: 0,
+            power_regen_elapsed_millis: 0,
+            health_regen_per_second: 0,
+            health_regen_elapsed_millis: 0,
+            millis_since_damage: 0,
+            zone_load_state: ZoneLoadState::Loaded,
+            in_combat: false,
+            stats: StatEngine::default(),
+            seek_target: None,
+            idle_millis: 0,
+            rail: self.rail.clone().map(Rail::from),
+            animation_slot: NO_ANIMATION_SLOT,
+            emote_millis_remaining: None,
+            despawned_millis: None,
+            interactable: true,
         }
     }
 }
@@ -155,6 +357,25 @@ pub struct Character {
     pub interact_radius: f32,
     pub auto_interact_radius: f32,
     pub instance_guid: u64,
+    pub health: u32,
+    pub max_health: u32,
+    pub power: u32,
+    pub max_power: u32,
+    pub power_regen_per_second: u32,
+    pub power_regen_elapsed_millis: u128,
+    pub health_regen_per_second: u32,
+    pub health_regen_elapsed_millis: u128,
+    pub millis_since_damage: u128,
+    pub zone_load_state: ZoneLoadState,
+    pub in_combat: bool,
+    pub stats: StatEngine,
+    pub seek_target: Option<u64>,
+    pub rail: Option<Rail>,
+    pub idle_millis: u128,
+    pub animation_slot: i32,
+    pub emote_millis_remaining: Option<u128>,
+    pub despawned_millis: Option<u128>,
+    pub interactable: bool,
 }
 
 impl IndexedGuid<u64, (u64, CharacterCategory)> for Character {
@@ -166,7 +387,7 @@ impl IndexedGuid<u64, (u64, CharacterCategory)> for Character {
         (
             self.instance_guid,
             match self.character_type {
-                CharacterType::Player => CharacterCategory::Player,
+                CharacterType::Player(_) => CharacterCategory::Player,
                 _ => match self.auto_interact_radius > 0.0 {
                     true => CharacterCategory::NpcAutoInteractEnabled,
                     false => CharacterCategory::NpcAutoInteractDisabled,
@@ -176,15 +397,284 @@ impl IndexedGuid<u64, (u64, CharacterCategory)> for Character {
     }
 }
 
+pub const DEAD_STATE_BITFLAG: u32 = 0x1;
+pub const COMBAT_STATE_BITFLAG: u32 = 0x2;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ZoneLoadState {
+    Loading,
+    DetailsSent,
+    Loaded,
+}
+
 impl Character {
+    pub fn is_dead(&self) -> bool {
+        self.max_health > 0 && self.health == 0
+    }
+
+    pub fn apply_damage(
+        &mut self,
+        amount: u32,
+        characters_table_read_handle: &CharacterTableReadHandle,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        let was_dead = self.is_dead();
+        self.health = self.health.saturating_sub(amount);
+        if amount > 0 {
+            self.millis_since_damage = 0;
+        }
+
+        let mut broadcasts = self.health_update()?;
+        if !was_dead && self.is_dead() {
+            broadcasts.append(&mut self.character_state_update(characters_table_read_handle)?);
+        }
+
+        Ok(broadcasts)
+    }
+
+    pub fn apply_heal(
+        &mut self,
+        amount: u32,
+        characters_table_read_handle: &CharacterTableReadHandle,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        let was_dead = self.is_dead();
+        self.health = self.health.saturating_add(amount).min(self.max_health);
+
+        let mut broadcasts = self.health_update()?;
+        if was_dead && !self.is_dead() {
+            broadcasts.append(&mut self.character_state_update(characters_table_read_handle)?);
+        }
+
+        Ok(broadcasts)
+    }
+
+    pub fn set_in_combat(
+        &mut self,
+        in_combat: bool,
+        characters_table_read_handle: &CharacterTableReadHandle,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        if self.in_combat == in_combat {
+            return Ok(Vec::new());
+        }
+
+        self.in_combat = in_combat;
+        self.character_state_update(characters_table_read_handle)
+    }
+
+    pub fn set_stat_multiplier(
+        &mut self,
+        stat: StatId,
+        source: StatSource,
+        multiplier: f32,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        if self.stats.set(stat, source, multiplier) {
+            self.stats_update(&[stat])
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    pub fn remove_stat_multiplier(
+        &mut self,
+        stat: StatId,
+        source: StatSource,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        if self.stats.remove(stat, source) {
+            self.stats_update(&[stat])
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    pub fn remove_stat_source(
+        &mut self,
+        source: StatSource,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        let changed_stats = self.stats.remove_source(source);
+        self.stats_update(&changed_stats)
+    }
+
+    pub fn stats_update(&mut self, stats: &[StatId]) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        if let CharacterType::Player(_) = self.character_type {
+            let Some(changed_stats) = self.stats.changed_update(stats) else {
+                return Ok(Vec::new());
+            };
+
+            Ok(vec![Broadcast::Single(
+                shorten_player_guid(self.guid)?,
+                vec![GamePacket::serialize(&TunneledPacket {
+                    unknown1: true,
+                    inner: changed_stats,
+                })?],
+            )])
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    fn state_bitflags(&self) -> u32 {
+        let mut bitflags = 0;
+        if self.is_dead() {
+            bitflags |= DEAD_STATE_BITFLAG;
+        }
+        if self.in_combat {
+            bitflags |= COMBAT_STATE_BITFLAG;
+        }
+
+        bitflags
+    }
+
+    pub fn finish_loading_zone_details(&mut self) -> bool {
+        if self.zone_load_state == ZoneLoadState::DetailsSent {
+            self.zone_load_state = ZoneLoadState::Loaded;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn consume_power(&mut self, amount: u32) -> bool {
+        if amount > self.power {
+            return false;
+        }
+
+        self.power -= amount;
+        true
+    }
+
+    pub fn regenerate_power(
+        &mut self,
+        elapsed_millis: u128,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        // Don't save up regeneration time while power is full
+        if self.power >= self.max_power {
+            self.power_regen_elapsed_millis = 0;
+            return Ok(Vec::new());
+        }
+
+        self.power_regen_elapsed_millis += elapsed_millis;
+        let regenerated_power =
+            self.power_regen_elapsed_millis * self.power_regen_per_second as u128 / 1000;
+        if regenerated_power == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Keep the time that did not yet produce a whole point of power
+        self.power_regen_elapsed_millis -=
+            regenerated_power * 1000 / self.power_regen_per_second as u128;
+        self.power = self
+            .power
+            .saturating_add(regenerated_power.min(u32::MAX as u128) as u32)
+            .min(self.max_power);
+
+        self.power_update()
+    }
+
+    // The HealthRegen stat scales the character's base regeneration, and regeneration waits
+    // until the character hasn't been damaged for the delay
+    pub fn regenerate_health(
+        &mut self,
+        elapsed_millis: u128,
+        delay_millis: u128,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        self.millis_since_damage = self.millis_since_damage.saturating_add(elapsed_millis);
+        let regen_per_second =
+            self.health_regen_per_second as f32 * self.stats.value(StatId::HealthRegen);
+        if self.is_dead()
+            || self.health >= self.max_health
+            || self.millis_since_damage < delay_millis
+            || regen_per_second <= 0.0
+        {
+            self.health_regen_elapsed_millis = 0;
+            return Ok(Vec::new());
+        }
+
+        self.health_regen_elapsed_millis += elapsed_millis;
+        let regenerated_health =
+            (self.health_regen_elapsed_millis as f32 * regen_per_second / 1000.0) as u32;
+        if regenerated_health == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Keep the time that did not yet produce a whole point of health
+        self.health_regen_elapsed_millis = self
+            .health_regen_elapsed_millis
+            .saturating_sub((regenerated_health as f32 * 1000.0 / regen_per_second) as u128);
+        self.health = self
+            .health
+            .saturating_add(regenerated_health)
+            .min(self.max_health);
+
+        self.health_update()
+    }
+
+    pub fn power_update(&self) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        if let CharacterType::Player(_) = self.character_type {
+            Ok(vec![Broadcast::Single(
+                shorten_player_guid(self.guid)?,
+                vec![GamePacket::serialize(&TunneledPacket {
+                    unknown1: true,
+                    inner: Power {
+                        current: self.power,
+                        max: self.max_power,
+                    },
+                })?],
+            )])
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    fn health_update(&self) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        // Health has no GUID and always updates the receiving player's own HUD. No packet is known
+        // that updates another character's health bar, so an NPC's bar isn't updated yet.
+        if let CharacterType::Player(_) = self.character_type {
+            Ok(vec![Broadcast::Single(
+                shorten_player_guid(self.guid)?,
+                vec![GamePacket::serialize(&TunneledPacket {
+                    unknown1: true,
+                    inner: Health {
+                        current: self.health,
+                        max: self.max_health,
+                    },
+                })?],
+            )])
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    // Everyone in the zone sees the character's state, such as whether it is dead
+    fn character_state_update(
+        &self,
+        characters_table_read_handle: &CharacterTableReadHandle,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        Ok(Zone::player_broadcast(
+            self.instance_guid,
+            characters_table_read_handle,
+            vec![GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: UpdateCharacterState {
+                    guid: self.guid,
+                    bitflags: self.state_bitflags(),
+                },
+            })?],
+            None,
+        ))
+    }
+
     pub fn to_packets(&self) -> Result<Vec<Vec<u8>>, SerializePacketError> {
-        let packets = match &self.character_type {
+        // Despawned NPCs are sent again when they respawn
+        if self.despawned_millis.is_some() {
+            return Ok(Vec::new());
+        }
+
+        let mut packets = match &self.character_type {
             CharacterType::Door(door) => {
                 let mut packets = vec![GamePacket::serialize(&TunneledPacket {
                     unknown1: true,
                     inner: Self::door_packet(self, door),
                 })?];
-                packets.append(&mut enable_interaction(self.guid, 55)?);
+                packets.append(&mut self.interaction_packets(55)?);
                 packets
             }
             CharacterType::Transport(transport) => {
@@ -213,12 +703,85 @@ impl Character {
                         },
                     })?,
                 ];
-                packets.append(&mut enable_interaction(self.guid, transport.cursor)?);
+                packets.append(&mut self.interaction_packets(transport.cursor)?);
+                packets
+            }
+            CharacterType::Npc(npc) => {
+                let mut packets = vec![GamePacket::serialize(&TunneledPacket {
+                    unknown1: true,
+                    inner: Self::npc_packet(self, npc),
+                })?];
+                if let Some(cursor) = npc.cursor {
+                    packets.append(&mut self.interaction_packets(cursor)?);
+                }
                 packets
             }
             _ => Vec::new(),
         };
 
+        if let Some(rail) = &self.rail {
+            packets.extend(rail.move_packet(self.guid)?);
+        }
+
+        Ok(packets)
+    }
+
+    // Locked doors and other characters can stop being interactable without despawning
+    pub fn set_interactable(
+        &mut self,
+        interactable: bool,
+    ) -> Result<Vec<Vec<u8>>, ProcessPacketError> {
+        if self.interactable == interactable {
+            return Ok(Vec::new());
+        }
+
+        self.interactable = interactable;
+        let cursor = match &self.character_type {
+            CharacterType::Door(_) => Some(55),
+            CharacterType::Transport(transport) => Some(transport.cursor),
+            CharacterType::Npc(npc) => npc.cursor,
+            CharacterType::Player(_) => None,
+        };
+
+        match cursor {
+            Some(cursor) => Ok(self.interaction_packets(cursor)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn interaction_packets(&self, cursor: u8) -> Result<Vec<Vec<u8>>, SerializePacketError> {
+        if self.interactable {
+            enable_interaction(self.guid, cursor)
+        } else {
+            disable_interaction(self.guid)
+        }
+    }
+
+    // Whether the character has anything to regenerate, move, or respawn on tick
+    pub fn can_advance(&self) -> bool {
+        let can_respawn = match &self.character_type {
+            CharacterType::Npc(npc) => {
+                self.is_dead() && (self.despawned_millis.is_none() || npc.respawn_seconds.is_some())
+            }
+            _ => false,
+        };
+        let can_regenerate = self.health < self.max_health && self.health_regen_per_second > 0;
+        can_respawn || can_regenerate || self.rail.is_some()
+    }
+
+    pub fn advance_rail(
+        &mut self,
+        elapsed_millis: u128,
+    ) -> Result<Vec<Vec<u8>>, ProcessPacketError> {
+        let Some(rail) = &mut self.rail else {
+            return Ok(Vec::new());
+        };
+
+        let (packets, finished) = rail.advance(self.guid, &mut self.pos, elapsed_millis)?;
+        if finished {
+            self.rail = None;
+        }
+
         Ok(packets)
     }
 
@@ -235,7 +798,7 @@ impl Character {
             pos: character.pos,
             rot: character.rot,
             unknown8: 1,
-            attachments: vec![],
+            attachments: door.attachments.clone(),
             is_not_targetable: 1,
             unknown10: 1,
             texture_name: "".to_string(),
@@ -245,8 +808,8 @@ impl Character {
             offset_y: 0.0,
             composite_effect: 0,
             weapon_animation: WeaponAnimation::None,
-            name_override: "".to_string(),
-            hide_name: false,
+            name_override: door.name_override.clone().unwrap_or_default(),
+            hide_name: door.hide_name,
             name_offset_x: 0.0,
             name_offset_y: 0.0,
             name_offset_z: 0.0,
@@ -257,13 +820,17 @@ impl Character {
             interactable_size_pct: 100,
             unknown23: -1,
             unknown24: -1,
-            active_animation_slot: -1,
+            active_animation_slot: character.animation_slot,
             unknown26: false,
             ignore_position: false,
             sub_title_id: 0,
             active_animation_slot2: 0,
             head_model_id: 0,
-            effects: vec![],
+            effects: door
+                .composite_effect
+                .map(Effect::from_composite_effect)
+                .into_iter()
+                .collect(),
             disable_interact_popup: true,
             unknown33: 0,
             unknown34: false,
@@ -335,7 +902,7 @@ impl Character {
             pos: character.pos,
             rot: character.rot,
             unknown8: 1,
-            attachments: vec![],
+            attachments: transport.attachments.clone(),
             is_not_targetable: 1,
             unknown10: 1,
             texture_name: "".to_string(),
@@ -345,7 +912,7 @@ impl Character {
             offset_y: 0.0,
             composite_effect: 0,
             weapon_animation: WeaponAnimation::None,
-            name_override: "".to_string(),
+            name_override: transport.name_override.clone().unwrap_or_default(),
             hide_name: !transport.show_name,
             name_offset_x: transport.name_offset_x.unwrap_or(0.0),
             name_offset_y: transport.name_offset_y.unwrap_or(0.0),
@@ -357,7 +924,7 @@ impl Character {
             interactable_size_pct: 100,
             unknown23: -1,
             unknown24: -1,
-            active_animation_slot: -1,
+            active_animation_slot: character.animation_slot,
             unknown26: false,
             ignore_position: false,
             sub_title_id: 0,
@@ -425,6 +992,106 @@ impl Character {
             icon_id: Icon::None,
         }
     }
+
+    fn npc_packet(character: &Character, npc: &NpcData) -> AddNpc {
+        AddNpc {
+            guid: character.guid,
+            name_id: npc.name_id,
+            model_id: npc.model_id,
+            unknown3: false,
+            unknown4: 408679,
+            unknown5: 13951728,
+            unknown6: 1,
+            scale: npc.scale.unwrap_or(1.0),
+            pos: character.pos,
+            rot: character.rot,
+            unknown8: 1,
+            attachments: npc.attachments.clone(),
+            is_not_targetable: 1,
+            unknown10: 1,
+            texture_name: npc.texture.clone(),
+            tint_name: "".to_string(),
+            tint_id: 0,
+            unknown11: true,
+            offset_y: 0.0,
+            composite_effect: 0,
+            weapon_animation: WeaponAnimation::None,
+            name_override: npc.name_override.clone().unwrap_or_default(),
+            hide_name: npc.hide_name,
+            name_offset_x: 0.0,
+            name_offset_y: 0.0,
+            name_offset_z: 0.0,
+            terrain_object_id: 0,
+            invisible: false,
+            unknown20: 0.0,
+            unknown21: false,
+            interactable_size_pct: 100,
+            unknown23: -1,
+            unknown24: -1,
+            active_animation_slot: character.animation_slot,
+            unknown26: false,
+            ignore_position: false,
+            sub_title_id: npc.sub_title_id,
+            active_animation_slot2: 0,
+            head_model_id: 0,
+            effects: vec![],
+            disable_interact_popup: false,
+            unknown33: 0,
+            unknown34: false,
+            show_health: npc.show_health.unwrap_or(character.max_health > 0),
+            hide_despawn_fade: false,
+            ignore_rotation_and_shadow: false,
+            base_attachment_group: BaseAttachmentGroup {
+                unknown1: 0,
+                unknown2: "".to_string(),
+                unknown3: "".to_string(),
+                unknown4: 0,
+                unknown5: "".to_string(),
+            },
+            unknown39: Pos {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                w: 0.0,
+            },
+            unknown40: 0,
+            unknown41: -1,
+            unknown42: 0,
+            collision: true,
+            unknown44: 0,
+            npc_type: 2,
+            unknown46: 0.0,
+            target: 0,
+            unknown50: vec![],
+            rail_id: 0,
+            rail_speed: 0.0,
+            rail_origin: Pos {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                w: 0.0,
+            },
+            unknown54: 0,
+            rail_unknown1: 0.0,
+            rail_unknown2: 0.0,
+            rail_unknown3: 0.0,
+            attachment_group_unknown: "".to_string(),
+            unknown59: "".to_string(),
+            unknown60: "".to_string(),
+            override_terrain_model: false,
+            hover_glow: npc.hover_glow,
+            hover_description: npc.hover_description,
+            fly_over_effect: npc.fly_over_effect,
+            unknown65: 8,
+            unknown66: 0,
+            unknown67: 3442,
+            disable_move_to_interact: false,
+            unknown69: 0.0,
+            unknown70: 0.0,
+            unknown71: 0,
+            icon_id: npc.icon,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -441,6 +1108,8 @@ pub struct ZoneTemplate {
     pub gravity_multiplier: f32,
     hide_ui: bool,
     combat_hud: bool,
+    interest_radius: Option<f32>,
+    interest_hysteresis: f32,
     characters: Vec<NpcTemplate>,
 }
 
@@ -496,7 +1165,10 @@ impl ZoneTemplate {
             gravity_multiplier: self.gravity_multiplier,
             hide_ui: self.hide_ui,
             combat_hud: self.combat_hud,
+            interest_radius: self.interest_radius,
+            interest_hysteresis: self.interest_hysteresis,
             house_data,
+            next_npc_index: AtomicU16::new(0),
         }
     }
 }
@@ -523,6 +1195,12 @@ pub struct House {
     pub is_rateable: bool,
 }
 
+pub const ZONE_STATS: [StatId; 3] = [
+    StatId::Speed,
+    StatId::JumpHeightMultiplier,
+    StatId::GravityMultiplier,
+];
+
 pub struct Zone {
     guid: u64,
     pub template_guid: u8,
@@ -537,7 +1215,10 @@ pub struct Zone {
     pub gravity_multiplier: f32,
     hide_ui: bool,
     combat_hud: bool,
+    interest_radius: Option<f32>,
+    interest_hysteresis: f32,
     pub house_data: Option<House>,
+    next_npc_index: AtomicU16,
 }
 
 impl IndexedGuid<u64, u8> for Zone {
@@ -580,48 +1261,356 @@ impl Zone {
         })?])
     }
 
-    pub fn character_guids(
-        guid: u64,
-        characters_table_read_handle: &CharacterTableReadHandle,
-    ) -> Vec<u64> {
-        let mut guids = Vec::new();
-        for category in CharacterCategory::iter() {
-            for guid in characters_table_read_handle.keys_by_index((guid, category)) {
-                guids.push(guid);
+    pub fn spawn_npc(
+        &self,
+        characters_table_write_handle: &mut CharacterTableWriteHandle,
+        npc: TransientNpc,
+    ) -> Result<(u64, Vec<Broadcast>), ProcessPacketError> {
+        let index = self.allocate_npc_index(characters_table_write_handle)?;
+        let character = NpcTemplate {
+            discriminant: TRANSIENT_NPC_DISCRIMINANT,
+            index,
+            pos: npc.pos,
+            rot: npc.rot,
+            state: npc.state,
+            character_type: npc.character_type,
+            mount_id: None,
+            interact_radius: npc.interact_radius,
+            auto_interact_radius: npc.auto_interact_radius,
+            rail: None,
+        }
+        .to_character(self.guid);
+
+        let guid = character.guid;
+        let pos = character.pos;
+        let packets = character.to_packets()?;
+        characters_table_write_handle.insert(character);
+
+        let mut players: Vec<_> = characters_table_write_handle
+            .values_by_index((self.guid, CharacterCategory::Player))
+            .map(|player| player.write())
+            .collect();
+        let player_guids =
+            self.show_character(players.iter_mut().map(|player| &mut **player), guid, &pos);
+        if player_guids.is_empty() {
+            Ok((guid, Vec::new()))
+        } else {
+            Ok((guid, vec![Broadcast::Multi(player_guids, packets)]))
+        }
+    }
+
+    // Return doors are the only transient NPCs so far, and they are removed with their zone's last
+    // player instead
+    #[cfg(test)]
+    pub fn despawn(
+        &self,
+        characters_table_write_handle: &mut CharacterTableWriteHandle,
+        guid: u64,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        // Only transient NPCs may be despawned so that players and configured NPCs stay intact
+        if npc_discriminant(guid) != TRANSIENT_NPC_DISCRIMINANT {
+            return Err(ProcessPacketError::CorruptedPacket);
+        }
+
+        match characters_table_write_handle.index(guid) {
+            Some((instance_guid, _)) if instance_guid == self.guid => {}
+            Some(_) => return Err(ProcessPacketError::CorruptedPacket),
+            None => return Ok(Vec::new()),
+        }
+
+        characters_table_write_handle.remove(guid);
+        let mut players: Vec<_> = characters_table_write_handle
+            .values_by_index((self.guid, CharacterCategory::Player))
+            .map(|player| player.write())
+            .collect();
+        let player_guids =
+            Zone::hide_character(players.iter_mut().map(|player| &mut **player), guid);
+        if player_guids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        Ok(vec![Broadcast::Multi(
+            player_guids,
+            vec![GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: RemoveGracefully {
+                    guid,
+                    unknown1: false,
+                    unknown2: 0,
+                    unknown3: 0,
+                    unknown4: 0,
+                    timer: 1000,
+                },
+            })?],
+        )])
+    }
+
+    // All characters share one table, so an index with no character in it can't collide with an NPC
+    // that is still spawned. Indices are handed out in order, so the search only continues past
+    // the cursor once the indices wrap around.
+    fn allocate_npc_index(
+        &self,
+        characters_table_write_handle: &CharacterTableWriteHandle,
+    ) -> Result<u16, ProcessPacketError> {
+        let first_index = self.next_npc_index.load(Ordering::Relaxed);
+        let index = (0..=u16::MAX)
+            .map(|offset| first_index.wrapping_add(offset))
+            .find(|index| {
+                characters_table_write_handle
+                    .get(npc_guid(TRANSIENT_NPC_DISCRIMINANT, self.guid, *index))
+                    .is_none()
+            })
+            .ok_or_else(|| {
+                println!("No NPC indices left in zone {}", self.guid);
+                ProcessPacketError::CorruptedPacket
+            })?;
+        self.next_npc_index
+            .store(index.wrapping_add(1), Ordering::Relaxed);
+        Ok(index)
+    }
+
+    // Interactions copy the destination out of the door, so teleports already underway keep the old one
+    pub fn set_door_destination(
+        &self,
+        characters_table_write_handle: &mut CharacterTableWriteHandle,
+        door_guid: u64,
+        destination_zone: u64,
+        destination_pos: Pos,
+        destination_rot: Pos,
+    ) -> Result<(), ProcessPacketError> {
+        let Some(door_lock) = characters_table_write_handle.get(door_guid) else {
+            println!("Tried to change destination of unknown door {}", door_guid);
+            return Err(ProcessPacketError::CorruptedPacket);
+        };
+        let mut door_character = door_lock.write();
+        if door_character.instance_guid != self.guid {
+            println!(
+                "Tried to change destination of door {} outside zone {}",
+                door_guid, self.guid
+            );
+            return Err(ProcessPacketError::CorruptedPacket);
+        }
+
+        let CharacterType::Door(door) = &mut door_character.character_type else {
+            println!("Tried to change destination of non-door {}", door_guid);
+            return Err(ProcessPacketError::CorruptedPacket);
+        };
+        door.destination_pos_x = destination_pos.x;
+        door.destination_pos_y = destination_pos.y;
+        door.destination_pos_z = destination_pos.z;
+        door.destination_pos_w = destination_pos.w;
+        door.destination_rot_x = destination_rot.x;
+        door.destination_rot_y = destination_rot.y;
+        door.destination_rot_z = destination_rot.z;
+        door.destination_rot_w = destination_rot.w;
+        door.destination_zone_template = None;
+        door.destination_zone = Some(destination_zone);
+
+        Ok(())
+    }
+
+    // Sets the zone's baseline movement stats, which other sources like mounts stack on top of
+    pub fn apply_stats(&self, character: &mut Character) {
+        character
+            .stats
+            .set(StatId::Speed, StatSource::Zone, self.speed);
+        character.stats.set(
+            StatId::JumpHeightMultiplier,
+            StatSource::Zone,
+            self.jump_height_multiplier,
+        );
+        character.stats.set(
+            StatId::GravityMultiplier,
+            StatSource::Zone,
+            self.gravity_multiplier,
+        );
+    }
+
+    pub fn character_guids(
+        guid: u64,
+        characters_table_read_handle: &CharacterTableReadHandle,
+    ) -> Vec<u64> {
+        let mut guids = Vec::new();
+        for category in CharacterCategory::iter() {
+            for guid in characters_table_read_handle.keys_by_index((guid, category)) {
+                guids.push(guid);
             }
         }
 
         guids
     }
 
+    // Sends characters that came within the interest radius of the player and removes those that
+    // left it. Characters must move past the hysteresis before they are removed, so a player
+    // walking along the boundary doesn't see them flicker in and out.
+    pub fn update_interest(
+        &self,
+        player: &mut Character,
+        characters_read: &BTreeMap<u64, CharacterReadGuard>,
+    ) -> Result<Vec<Vec<u8>>, ProcessPacketError> {
+        let CharacterType::Player(player_state) = &mut player.character_type else {
+            return Ok(Vec::new());
+        };
+
+        // Characters are streamed once the client has finished loading the zone
+        if player.zone_load_state != ZoneLoadState::Loaded {
+            return Ok(Vec::new());
+        }
+
+        // Characters that were removed from the zone no longer need to be removed from the client
+        player_state
+            .visible_characters
+            .retain(|guid| characters_read.contains_key(guid));
+
+        let mut packets = Vec::new();
+        for (guid, character) in characters_read.iter() {
+            if let CharacterType::Player(_) = character.character_type {
+                continue;
+            }
+
+            let distance = distance(&player.pos, &character.pos);
+            let is_visible = player_state.visible_characters.contains(guid);
+
+            match self.interest_radius {
+                Some(interest_radius) if is_visible => {
+                    if distance > interest_radius + self.interest_hysteresis {
+                        player_state.visible_characters.remove(guid);
+                        packets.push(GamePacket::serialize(&TunneledPacket {
+                            unknown1: true,
+                            inner: RemoveGracefully {
+                                guid: *guid,
+                                unknown1: false,
+                                unknown2: 0,
+                                unknown3: 0,
+                                unknown4: 0,
+                                timer: 1000,
+                            },
+                        })?);
+                    }
+                }
+                Some(interest_radius) if distance > interest_radius => {}
+                _ if is_visible => {}
+                _ => {
+                    // Despawned characters are sent once they spawn again
+                    let mut character_packets = character.to_packets()?;
+                    if character_packets.is_empty() {
+                        continue;
+                    }
+
+                    player_state.visible_characters.insert(*guid);
+                    packets.append(&mut character_packets);
+                }
+            }
+        }
+
+        Ok(packets)
+    }
+
+    // Returns the players that should see a character that appeared at runtime, recording it as
+    // visible to them so that interest updates don't send it again
+    pub fn show_character<'a>(
+        &self,
+        players: impl Iterator<Item = &'a mut Character>,
+        guid: u64,
+        pos: &Pos,
+    ) -> Vec<u32> {
+        players
+            .filter_map(|player| {
+                let CharacterType::Player(player_state) = &mut player.character_type else {
+                    return None;
+                };
+                if player.zone_load_state != ZoneLoadState::Loaded {
+                    return None;
+                }
+                if let Some(interest_radius) = self.interest_radius {
+                    if distance(&player.pos, pos) > interest_radius {
+                        return None;
+                    }
+                }
+
+                player_state.visible_characters.insert(guid);
+                shorten_player_guid(player.guid).ok()
+            })
+            .collect()
+    }
+
+    // Returns the players that could see a character that disappeared at runtime
+    pub fn hide_character<'a>(
+        players: impl Iterator<Item = &'a mut Character>,
+        guid: u64,
+    ) -> Vec<u32> {
+        players
+            .filter_map(|player| {
+                let CharacterType::Player(player_state) = &mut player.character_type else {
+                    return None;
+                };
+                if player_state.visible_characters.remove(&guid) {
+                    shorten_player_guid(player.guid).ok()
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn player_broadcast(
+        guid: u64,
+        characters_table_read_handle: &CharacterTableReadHandle,
+        packets: Vec<Vec<u8>>,
+        exclude: Option<u64>,
+    ) -> Vec<Broadcast> {
+        let player_guids: Vec<u32> = characters_table_read_handle
+            .keys_by_index((guid, CharacterCategory::Player))
+            .filter(|player_guid| Some(*player_guid) != exclude)
+            .filter_map(|player_guid| shorten_player_guid(player_guid).ok())
+            .collect();
+
+        if player_guids.is_empty() {
+            Vec::new()
+        } else {
+            vec![Broadcast::Multi(player_guids, packets)]
+        }
+    }
+
     pub fn move_character(
         pos_update: UpdatePlayerPosition,
         game_server: &GameServer,
     ) -> Result<Vec<Broadcast>, ProcessPacketError> {
-        let characters_to_interact =
-            game_server
-                .lock_enforcer()
-                .read_characters(|characters_table_read_handle| {
-                    let auto_interact_npcs = if let Some((instance_guid, _)) =
-                        characters_table_read_handle.index(pos_update.guid)
-                    {
-                        characters_table_read_handle
-                            .keys_by_index((
-                                instance_guid,
-                                CharacterCategory::NpcAutoInteractEnabled,
-                            ))
-                            .collect()
-                    } else {
-                        Vec::new()
-                    };
+        if !pos_update.is_finite() {
+            println!(
+                "Character {} sent a position update with non-finite values",
+                pos_update.guid
+            );
+            return Err(ProcessPacketError::CorruptedPacket);
+        }
 
-                    CharacterLockRequest {
-                        read_guids: auto_interact_npcs.clone(),
-                        write_guids: vec![pos_update.guid],
-                        character_consumer: move |_, characters_read, mut characters_write, _| {
+        let (characters_to_interact, interest_packets, mut emote_broadcasts) = game_server
+            .lock_enforcer()
+            .read_characters(|characters_table_read_handle| {
+                let (instance_guid, npcs) = if let Some((instance_guid, _)) =
+                    characters_table_read_handle.index(pos_update.guid)
+                {
+                    let npcs = Zone::character_guids(instance_guid, characters_table_read_handle)
+                        .into_iter()
+                        .filter(|guid| *guid != pos_update.guid)
+                        .collect();
+                    (instance_guid, npcs)
+                } else {
+                    (0, Vec::new())
+                };
+
+                CharacterLockRequest {
+                    read_guids: npcs,
+                    write_guids: vec![pos_update.guid],
+                    character_consumer:
+                        move |characters_table_read_handle,
+                              characters_read,
+                              mut characters_write,
+                              zones_lock_enforcer| {
                             if let Some(character_write_handle) =
                                 characters_write.get_mut(&pos_update.guid)
                             {
+                                let previous_pos = character_write_handle.pos;
                                 character_write_handle.pos = Pos {
                                     x: pos_update.pos_x,
                                     y: pos_update.pos_y,
@@ -635,27 +1624,64 @@ impl Zone {
                                     w: character_write_handle.rot.z,
                                 };
                                 character_write_handle.state = pos_update.character_state;
+                                character_write_handle.idle_millis = 0;
+
+                                // Position updates also arrive while standing still, so only
+                                // an actual change in position interrupts an emote
+                                let new_pos = character_write_handle.pos;
+                                let moved = (previous_pos.x, previous_pos.y, previous_pos.z)
+                                    != (new_pos.x, new_pos.y, new_pos.z);
+                                let emote_packets = if moved {
+                                    character_write_handle.stop_emote()?
+                                } else {
+                                    Vec::new()
+                                };
+                                let emote_broadcasts = if emote_packets.is_empty() {
+                                    Vec::new()
+                                } else {
+                                    Zone::player_broadcast(
+                                        instance_guid,
+                                        characters_table_read_handle,
+                                        emote_packets,
+                                        None,
+                                    )
+                                };
 
                                 let mut characters_to_interact = Vec::new();
-                                for npc_guid in auto_interact_npcs {
-                                    if let Some(npc_read_handle) = characters_read.get(&npc_guid) {
-                                        if npc_read_handle.auto_interact_radius > 0.0 {
-                                            let distance = distance3(
-                                                character_write_handle.pos.x,
-                                                character_write_handle.pos.y,
-                                                character_write_handle.pos.z,
-                                                npc_read_handle.pos.x,
-                                                npc_read_handle.pos.y,
-                                                npc_read_handle.pos.z,
-                                            );
-                                            if distance <= npc_read_handle.auto_interact_radius {
-                                                characters_to_interact.push(npc_read_handle.guid);
-                                            }
+                                for npc_read_handle in characters_read.values() {
+                                    if npc_read_handle.auto_interact_radius > 0.0 {
+                                        let distance = distance(
+                                            &character_write_handle.pos,
+                                            &npc_read_handle.pos,
+                                        );
+                                        if distance <= npc_read_handle.auto_interact_radius {
+                                            characters_to_interact.push(npc_read_handle.guid);
                                         }
                                     }
                                 }
 
-                                Ok(characters_to_interact)
+                                // Interest is updated again with the next position update, so
+                                // a contended zone is skipped instead of stalling the game loop
+                                let interest_packets = zones_lock_enforcer
+                                    .try_read_zones(|_| ZoneLockRequest {
+                                        read_guids: vec![instance_guid],
+                                        write_guids: Vec::new(),
+                                        zone_consumer: |_, zones_read, _| {
+                                            if let Some(zone_read_handle) =
+                                                zones_read.get(&instance_guid)
+                                            {
+                                                zone_read_handle.update_interest(
+                                                    character_write_handle,
+                                                    &characters_read,
+                                                )
+                                            } else {
+                                                Ok(Vec::new())
+                                            }
+                                        },
+                                    })
+                                    .unwrap_or(Ok(Vec::new()))?;
+
+                                Ok((characters_to_interact, interest_packets, emote_broadcasts))
                             } else {
                                 println!(
                                     "Received position update from unknown character {}",
@@ -664,10 +1690,18 @@ impl Zone {
                                 Err(ProcessPacketError::CorruptedPacket)
                             }
                         },
-                    }
-                })?;
+                }
+            })?;
 
         let mut broadcasts = Vec::new();
+        broadcasts.append(&mut emote_broadcasts);
+        if !interest_packets.is_empty() {
+            broadcasts.push(Broadcast::Single(
+                shorten_player_guid(pos_update.guid)?,
+                interest_packets,
+            ));
+        }
+
         for character_guid in characters_to_interact {
             let interact_request = SelectPlayer {
                 requester: pos_update.guid,
@@ -715,6 +1749,7 @@ impl ZoneConfig {
                     mount_id: None,
                     interact_radius: self.interact_radius,
                     auto_interact_radius: self.door_auto_interact_radius,
+                    rail: None,
                 });
                 index += 1;
             }
@@ -736,6 +1771,7 @@ impl ZoneConfig {
                         w: transport.rot_w,
                     },
                     state: 0,
+                    rail: transport.rail.clone(),
                     character_type: CharacterType::Transport(transport),
                     mount_id: None,
                     interact_radius: self.interact_radius,
@@ -743,6 +1779,33 @@ impl ZoneConfig {
                 });
                 index += 1;
             }
+
+            for mut npc in self.npcs {
+                npc.clamp_to_packet_limits();
+                characters.push(NpcTemplate {
+                    discriminant: AMBIENT_NPC_DISCRIMINANT,
+                    index,
+                    pos: Pos {
+                        x: npc.pos_x,
+                        y: npc.pos_y,
+                        z: npc.pos_z,
+                        w: npc.pos_w,
+                    },
+                    rot: Pos {
+                        x: npc.rot_x,
+                        y: npc.rot_y,
+                        z: npc.rot_z,
+                        w: npc.rot_w,
+                    },
+                    state: 0,
+                    rail: npc.rail.clone(),
+                    character_type: CharacterType::Npc(npc),
+                    mount_id: None,
+                    interact_radius: self.interact_radius,
+                    auto_interact_radius: 0.0,
+                });
+                index += 1;
+            }
         }
 
         let template = ZoneTemplate {
@@ -768,6 +1831,8 @@ impl ZoneConfig {
             gravity_multiplier: self.gravity_multiplier,
             hide_ui: self.hide_ui,
             combat_hud: self.combat_hud,
+            interest_radius: self.interest_radius,
+            interest_hysteresis: self.interest_hysteresis,
             characters,
         };
 
@@ -825,16 +1890,46 @@ pub fn enter_zone(
     let destination_rot = destination_rot.unwrap_or(destination_read_handle.default_spawn_rot);
 
     let character = characters_table_write_handle.remove(player_guid(player));
-    if let Some((character, (_, character_category))) = character {
+    if let Some((character, (previous_instance_guid, character_category))) = character {
+        let mut broadcasts = stop_seeking(
+            characters_table_write_handle,
+            previous_instance_guid,
+            player_guid(player),
+        )?;
+
         let mut character_write_handle = character.write();
         character_write_handle.instance_guid = destination_read_handle.guid;
+        character_write_handle.pos = destination_pos;
+        character_write_handle.rot = destination_rot;
+        character_write_handle.zone_load_state = ZoneLoadState::DetailsSent;
+        destination_read_handle.apply_stats(&mut character_write_handle);
+        let stats = character_write_handle.stats.full_update(&ZONE_STATS);
         drop(character_write_handle);
         characters_table_write_handle.insert_lock(
             player_guid(player),
             (destination_read_handle.guid, character_category),
             character,
         );
+        if previous_instance_guid != destination_read_handle.guid {
+            close_return_doors(characters_table_write_handle, previous_instance_guid);
+        }
+
+        broadcasts.append(&mut prepare_init_zone_packets(
+            player,
+            destination_read_handle,
+            destination_pos,
+            destination_rot,
+        )?);
+        broadcasts.push(Broadcast::Single(
+            player,
+            vec![GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: stats,
+            })?],
+        ));
+        return Ok(broadcasts);
     }
+
     prepare_init_zone_packets(
         player,
         destination_read_handle,
@@ -905,7 +2000,6 @@ macro_rules! teleport_to_zone {
         if let Some(character_lock) = character {
             broadcasts.append(&mut $crate::game_server::mount::reply_dismount(
                 $player,
-                $destination_read_handle,
                 &mut character_lock.write(),
                 $mounts,
             )?);
@@ -932,6 +2026,238 @@ fn coerce_to_packet_supplier(
 ) -> PacketSupplier {
     Ok(Box::new(f))
 }
+// Each zone has at most one return door per source door. Return doors are spawned as transient
+// NPCs because doors in different source zones may share an index.
+struct ReturnDoor {
+    door: Door,
+    interact_radius: f32,
+}
+
+impl ReturnDoor {
+    fn spawn(
+        self,
+        characters_table_write_handle: &mut CharacterTableWriteHandle,
+        zone: &Zone,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        let already_spawned = return_door_guids(characters_table_write_handle, zone.guid)
+            .into_iter()
+            .any(|(_, return_from)| return_from == self.door.return_from);
+        if already_spawned {
+            return Ok(Vec::new());
+        }
+
+        let (_, broadcasts) = zone.spawn_npc(
+            characters_table_write_handle,
+            TransientNpc {
+                pos: Pos {
+                    x: self.door.x,
+                    y: self.door.y,
+                    z: self.door.z,
+                    w: self.door.w,
+                },
+                rot: Pos {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                    w: 0.0,
+                },
+                state: 0,
+                character_type: CharacterType::Door(self.door),
+                interact_radius: self.interact_radius,
+                auto_interact_radius: 0.0,
+            },
+        )?;
+        Ok(broadcasts)
+    }
+}
+
+// Returns each return door in the zone with the source door it leads back to
+fn return_door_guids(
+    characters_table_write_handle: &CharacterTableWriteHandle,
+    zone_guid: u64,
+) -> Vec<(u64, Option<u64>)> {
+    CharacterCategory::iter()
+        .flat_map(|category| characters_table_write_handle.keys_by_index((zone_guid, category)))
+        .filter(|guid| npc_discriminant(*guid) == TRANSIENT_NPC_DISCRIMINANT)
+        .filter_map(|guid| {
+            let character_read_handle = characters_table_write_handle.get(guid)?.read();
+            match &character_read_handle.character_type {
+                CharacterType::Door(door) if door.return_from.is_some() => {
+                    Some((guid, door.return_from))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// Return doors only lead players back out of their zone, so they are removed once no players
+// remain in it, however the last player left
+pub fn close_return_doors(
+    characters_table_write_handle: &mut CharacterTableWriteHandle,
+    zone_guid: u64,
+) {
+    let players_remain = characters_table_write_handle
+        .keys_by_index((zone_guid, CharacterCategory::Player))
+        .next()
+        .is_some();
+    if players_remain {
+        return;
+    }
+
+    for (guid, _) in return_door_guids(characters_table_write_handle, zone_guid) {
+        characters_table_write_handle.remove(guid);
+    }
+}
+
+pub fn seek(
+    characters_table_write_handle: &CharacterTableWriteHandle,
+    seeker: u64,
+    target: u64,
+    speed: f32,
+) -> Result<Vec<Broadcast>, ProcessPacketError> {
+    let Some((instance_guid, _)) = characters_table_write_handle.index(seeker) else {
+        println!("Tried to make non-existent character {} seek", seeker);
+        return Err(ProcessPacketError::CorruptedPacket);
+    };
+    if characters_table_write_handle
+        .index(target)
+        .map(|(instance_guid, _)| instance_guid)
+        != Some(instance_guid)
+    {
+        println!(
+            "Character {} tried to seek {}, which is not in its zone",
+            seeker, target
+        );
+        return Err(ProcessPacketError::CorruptedPacket);
+    }
+
+    let mut character = characters_table_write_handle.get(seeker).unwrap().write();
+    character.seek_target = Some(target);
+    let packet = GamePacket::serialize(&TunneledPacket {
+        unknown1: true,
+        inner: SeekTarget {
+            guid: seeker,
+            target_id: target,
+            init_speed: speed,
+            acceleration: 0.0,
+            speed,
+            unknown1: 0.0,
+            rot_y: 0.0,
+            rot: character.rot,
+        },
+    })?;
+    drop(character);
+
+    Ok(players_in_zone(
+        characters_table_write_handle,
+        instance_guid,
+        vec![packet],
+    ))
+}
+
+// Stops every character in the zone that is seeking a target that left it, so they don't chase
+// a character that no longer exists on the client
+pub fn stop_seeking(
+    characters_table_write_handle: &CharacterTableWriteHandle,
+    zone_guid: u64,
+    target: u64,
+) -> Result<Vec<Broadcast>, ProcessPacketError> {
+    let mut packets = Vec::new();
+    for category in CharacterCategory::iter() {
+        for character in characters_table_write_handle.values_by_index((zone_guid, category)) {
+            let mut character_write_handle = character.write();
+            if character_write_handle.seek_target == Some(target) {
+                character_write_handle.seek_target = None;
+                packets.push(GamePacket::serialize(&TunneledPacket {
+                    unknown1: true,
+                    inner: SeekTargetUpdate {
+                        guid: character_write_handle.guid,
+                        target_id: 0,
+                    },
+                })?);
+            }
+        }
+    }
+
+    if packets.is_empty() {
+        Ok(Vec::new())
+    } else {
+        Ok(players_in_zone(
+            characters_table_write_handle,
+            zone_guid,
+            packets,
+        ))
+    }
+}
+
+pub fn players_in_zone(
+    characters_table_write_handle: &CharacterTableWriteHandle,
+    zone_guid: u64,
+    packets: Vec<Vec<u8>>,
+) -> Vec<Broadcast> {
+    let player_guids: Vec<u32> = characters_table_write_handle
+        .keys_by_index((zone_guid, CharacterCategory::Player))
+        .filter_map(|player_guid| shorten_player_guid(player_guid).ok())
+        .collect();
+
+    if player_guids.is_empty() {
+        Vec::new()
+    } else {
+        vec![Broadcast::Multi(player_guids, packets)]
+    }
+}
+
+pub fn play_composite_effect(
+    characters_table_read_handle: &CharacterTableReadHandle,
+    guid: u64,
+    composite_effect: u32,
+) -> Result<Vec<Broadcast>, ProcessPacketError> {
+    let Some((instance_guid, _)) = characters_table_read_handle.index(guid) else {
+        println!(
+            "Tried to play composite effect {} on unknown character {}",
+            composite_effect, guid
+        );
+        return Err(ProcessPacketError::CorruptedPacket);
+    };
+
+    Ok(Zone::player_broadcast(
+        instance_guid,
+        characters_table_read_handle,
+        vec![GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: SlotCompositeEffectOverride {
+                guid,
+                slot_id: 0,
+                composite_effect,
+            },
+        })?],
+        None,
+    ))
+}
+
+fn play_door_effect(
+    game_server: &GameServer,
+    guid: u64,
+    composite_effect: Option<u32>,
+) -> Result<Vec<Broadcast>, ProcessPacketError> {
+    let Some(composite_effect) = composite_effect else {
+        return Ok(Vec::new());
+    };
+
+    game_server
+        .lock_enforcer()
+        .read_characters(|_| CharacterLockRequest {
+            read_guids: Vec::new(),
+            write_guids: Vec::new(),
+            character_consumer: |characters_table_read_handle, _, _, _| {
+                play_composite_effect(characters_table_read_handle, guid, composite_effect)
+            },
+        })
+}
+
+const TOO_FAR_TO_INTERACT_MESSAGE: &str = "You are too far away.";
+
 pub fn interact_with_character(
     request: SelectPlayer,
     game_server: &GameServer,
@@ -943,29 +2269,35 @@ pub fn interact_with_character(
             write_guids: Vec::new(),
             character_consumer: move |_, characters_read, _, zones_lock_enforcer| {
                 let source_zone_guid;
-                let requester_x;
-                let requester_y;
-                let requester_z;
+                let requester_pos;
                 if let Some(requester_read_handle) = characters_read.get(&request.requester) {
                     source_zone_guid = requester_read_handle.instance_guid;
-                    requester_x = requester_read_handle.pos.x;
-                    requester_y = requester_read_handle.pos.y;
-                    requester_z = requester_read_handle.pos.z;
+                    requester_pos = requester_read_handle.pos;
                 } else {
                     return coerce_to_packet_supplier(|_| Ok(Vec::new()));
                 }
 
                 if let Some(target_read_handle) = characters_read.get(&request.target) {
+                    if target_read_handle.instance_guid != source_zone_guid {
+                        println!(
+                            "Player {} tried to interact with character {} in another zone",
+                            request.requester, request.target
+                        );
+                        return Err(ProcessPacketError::CorruptedPacket);
+                    }
+
                     // Ensure the character is close enough to interact
-                    let distance = distance3(
-                        requester_x,
-                        requester_y,
-                        requester_z,
-                        target_read_handle.pos.x,
-                        target_read_handle.pos.y,
-                        target_read_handle.pos.z,
-                    );
+                    let distance = distance(&requester_pos, &target_read_handle.pos);
                     if distance > target_read_handle.interact_radius {
+                        return coerce_to_packet_supplier(move |_| {
+                            Ok(vec![Broadcast::Single(
+                                requester,
+                                vec![system_message(TOO_FAR_TO_INTERACT_MESSAGE.to_string())?],
+                            )])
+                        });
+                    }
+
+                    if !target_read_handle.interactable {
                         return coerce_to_packet_supplier(|_| Ok(Vec::new()));
                     }
 
@@ -1004,9 +2336,39 @@ pub fn interact_with_character(
                                 } else {
                                     source_zone_guid
                                 };
+
+                            let door_guid = target_read_handle.guid;
+                            let enter_composite_effect = door.enter_composite_effect;
+                            let exit_composite_effect = door.exit_composite_effect;
+                            let fade_composite_effect = door.fade_composite_effect;
+                            let return_door = if door.return_door {
+                                Some(ReturnDoor {
+                                    door: door.to_return_door(
+                                        door_guid,
+                                        source_zone_guid,
+                                        destination_pos,
+                                        destination_rot,
+                                    ),
+                                    interact_radius: target_read_handle.interact_radius,
+                                })
+                            } else {
+                                None
+                            };
+
                             if source_zone_guid != destination_zone_guid {
                                 coerce_to_packet_supplier(move |game_server| {
-                                    game_server.lock_enforcer().write_characters(
+                                    // The player is still in the source zone, so the effect plays
+                                    // for the players they are leaving
+                                    let mut broadcasts = play_door_effect(
+                                        game_server,
+                                        request.requester,
+                                        enter_composite_effect,
+                                    )?;
+
+                                    let teleport_broadcasts: Result<
+                                        Vec<Broadcast>,
+                                        ProcessPacketError,
+                                    > = game_server.lock_enforcer().write_characters(
                                         |characters_table_write_handle, zones_lock_enforcer| {
                                             zones_lock_enforcer.read_zones(|_| ZoneLockRequest {
                                                 read_guids: vec![destination_zone_guid],
@@ -1015,35 +2377,112 @@ pub fn interact_with_character(
                                                     if let Some(destination_read_handle) =
                                                         zones_read.get(&destination_zone_guid)
                                                     {
-                                                        teleport_to_zone!(
+                                                        let mut broadcasts = Vec::new();
+
+                                                        if let Some(return_door) = return_door {
+                                                            broadcasts.append(
+                                                                &mut return_door.spawn(
+                                                                    characters_table_write_handle,
+                                                                    destination_read_handle,
+                                                                )?,
+                                                            );
+                                                        }
+
+                                                        let teleport_broadcasts: Result<
+                                                            Vec<Broadcast>,
+                                                            ProcessPacketError,
+                                                        > = teleport_to_zone!(
                                                             characters_table_write_handle,
                                                             requester,
                                                             destination_read_handle,
                                                             Some(destination_pos),
                                                             Some(destination_rot),
                                                             game_server.mounts()
-                                                        )
+                                                        );
+                                                        broadcasts
+                                                            .append(&mut teleport_broadcasts?);
+
+                                                        Ok(broadcasts)
                                                     } else {
                                                         Ok(Vec::new())
                                                     }
                                                 },
                                             })
                                         },
-                                    )
+                                    );
+                                    broadcasts.append(&mut teleport_broadcasts?);
+
+                                    broadcasts.append(&mut play_door_effect(
+                                        game_server,
+                                        request.requester,
+                                        exit_composite_effect,
+                                    )?);
+                                    Ok(broadcasts)
                                 })
                             } else {
-                                coerce_to_packet_supplier(move |_| {
-                                    teleport_within_zone(
+                                coerce_to_packet_supplier(move |game_server| {
+                                    let mut broadcasts = play_door_effect(
+                                        game_server,
+                                        request.requester,
+                                        enter_composite_effect,
+                                    )?;
+                                    if let Some(return_door) = return_door {
+                                        broadcasts.append(
+                                            &mut game_server.lock_enforcer().write_characters(
+                                                |characters_table_write_handle,
+                                                 zones_lock_enforcer| {
+                                                    zones_lock_enforcer.read_zones(|_| {
+                                                        ZoneLockRequest {
+                                                            read_guids: vec![destination_zone_guid],
+                                                            write_guids: Vec::new(),
+                                                            zone_consumer: |_, zones_read, _| {
+                                                                match zones_read
+                                                                    .get(&destination_zone_guid)
+                                                                {
+                                                                    Some(zone_read_handle) => {
+                                                                        return_door.spawn(
+                                                                        characters_table_write_handle,
+                                                                        zone_read_handle,
+                                                                    )
+                                                                    }
+                                                                    None => Ok(Vec::new()),
+                                                                }
+                                                            },
+                                                        }
+                                                    })
+                                                },
+                                            )?,
+                                        );
+                                    }
+
+                                    broadcasts.append(&mut teleport_within_zone(
                                         requester,
                                         destination_pos,
                                         destination_rot,
-                                    )
+                                        fade_composite_effect,
+                                    )?);
+                                    broadcasts.append(&mut play_door_effect(
+                                        game_server,
+                                        request.requester,
+                                        exit_composite_effect,
+                                    )?);
+                                    Ok(broadcasts)
                                 })
                             }
                         }
                         CharacterType::Transport(_) => coerce_to_packet_supplier(move |_| {
                             Ok(vec![Broadcast::Single(requester, show_galaxy_map()?)])
                         }),
+                        CharacterType::Npc(npc) => {
+                            let interact_message = npc.interact_message.clone();
+                            coerce_to_packet_supplier(move |_| match interact_message {
+                                Some(message) => Ok(vec![Broadcast::Single(
+                                    requester,
+                                    vec![system_message(message)?],
+                                )]),
+                                None => Ok(Vec::new()),
+                            })
+                        }
                         _ => coerce_to_packet_supplier(|_| Ok(Vec::new())),
                     }
                 } else {
@@ -1060,22 +2499,48 @@ pub fn interact_with_character(
     packet_supplier?(game_server)
 }
 
+// The player is frozen while the fade effect plays so they can't move before the teleport
 pub fn teleport_within_zone(
     sender: u32,
     destination_pos: Pos,
     destination_rot: Pos,
+    fade_composite_effect: Option<u32>,
 ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+    let position = GamePacket::serialize(&TunneledPacket {
+        unknown1: true,
+        inner: Position {
+            player_pos: destination_pos,
+            rot: destination_rot,
+            is_teleport: true,
+            unknown2: true,
+        },
+    })?;
+
+    let Some(fade_composite_effect) = fade_composite_effect else {
+        return Ok(vec![Broadcast::Single(sender, vec![position])]);
+    };
+
     Ok(vec![Broadcast::Single(
         sender,
-        vec![GamePacket::serialize(&TunneledPacket {
-            unknown1: true,
-            inner: Position {
-                player_pos: destination_pos,
-                rot: destination_rot,
-                is_teleport: true,
-                unknown2: true,
-            },
-        })?],
+        vec![
+            GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: Freeze { freeze: true },
+            })?,
+            GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: SlotCompositeEffectOverride {
+                    guid: player_guid(sender),
+                    slot_id: 0,
+                    composite_effect: fade_composite_effect,
+                },
+            })?,
+            position,
+            GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: Freeze { freeze: false },
+            })?,
+        ],
     )])
 }
 
@@ -1102,6 +2567,19 @@ fn enable_interaction(guid: u64, cursor: u8) -> Result<Vec<Vec<u8>>, SerializePa
     })?])
 }
 
+fn disable_interaction(guid: u64) -> Result<Vec<Vec<u8>>, SerializePacketError> {
+    Ok(vec![GamePacket::serialize(&TunneledPacket {
+        unknown1: true,
+        inner: NpcRelevance {
+            new_states: vec![SingleNpcRelevance {
+                guid,
+                cursor: None,
+                unknown1: false,
+            }],
+        },
+    })?])
+}
+
 fn show_galaxy_map() -> Result<Vec<Vec<u8>>, ProcessPacketError> {
     Ok(vec![GamePacket::serialize(&TunneledPacket {
         unknown1: false,
@@ -1112,9 +2590,1891 @@ fn show_galaxy_map() -> Result<Vec<Vec<u8>>, ProcessPacketError> {
     })?])
 }
 
-fn distance3(x1: f32, y1: f32, z1: f32, x2: f32, y2: f32, z2: f32) -> f32 {
-    let diff_x = x2 - x1;
-    let diff_y = y2 - y1;
-    let diff_z = z2 - z1;
-    (diff_x * diff_x + diff_y * diff_y + diff_z * diff_z).sqrt()
+#[cfg(test)]
+pub fn make_test_character(guid: u64, character_type: CharacterType) -> Character {
+    Character {
+        guid,
+        pos: Pos {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+            w: 1.0,
+        },
+        rot: Pos {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 0.0,
+        },
+        state: 0,
+        character_type,
+        mount_id: None,
+        interact_radius: 5.0,
+        auto_interact_radius: 0.0,
+        instance_guid: 24,
+        health: 100,
+        max_health: 100,
+        power: 50,
+        max_power: 50,
+        power_regen_per_second: 4,
+        power_regen_elapsed_millis: 0,
+        health_regen_per_second: 0,
+        health_regen_elapsed_millis: 0,
+        millis_since_damage: 0,
+        zone_load_state: ZoneLoadState::Loaded,
+        in_combat: false,
+        stats: StatEngine::default(),
+        seek_target: None,
+        idle_millis: 0,
+        rail: None,
+        animation_slot: NO_ANIMATION_SLOT,
+        emote_millis_remaining: None,
+        despawned_millis: None,
+        interactable: true,
+    }
+}
+
+#[cfg(test)]
+pub fn make_test_npc_data() -> NpcData {
+    serde_json::from_str(
+        r#"{
+            "model_id": 1234,
+            "name_id": 5,
+            "pos_x": 1.0,
+            "pos_y": 2.0,
+            "pos_z": 3.0,
+            "pos_w": 1.0,
+            "rot_x": 0.0,
+            "rot_y": 0.0,
+            "rot_z": 0.0,
+            "rot_w": 0.0,
+            "cursor": 55,
+            "interact_message": "Hello there"
+        }"#,
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+pub fn make_test_zone(
+    guid: u64,
+    characters_table_write_handle: &mut GuidTableWriteHandle<
+        u64,
+        Character,
+        (u64, CharacterCategory),
+    >,
+) -> Zone {
+    let origin = Pos {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+        w: 1.0,
+    };
+    ZoneTemplate {
+        guid: crate::game_server::unique_guid::zone_template_guid(guid),
+        template_name: 0,
+        template_icon: 0,
+        asset_name: "".to_string(),
+        default_spawn_pos: origin,
+        default_spawn_rot: origin,
+        default_spawn_sky: "".to_string(),
+        speed: 1.0,
+        jump_height_multiplier: 1.0,
+        gravity_multiplier: 1.0,
+        hide_ui: false,
+        combat_hud: false,
+        interest_radius: None,
+        interest_hysteresis: 0.0,
+        characters: Vec::new(),
+    }
+    .to_zone(guid, None, characters_table_write_handle)
+}
+
+#[cfg(test)]
+pub fn move_to(x: f32) -> UpdatePlayerPosition {
+    UpdatePlayerPosition {
+        guid: 1,
+        pos_x: x,
+        pos_y: 2.0,
+        pos_z: 3.0,
+        rot_x: 0.0,
+        rot_y: 0.0,
+        rot_z: 0.0,
+        character_state: 0,
+        unknown: 0,
+    }
+}
+
+#[cfg(test)]
+pub fn make_test_players(guids: &[u64]) -> GuidTable<u64, Character, (u64, CharacterCategory)> {
+    let characters = GuidTable::new();
+    {
+        let mut characters_write_handle = characters.write();
+        for guid in guids {
+            characters_write_handle.insert(make_test_character(
+                *guid,
+                CharacterType::Player(PlayerState::default()),
+            ));
+        }
+    }
+    characters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_server::client_update_packet::{Stat, Stats};
+
+    fn make_test_door(composite_effect: Option<u32>) -> Door {
+        Door {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+            w: 1.0,
+            terrain_object_id: 100,
+            destination_pos_x: 0.0,
+            destination_pos_y: 0.0,
+            destination_pos_z: 0.0,
+            destination_pos_w: 1.0,
+            destination_rot_x: 0.0,
+            destination_rot_y: 0.0,
+            destination_rot_z: 0.0,
+            destination_rot_w: 0.0,
+            destination_zone_template: None,
+            destination_zone: None,
+            composite_effect,
+            attachments: Vec::new(),
+            return_door: false,
+            name_override: None,
+            hide_name: false,
+            enter_composite_effect: None,
+            exit_composite_effect: None,
+            fade_composite_effect: None,
+            return_from: None,
+        }
+    }
+
+    fn serialize_door_packet(composite_effect: Option<u32>) -> Vec<u8> {
+        let door = make_test_door(composite_effect);
+        let character = make_test_character(1, CharacterType::Door(door.clone()));
+        let mut buffer = Vec::new();
+        SerializePacket::serialize(&Character::door_packet(&character, &door), &mut buffer)
+            .unwrap();
+        buffer
+    }
+
+    #[test]
+    fn test_door_packet_with_effect() {
+        let mut effect_bytes = Vec::new();
+        SerializePacket::serialize(&Effect::from_composite_effect(1234), &mut effect_bytes)
+            .unwrap();
+        let mut expected_effect_bytes = vec![0; 28 + 1 + 8 + 12];
+        expected_effect_bytes.extend_from_slice(&1234u32.to_le_bytes());
+        expected_effect_bytes.extend_from_slice(&[0; 8 + 8 + 3]);
+        assert_eq!(effect_bytes, expected_effect_bytes);
+
+        let with_effect = serialize_door_packet(Some(1234));
+        let without_effect = serialize_door_packet(None);
+        assert_eq!(with_effect.len(), without_effect.len() + effect_bytes.len());
+
+        // The first difference is the length of the effects list
+        let offset = with_effect
+            .iter()
+            .zip(without_effect.iter())
+            .position(|(with, without)| with != without)
+            .unwrap();
+        assert_eq!(with_effect[offset..offset + 4], 1u32.to_le_bytes());
+        assert_eq!(without_effect[offset..offset + 4], 0u32.to_le_bytes());
+        assert_eq!(
+            with_effect[offset + 4..offset + 4 + effect_bytes.len()],
+            effect_bytes
+        );
+        assert_eq!(
+            with_effect[offset + 4 + effect_bytes.len()..],
+            without_effect[offset + 4..]
+        );
+    }
+
+    #[test]
+    fn test_terrain_door_becomes_interactable() {
+        let mut character = make_test_character(1, CharacterType::Door(make_test_door(None)));
+        assert_eq!(
+            character.set_interactable(false).unwrap(),
+            disable_interaction(1).unwrap()
+        );
+        assert_eq!(
+            character.to_packets().unwrap().last(),
+            disable_interaction(1).unwrap().last()
+        );
+        assert!(character.set_interactable(false).unwrap().is_empty());
+
+        assert_eq!(
+            character.set_interactable(true).unwrap(),
+            enable_interaction(1, 55).unwrap()
+        );
+        assert_eq!(
+            character.to_packets().unwrap().last(),
+            enable_interaction(1, 55).unwrap().last()
+        );
+    }
+
+    #[test]
+    fn test_door_packet_with_configured_attachment() {
+        let door: Door = serde_json::from_str(
+            r#"{
+                "x": 1.0, "y": 2.0, "z": 3.0, "w": 1.0,
+                "terrain_object_id": 100,
+                "destination_pos_x": 0.0, "destination_pos_y": 0.0,
+                "destination_pos_z": 0.0, "destination_pos_w": 1.0,
+                "destination_rot_x": 0.0, "destination_rot_y": 0.0,
+                "destination_rot_z": 0.0, "destination_rot_w": 0.0,
+                "attachments": [{
+                    "model_name": "Wep_Blaster.adr",
+                    "texture_alias": "Default",
+                    "tint_alias": "",
+                    "tint_id": 0,
+                    "composite_effect": 0,
+                    "slot": 7
+                }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(door.attachments.len(), 1);
+
+        let character = make_test_character(1, CharacterType::Door(door.clone()));
+        let mut buffer = Vec::new();
+        SerializePacket::serialize(&Character::door_packet(&character, &door), &mut buffer)
+            .unwrap();
+
+        let mut attachments_bytes = Vec::new();
+        SerializePacket::serialize(&door.attachments, &mut attachments_bytes).unwrap();
+        assert_eq!(attachments_bytes[..4], 1u32.to_le_bytes());
+        assert!(buffer
+            .windows(attachments_bytes.len())
+            .any(|window| window == attachments_bytes));
+    }
+
+    #[test]
+    fn test_npc_serializes_to_add_npc() {
+        let character = make_test_character(1, CharacterType::Npc(make_test_npc_data()));
+        let packets = character.to_packets().unwrap();
+        assert_eq!(packets.len(), 2);
+
+        // The model ID follows the tunnel header, the player update header, the GUID, and the name ID
+        let model_id_offset = 2 + 1 + 4 + 4 + 8 + 4;
+        assert_eq!(
+            packets[0][model_id_offset..model_id_offset + 4],
+            1234u32.to_le_bytes()
+        );
+        assert_eq!(packets[1], enable_interaction(1, 55).unwrap()[0]);
+    }
+
+    #[test]
+    fn test_npc_fly_over_effect_is_sent_and_clamped() {
+        let mut npc = make_test_npc_data();
+        npc.fly_over_effect = 2;
+        npc.clamp_to_packet_limits();
+        let character = make_test_character(1, CharacterType::Npc(npc.clone()));
+        assert_eq!(Character::npc_packet(&character, &npc).fly_over_effect, 2);
+
+        npc.fly_over_effect = 9;
+        npc.clamp_to_packet_limits();
+        assert_eq!(npc.fly_over_effect, MAX_FLY_OVER_EFFECT);
+        assert_eq!(
+            Character::npc_packet(&character, &npc).fly_over_effect,
+            MAX_FLY_OVER_EFFECT
+        );
+    }
+
+    #[test]
+    fn test_npc_hover_glow_and_description_are_sent_and_clamped() {
+        let mut npc = make_test_npc_data();
+        npc.hover_glow = 1;
+        npc.hover_description = 4;
+        npc.clamp_to_packet_limits();
+        let character = make_test_character(1, CharacterType::Npc(npc.clone()));
+        let add_npc = Character::npc_packet(&character, &npc);
+        assert_eq!(add_npc.hover_glow, 1);
+        assert_eq!(add_npc.hover_description, 4);
+
+        npc.hover_description = 12;
+        npc.clamp_to_packet_limits();
+        assert_eq!(npc.hover_glow, 1);
+        assert_eq!(
+            Character::npc_packet(&character, &npc).hover_description,
+            MAX_HOVER_DESCRIPTION
+        );
+    }
+
+    #[test]
+    fn test_npc_sub_title_is_sent() {
+        let mut npc = make_test_npc_data();
+        npc.sub_title_id = 42;
+        let character = make_test_character(1, CharacterType::Npc(npc.clone()));
+        let add_npc = Character::npc_packet(&character, &npc);
+        assert_eq!(add_npc.sub_title_id, 42);
+
+        let expected_spawn = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: add_npc,
+        })
+        .unwrap();
+        assert_eq!(character.to_packets().unwrap()[0], expected_spawn);
+    }
+
+    #[test]
+    fn test_member_npc_serializes_member_icon() {
+        let npc: NpcData = serde_json::from_str(
+            r#"{
+                "model_id": 1234,
+                "pos_x": 1.0,
+                "pos_y": 2.0,
+                "pos_z": 3.0,
+                "pos_w": 1.0,
+                "rot_x": 0.0,
+                "rot_y": 0.0,
+                "rot_z": 0.0,
+                "rot_w": 0.0,
+                "icon": "Member"
+            }"#,
+        )
+        .unwrap();
+        let character = make_test_character(1, CharacterType::Npc(npc.clone()));
+        assert_eq!(
+            Character::npc_packet(&character, &npc).icon_id,
+            Icon::Member
+        );
+        assert_eq!(
+            Character::npc_packet(&character, &make_test_npc_data()).icon_id,
+            Icon::None
+        );
+    }
+
+    #[test]
+    fn test_damageable_npc_shows_health_by_default() {
+        let npc = make_test_npc_data();
+        let mut character = make_test_character(1, CharacterType::Npc(npc.clone()));
+        assert!(Character::npc_packet(&character, &npc).show_health);
+
+        character.max_health = 0;
+        assert!(!Character::npc_packet(&character, &npc).show_health);
+
+        let mut hidden_health_npc = npc.clone();
+        hidden_health_npc.show_health = Some(false);
+        character.max_health = 100;
+        assert!(!Character::npc_packet(&character, &hidden_health_npc).show_health);
+
+        let mut shown_health_npc = npc;
+        shown_health_npc.show_health = Some(true);
+        character.max_health = 0;
+        assert!(Character::npc_packet(&character, &shown_health_npc).show_health);
+    }
+
+    #[test]
+    fn test_damaged_npc_health_is_not_sent_as_player_health() {
+        let characters = make_test_players(&[1, 2]);
+        let characters_table_read_handle = CharacterTableReadHandle::from(characters.read());
+        let mut npc = make_test_npc_data();
+        npc.show_health = Some(true);
+        let mut character = make_test_character(3, CharacterType::Npc(npc));
+
+        // Sending Health would set the nearby players' own health to the NPC's
+        assert!(character
+            .apply_damage(40, &characters_table_read_handle)
+            .unwrap()
+            .is_empty());
+        assert_eq!(character.health, 60);
+    }
+
+    #[test]
+    fn test_damage_clamps_health_and_kills() {
+        let characters = make_test_players(&[1, 2]);
+        let characters_table_read_handle = CharacterTableReadHandle::from(characters.read());
+        let mut character = make_test_character(1, CharacterType::Player(PlayerState::default()));
+
+        let broadcasts = character
+            .apply_damage(40, &characters_table_read_handle)
+            .unwrap();
+        assert_eq!(character.health, 60);
+        assert!(!character.is_dead());
+        assert!(matches!(&broadcasts[..], [Broadcast::Single(1, packets)] if packets.len() == 1));
+
+        // Everyone in the zone sees that the player died
+        let dead_state = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: UpdateCharacterState {
+                guid: 1,
+                bitflags: DEAD_STATE_BITFLAG,
+            },
+        })
+        .unwrap();
+        let broadcasts = character
+            .apply_damage(1000, &characters_table_read_handle)
+            .unwrap();
+        assert_eq!(character.health, 0);
+        assert!(character.is_dead());
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, _), Broadcast::Multi(players, packets)]
+                if players == &vec![1, 2] && packets == &vec![dead_state]));
+
+        character
+            .apply_heal(1000, &characters_table_read_handle)
+            .unwrap();
+        assert_eq!(character.health, 100);
+        assert!(!character.is_dead());
+    }
+
+    #[test]
+    fn test_only_npcs_with_work_can_advance() {
+        let characters = make_test_players(&[]);
+        let characters_table_read_handle = CharacterTableReadHandle::from(characters.read());
+        let door = make_test_character(1, CharacterType::Door(make_test_door(None)));
+        assert!(!door.can_advance());
+
+        let mut npc_data = make_test_npc_data();
+        npc_data.respawn_seconds = Some(5);
+        let mut npc = make_test_character(2, CharacterType::Npc(npc_data));
+        assert!(!npc.can_advance());
+
+        npc.apply_damage(1000, &characters_table_read_handle)
+            .unwrap();
+        assert!(npc.can_advance());
+
+        let mut npc = make_test_character(3, CharacterType::Npc(make_test_npc_data()));
+        npc.apply_damage(1000, &characters_table_read_handle)
+            .unwrap();
+        npc.advance_respawn(0).unwrap();
+        assert!(!npc.can_advance());
+    }
+
+    fn health_packet(current: u32, max: u32) -> Vec<u8> {
+        GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: Health { current, max },
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_health_regen_is_suppressed_after_damage() {
+        let characters = make_test_players(&[]);
+        let characters_table_read_handle = CharacterTableReadHandle::from(characters.read());
+        let mut character = make_test_character(1, CharacterType::Player(PlayerState::default()));
+        character.health_regen_per_second = 10;
+        character
+            .stats
+            .set(StatId::HealthRegen, StatSource::Equipment(1), 2.0);
+        character
+            .apply_damage(50, &characters_table_read_handle)
+            .unwrap();
+
+        // Regeneration waits until the character hasn't been damaged for the delay
+        assert!(character.regenerate_health(4000, 5000).unwrap().is_empty());
+        assert_eq!(character.health, 50);
+
+        let broadcasts = character.regenerate_health(1000, 5000).unwrap();
+        assert_eq!(character.health, 70);
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)] if packets == &vec![health_packet(70, 100)]));
+
+        // Not enough time has passed to regenerate a whole point of health
+        assert!(character.regenerate_health(40, 5000).unwrap().is_empty());
+        character.regenerate_health(10, 5000).unwrap();
+        assert_eq!(character.health, 71);
+
+        character
+            .apply_damage(1, &characters_table_read_handle)
+            .unwrap();
+        assert!(character.regenerate_health(1000, 5000).unwrap().is_empty());
+        assert_eq!(character.health, 70);
+
+        character.regenerate_health(5000, 5000).unwrap();
+        assert_eq!(character.health, 100);
+        assert!(character.regenerate_health(1000, 5000).unwrap().is_empty());
+    }
+
+    fn power_packet(current: u32, max: u32) -> Vec<u8> {
+        GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: Power { current, max },
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_consume_and_regenerate_power() {
+        let mut character = make_test_character(1, CharacterType::Player(PlayerState::default()));
+        assert!(!character.consume_power(51));
+        assert!(character.consume_power(10));
+        assert_eq!(character.power, 40);
+
+        // Not enough time has passed to regenerate a whole point of power
+        assert!(character.regenerate_power(200).unwrap().is_empty());
+
+        let broadcasts = character.regenerate_power(300).unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)] if packets == &vec![power_packet(42, 50)]));
+
+        let broadcasts = character.regenerate_power(10000).unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)] if packets == &vec![power_packet(50, 50)]));
+
+        // Identical values are not sent again
+        assert!(character.regenerate_power(10000).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_player_broadcast_targets_only_players() {
+        let characters = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            characters_write_handle.insert(make_test_character(
+                1,
+                CharacterType::Player(PlayerState::default()),
+            ));
+            characters_write_handle.insert(make_test_character(
+                2,
+                CharacterType::Player(PlayerState::default()),
+            ));
+            characters_write_handle.insert(make_test_character(
+                npc_guid(AMBIENT_NPC_DISCRIMINANT, 24, 0),
+                CharacterType::Door(make_test_door(None)),
+            ));
+
+            let mut auto_interact_door = make_test_character(
+                npc_guid(AMBIENT_NPC_DISCRIMINANT, 24, 1),
+                CharacterType::Door(make_test_door(None)),
+            );
+            auto_interact_door.auto_interact_radius = 2.0;
+            characters_write_handle.insert(auto_interact_door);
+
+            let mut other_zone_player =
+                make_test_character(3, CharacterType::Player(PlayerState::default()));
+            other_zone_player.instance_guid = 25;
+            characters_write_handle.insert(other_zone_player);
+        }
+
+        let characters_table_read_handle: CharacterTableReadHandle = characters.read().into();
+        let broadcasts =
+            Zone::player_broadcast(24, &characters_table_read_handle, vec![vec![1]], None);
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Multi(guids, packets)] if guids == &vec![1, 2] && packets == &vec![vec![1]]));
+
+        assert!(
+            Zone::player_broadcast(26, &characters_table_read_handle, vec![vec![1]], None)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_player_broadcast_excludes_sender() {
+        let characters = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            characters_write_handle.insert(make_test_character(
+                1,
+                CharacterType::Player(PlayerState::default()),
+            ));
+            characters_write_handle.insert(make_test_character(
+                2,
+                CharacterType::Player(PlayerState::default()),
+            ));
+            characters_write_handle.insert(make_test_character(
+                3,
+                CharacterType::Player(PlayerState::default()),
+            ));
+        }
+
+        let characters_table_read_handle: CharacterTableReadHandle = characters.read().into();
+        let broadcasts =
+            Zone::player_broadcast(24, &characters_table_read_handle, vec![vec![1]], Some(2));
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Multi(guids, _)] if guids == &vec![1, 3]));
+
+        // Nothing is sent if the excluded player is the only one in the zone
+        let characters = GuidTable::new();
+        characters.write().insert(make_test_character(
+            1,
+            CharacterType::Player(PlayerState::default()),
+        ));
+        let characters_table_read_handle: CharacterTableReadHandle = characters.read().into();
+        assert!(
+            Zone::player_broadcast(24, &characters_table_read_handle, vec![vec![1]], Some(1))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_composite_effect_broadcasts_to_zone() {
+        let characters = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            characters_write_handle.insert(make_test_character(
+                1,
+                CharacterType::Player(PlayerState::default()),
+            ));
+            characters_write_handle.insert(make_test_character(
+                2,
+                CharacterType::Player(PlayerState::default()),
+            ));
+            characters_write_handle.insert(make_test_character(
+                npc_guid(AMBIENT_NPC_DISCRIMINANT, 24, 0),
+                CharacterType::Door(make_test_door(None)),
+            ));
+
+            let mut other_zone_player =
+                make_test_character(3, CharacterType::Player(PlayerState::default()));
+            other_zone_player.instance_guid = 25;
+            characters_write_handle.insert(other_zone_player);
+        }
+
+        let characters_table_read_handle: CharacterTableReadHandle = characters.read().into();
+        let target = npc_guid(AMBIENT_NPC_DISCRIMINANT, 24, 0);
+        let broadcasts =
+            play_composite_effect(&characters_table_read_handle, target, 2696).unwrap();
+        let expected_packet = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: SlotCompositeEffectOverride {
+                guid: target,
+                slot_id: 0,
+                composite_effect: 2696,
+            },
+        })
+        .unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Multi(guids, packets)] if guids == &vec![1, 2] && packets == &vec![expected_packet]));
+
+        assert!(play_composite_effect(&characters_table_read_handle, 4, 2696).is_err());
+    }
+
+    #[test]
+    fn test_far_away_requester_cannot_use_door() {
+        let door_guid = npc_guid(AMBIENT_NPC_DISCRIMINANT, 24, 0);
+        let characters = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            let mut requester =
+                make_test_character(1, CharacterType::Player(PlayerState::default()));
+            requester.pos.x = 1000.0;
+            characters_write_handle.insert(requester);
+            characters_write_handle.insert(make_test_character(
+                door_guid,
+                CharacterType::Door(make_test_door(None)),
+            ));
+        }
+
+        let game_server = GameServer::for_test(characters, GuidTable::new());
+        let broadcasts = interact_with_character(
+            SelectPlayer {
+                requester: 1,
+                target: door_guid,
+            },
+            &game_server,
+        )
+        .unwrap();
+
+        let expected_packets =
+            vec![system_message(TOO_FAR_TO_INTERACT_MESSAGE.to_string()).unwrap()];
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)] if packets == &expected_packets));
+    }
+
+    fn select_player(requester: u64, target: u64) -> Vec<u8> {
+        GamePacket::serialize(&SelectPlayer { requester, target }).unwrap()
+    }
+
+    #[test]
+    fn test_select_door_teleports_requester() {
+        let door_guid = npc_guid(AMBIENT_NPC_DISCRIMINANT, 24, 0);
+        let other_zone_door_guid = npc_guid(AMBIENT_NPC_DISCRIMINANT, 25, 0);
+        let characters = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            characters_write_handle.insert(make_test_character(
+                1,
+                CharacterType::Player(PlayerState::default()),
+            ));
+            characters_write_handle.insert(make_test_character(
+                door_guid,
+                CharacterType::Door(make_test_door(None)),
+            ));
+            let mut other_zone_door = make_test_character(
+                other_zone_door_guid,
+                CharacterType::Door(make_test_door(None)),
+            );
+            other_zone_door.instance_guid = 25;
+            characters_write_handle.insert(other_zone_door);
+        }
+
+        let game_server = GameServer::for_test(characters, GuidTable::new());
+
+        let broadcasts = game_server
+            .process_packet(1, select_player(1, door_guid))
+            .unwrap();
+        let expected_broadcasts = teleport_within_zone(
+            1,
+            Pos {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                w: 1.0,
+            },
+            Pos {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                w: 0.0,
+            },
+            None,
+        )
+        .unwrap();
+        assert!(matches!((&broadcasts[..], &expected_broadcasts[..]),
+            ([Broadcast::Single(1, packets)], [Broadcast::Single(1, expected_packets)])
+                if packets == expected_packets));
+
+        // Players can only select characters as themselves and in their own zone
+        assert!(game_server
+            .process_packet(2, select_player(1, door_guid))
+            .is_err());
+        assert!(game_server
+            .process_packet(1, select_player(1, other_zone_door_guid))
+            .is_err());
+    }
+
+    fn instance_guid(game_server: &GameServer, guid: u64) -> Option<u64> {
+        game_server
+            .lock_enforcer()
+            .read_characters(|characters_table_read_handle| {
+                let index = characters_table_read_handle.index(guid);
+                CharacterLockRequest {
+                    read_guids: Vec::new(),
+                    write_guids: Vec::new(),
+                    character_consumer: move |_, _, _, _| {
+                        index.map(|(instance_guid, _)| instance_guid)
+                    },
+                }
+            })
+    }
+
+    #[test]
+    fn test_fade_teleport_orders_freeze_effect_position_and_unfreeze() {
+        let pos = Pos {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+            w: 1.0,
+        };
+        let rot = Pos {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 0.0,
+        };
+        let broadcasts = teleport_within_zone(1, pos, rot, Some(2696)).unwrap();
+
+        let freeze_packet = |freeze| {
+            GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: Freeze { freeze },
+            })
+            .unwrap()
+        };
+        let expected_packets = vec![
+            freeze_packet(true),
+            GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: SlotCompositeEffectOverride {
+                    guid: 1,
+                    slot_id: 0,
+                    composite_effect: 2696,
+                },
+            })
+            .unwrap(),
+            GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: Position {
+                    player_pos: pos,
+                    rot,
+                    is_teleport: true,
+                    unknown2: true,
+                },
+            })
+            .unwrap(),
+            freeze_packet(false),
+        ];
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)] if packets == &expected_packets));
+    }
+
+    #[test]
+    fn test_concurrent_cross_zone_teleports_do_not_deadlock() {
+        let first_zone_guid = zone_instance_guid(0, 1);
+        let second_zone_guid = zone_instance_guid(0, 2);
+        let first_door_guid = npc_guid(AMBIENT_NPC_DISCRIMINANT, first_zone_guid, 0);
+        let second_door_guid = npc_guid(AMBIENT_NPC_DISCRIMINANT, second_zone_guid, 0);
+
+        let characters = GuidTable::new();
+        let zones = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            let mut zones_write_handle = zones.write();
+            for (zone_guid, door_guid, destination_zone_guid) in [
+                (first_zone_guid, first_door_guid, second_zone_guid),
+                (second_zone_guid, second_door_guid, first_zone_guid),
+            ] {
+                zones_write_handle.insert(make_test_zone(zone_guid, &mut characters_write_handle));
+
+                // Each door leads to the other door, so players can bounce between the zones
+                let mut door = make_test_door(None);
+                door.destination_zone = Some(destination_zone_guid);
+                door.destination_pos_x = door.x;
+                door.destination_pos_y = door.y;
+                door.destination_pos_z = door.z;
+                let mut door_character = make_test_character(door_guid, CharacterType::Door(door));
+                door_character.instance_guid = zone_guid;
+                characters_write_handle.insert(door_character);
+            }
+
+            for guid in 1..=4 {
+                let mut player =
+                    make_test_character(guid, CharacterType::Player(PlayerState::default()));
+                player.instance_guid = if guid % 2 == 0 {
+                    first_zone_guid
+                } else {
+                    second_zone_guid
+                };
+                characters_write_handle.insert(player);
+            }
+        }
+
+        let game_server = std::sync::Arc::new(GameServer::for_test(characters, zones));
+
+        let (done_sender, done_receiver) = std::sync::mpsc::channel();
+        for guid in 1..=4 {
+            let game_server = game_server.clone();
+            let done_sender = done_sender.clone();
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    let door_guid = if instance_guid(&game_server, guid) == Some(first_zone_guid) {
+                        first_door_guid
+                    } else {
+                        second_door_guid
+                    };
+                    interact_with_character(
+                        SelectPlayer {
+                            requester: guid,
+                            target: door_guid,
+                        },
+                        &game_server,
+                    )
+                    .unwrap();
+                }
+                done_sender.send(guid).unwrap();
+            });
+        }
+
+        for _ in 1..=4 {
+            done_receiver
+                .recv_timeout(std::time::Duration::from_secs(30))
+                .expect("Teleports deadlocked");
+        }
+    }
+
+    #[test]
+    fn test_return_door_leads_back_to_source() {
+        let source_zone_guid = zone_instance_guid(0, 1);
+        let destination_zone_guid = zone_instance_guid(0, 2);
+        let source_door_guid = npc_guid(AMBIENT_NPC_DISCRIMINANT, source_zone_guid, 3);
+
+        // The first return door spawned in the destination zone takes its first index
+        let return_door_guid = npc_guid(TRANSIENT_NPC_DISCRIMINANT, destination_zone_guid, 0);
+
+        let characters = GuidTable::new();
+        let zones = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            let mut zones_write_handle = zones.write();
+            zones_write_handle.insert(make_test_zone(
+                source_zone_guid,
+                &mut characters_write_handle,
+            ));
+            zones_write_handle.insert(make_test_zone(
+                destination_zone_guid,
+                &mut characters_write_handle,
+            ));
+
+            let mut player = make_test_character(1, CharacterType::Player(PlayerState::default()));
+            player.instance_guid = source_zone_guid;
+            characters_write_handle.insert(player);
+
+            let mut door = make_test_door(None);
+            door.destination_zone = Some(destination_zone_guid);
+            door.return_door = true;
+            let mut door_character =
+                make_test_character(source_door_guid, CharacterType::Door(door));
+            door_character.instance_guid = source_zone_guid;
+            characters_write_handle.insert(door_character);
+        }
+
+        let game_server = GameServer::for_test(characters, zones);
+        interact_with_character(
+            SelectPlayer {
+                requester: 1,
+                target: source_door_guid,
+            },
+            &game_server,
+        )
+        .unwrap();
+        assert_eq!(instance_guid(&game_server, 1), Some(destination_zone_guid));
+        assert_eq!(
+            instance_guid(&game_server, return_door_guid),
+            Some(destination_zone_guid)
+        );
+
+        // The player arrives at the return door, which should lead back to the source door
+        game_server
+            .lock_enforcer()
+            .read_characters(|_| CharacterLockRequest {
+                read_guids: vec![return_door_guid],
+                write_guids: vec![1],
+                character_consumer: |_, characters_read, mut characters_write, _| {
+                    let return_door_read_handle = characters_read.get(&return_door_guid).unwrap();
+                    if let CharacterType::Door(door) = &return_door_read_handle.character_type {
+                        assert_eq!(door.destination_zone, Some(source_zone_guid));
+                        assert_eq!(door.destination_pos_x, 1.0);
+                        assert_eq!(door.destination_pos_y, 2.0);
+                        assert_eq!(door.destination_pos_z, 3.0);
+                    } else {
+                        panic!("Return door is not a door");
+                    }
+
+                    characters_write.get_mut(&1).unwrap().pos = return_door_read_handle.pos;
+                },
+            });
+
+        interact_with_character(
+            SelectPlayer {
+                requester: 1,
+                target: return_door_guid,
+            },
+            &game_server,
+        )
+        .unwrap();
+        assert_eq!(instance_guid(&game_server, 1), Some(source_zone_guid));
+
+        // The last player left the destination zone, so the return door is no longer needed
+        assert_eq!(instance_guid(&game_server, return_door_guid), None);
+    }
+
+    // Returns the zone that each return door in the given zone leads back to
+    fn return_door_destinations(game_server: &GameServer, zone_guid: u64) -> Vec<u64> {
+        game_server
+            .lock_enforcer()
+            .write_characters(|characters_table_write_handle, _| {
+                return_door_guids(characters_table_write_handle, zone_guid)
+                    .into_iter()
+                    .filter_map(|(guid, _)| {
+                        match &characters_table_write_handle
+                            .get(guid)
+                            .unwrap()
+                            .read()
+                            .character_type
+                        {
+                            CharacterType::Door(door) => door.destination_zone,
+                            _ => None,
+                        }
+                    })
+                    .collect()
+            })
+    }
+
+    fn return_door_server(source_zone_guids: &[u64], destination_zone_guid: u64) -> GameServer {
+        let characters = GuidTable::new();
+        let zones = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            let mut zones_write_handle = zones.write();
+            zones_write_handle.insert(make_test_zone(
+                destination_zone_guid,
+                &mut characters_write_handle,
+            ));
+
+            for (player_guid, source_zone_guid) in (1..).zip(source_zone_guids) {
+                zones_write_handle.insert(make_test_zone(
+                    *source_zone_guid,
+                    &mut characters_write_handle,
+                ));
+
+                let mut player =
+                    make_test_character(player_guid, CharacterType::Player(PlayerState::default()));
+                player.instance_guid = *source_zone_guid;
+                characters_write_handle.insert(player);
+
+                let mut door = make_test_door(None);
+                door.destination_zone = Some(destination_zone_guid);
+                door.return_door = true;
+                let mut door_character = make_test_character(
+                    npc_guid(AMBIENT_NPC_DISCRIMINANT, *source_zone_guid, 0),
+                    CharacterType::Door(door),
+                );
+                door_character.instance_guid = *source_zone_guid;
+                characters_write_handle.insert(door_character);
+            }
+        }
+
+        GameServer::for_test(characters, zones)
+    }
+
+    #[test]
+    fn test_return_doors_from_different_zones_do_not_collide() {
+        let first_zone_guid = zone_instance_guid(0, 1);
+        let second_zone_guid = zone_instance_guid(0, 2);
+        let destination_zone_guid = zone_instance_guid(0, 3);
+        let game_server =
+            return_door_server(&[first_zone_guid, second_zone_guid], destination_zone_guid);
+
+        // Both source doors have the same index in their own zones
+        for (requester, source_zone_guid) in [(1, first_zone_guid), (2, second_zone_guid)] {
+            interact_with_character(
+                SelectPlayer {
+                    requester,
+                    target: npc_guid(AMBIENT_NPC_DISCRIMINANT, source_zone_guid, 0),
+                },
+                &game_server,
+            )
+            .unwrap();
+        }
+
+        let mut destinations = return_door_destinations(&game_server, destination_zone_guid);
+        destinations.sort();
+        assert_eq!(destinations, vec![first_zone_guid, second_zone_guid]);
+    }
+
+    #[test]
+    fn test_return_door_is_removed_when_last_player_logs_out() {
+        let source_zone_guid = zone_instance_guid(0, 1);
+        let destination_zone_guid = zone_instance_guid(0, 2);
+        let game_server = return_door_server(&[source_zone_guid], destination_zone_guid);
+
+        use_door(
+            &game_server,
+            npc_guid(AMBIENT_NPC_DISCRIMINANT, source_zone_guid, 0),
+        )
+        .unwrap();
+        assert_eq!(
+            return_door_destinations(&game_server, destination_zone_guid),
+            vec![source_zone_guid]
+        );
+
+        game_server.logout(1).unwrap();
+        assert!(return_door_destinations(&game_server, destination_zone_guid).is_empty());
+    }
+
+    #[test]
+    fn test_teleport_to_fast_zone_sends_speed() {
+        let default_zone_guid = zone_instance_guid(0, 1);
+        let fast_zone_guid = zone_instance_guid(0, 2);
+        let door_guid = npc_guid(AMBIENT_NPC_DISCRIMINANT, default_zone_guid, 0);
+
+        let characters = GuidTable::new();
+        let zones = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            let mut zones_write_handle = zones.write();
+            zones_write_handle.insert(make_test_zone(
+                default_zone_guid,
+                &mut characters_write_handle,
+            ));
+            let mut fast_zone = make_test_zone(fast_zone_guid, &mut characters_write_handle);
+            fast_zone.speed = 2.0;
+            zones_write_handle.insert(fast_zone);
+
+            let mut player = make_test_character(1, CharacterType::Player(PlayerState::default()));
+            player.instance_guid = default_zone_guid;
+            characters_write_handle.insert(player);
+
+            let mut door = make_test_door(None);
+            door.destination_zone = Some(fast_zone_guid);
+            let mut door_character = make_test_character(door_guid, CharacterType::Door(door));
+            door_character.instance_guid = default_zone_guid;
+            characters_write_handle.insert(door_character);
+        }
+
+        let game_server = GameServer::for_test(characters, zones);
+        let broadcasts = interact_with_character(
+            SelectPlayer {
+                requester: 1,
+                target: door_guid,
+            },
+            &game_server,
+        )
+        .unwrap();
+
+        let expected_stats = zone_stats_packet(2.0, 1.0, 1.0);
+        assert!(broadcasts.iter().any(|broadcast| matches!(broadcast,
+            Broadcast::Single(1, packets) if packets.contains(&expected_stats))));
+    }
+
+    #[test]
+    fn test_changed_door_destination_routes_next_interaction() {
+        let default_zone_guid = zone_instance_guid(0, 1);
+        let fast_zone_guid = zone_instance_guid(0, 2);
+        let door_guid = npc_guid(AMBIENT_NPC_DISCRIMINANT, default_zone_guid, 0);
+
+        let characters = GuidTable::new();
+        let zones = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            let mut zones_write_handle = zones.write();
+            let default_zone = make_test_zone(default_zone_guid, &mut characters_write_handle);
+            let mut fast_zone = make_test_zone(fast_zone_guid, &mut characters_write_handle);
+            fast_zone.speed = 2.0;
+
+            let mut player = make_test_character(1, CharacterType::Player(PlayerState::default()));
+            player.instance_guid = default_zone_guid;
+            characters_write_handle.insert(player);
+
+            let mut door_character =
+                make_test_character(door_guid, CharacterType::Door(make_test_door(None)));
+            door_character.instance_guid = default_zone_guid;
+            characters_write_handle.insert(door_character);
+
+            let destination = Pos {
+                x: 5.0,
+                y: 6.0,
+                z: 7.0,
+                w: 1.0,
+            };
+            assert!(default_zone
+                .set_door_destination(
+                    &mut characters_write_handle,
+                    1,
+                    fast_zone_guid,
+                    destination,
+                    destination
+                )
+                .is_err());
+            assert!(fast_zone
+                .set_door_destination(
+                    &mut characters_write_handle,
+                    door_guid,
+                    fast_zone_guid,
+                    destination,
+                    destination
+                )
+                .is_err());
+            default_zone
+                .set_door_destination(
+                    &mut characters_write_handle,
+                    door_guid,
+                    fast_zone_guid,
+                    destination,
+                    destination,
+                )
+                .unwrap();
+
+            zones_write_handle.insert(default_zone);
+            zones_write_handle.insert(fast_zone);
+        }
+
+        let game_server = GameServer::for_test(characters, zones);
+        let broadcasts = interact_with_character(
+            SelectPlayer {
+                requester: 1,
+                target: door_guid,
+            },
+            &game_server,
+        )
+        .unwrap();
+
+        let expected_stats = zone_stats_packet(2.0, 1.0, 1.0);
+        assert!(broadcasts.iter().any(|broadcast| matches!(broadcast,
+            Broadcast::Single(1, packets) if packets.contains(&expected_stats))));
+        game_server
+            .lock_enforcer()
+            .read_characters(|_| CharacterLockRequest {
+                read_guids: vec![1],
+                write_guids: Vec::new(),
+                character_consumer: |_, characters_read, _, _| {
+                    let player = characters_read.get(&1).unwrap();
+                    assert_eq!(player.instance_guid, fast_zone_guid);
+                    assert_eq!(player.pos.x, 5.0);
+                },
+            });
+    }
+
+    #[test]
+    fn test_door_effects_broadcast_on_teleport() {
+        let default_zone_guid = zone_instance_guid(0, 1);
+        let fast_zone_guid = zone_instance_guid(0, 2);
+        let door_guid = npc_guid(AMBIENT_NPC_DISCRIMINANT, default_zone_guid, 0);
+
+        let characters = GuidTable::new();
+        let zones = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            let mut zones_write_handle = zones.write();
+            let default_zone = make_test_zone(default_zone_guid, &mut characters_write_handle);
+            let fast_zone = make_test_zone(fast_zone_guid, &mut characters_write_handle);
+
+            for guid in [1, 2] {
+                let mut player =
+                    make_test_character(guid, CharacterType::Player(PlayerState::default()));
+                player.instance_guid = default_zone_guid;
+                characters_write_handle.insert(player);
+            }
+
+            let mut door = make_test_door(None);
+            door.destination_zone = Some(fast_zone_guid);
+            door.enter_composite_effect = Some(1234);
+            door.exit_composite_effect = Some(5678);
+            let mut door_character = make_test_character(door_guid, CharacterType::Door(door));
+            door_character.instance_guid = default_zone_guid;
+            characters_write_handle.insert(door_character);
+
+            zones_write_handle.insert(default_zone);
+            zones_write_handle.insert(fast_zone);
+        }
+
+        let game_server = GameServer::for_test(characters, zones);
+        let broadcasts = interact_with_character(
+            SelectPlayer {
+                requester: 1,
+                target: door_guid,
+            },
+            &game_server,
+        )
+        .unwrap();
+
+        let effect_packet = |composite_effect| {
+            GamePacket::serialize(&TunneledPacket {
+                unknown1: true,
+                inner: SlotCompositeEffectOverride {
+                    guid: 1,
+                    slot_id: 0,
+                    composite_effect,
+                },
+            })
+            .unwrap()
+        };
+
+        // The players left behind in the source zone see the player leave
+        let enter_packet = effect_packet(1234);
+        assert!(matches!(&broadcasts[0],
+            Broadcast::Multi(guids, packets) if guids == &vec![1, 2] && packets == &vec![enter_packet]));
+
+        // Only the player has arrived in the destination zone
+        let exit_packet = effect_packet(5678);
+        assert!(matches!(broadcasts.last().unwrap(),
+            Broadcast::Multi(guids, packets) if guids == &vec![1] && packets == &vec![exit_packet]));
+    }
+
+    // The door teleport tests below share a source zone with a player and a door. The destination
+    // zone is created and then destroyed again if it should no longer exist.
+    const DOOR_SOURCE_ZONE_GUID: u64 = zone_instance_guid(0, 1);
+    const DOOR_DESTINATION_ZONE_GUID: u64 = zone_instance_guid(0, 2);
+
+    fn door_teleport_server(door: Door, destination_exists: bool) -> (GameServer, u64) {
+        let door_guid = npc_guid(AMBIENT_NPC_DISCRIMINANT, DOOR_SOURCE_ZONE_GUID, 0);
+        let characters = GuidTable::new();
+        let zones = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            let mut zones_write_handle = zones.write();
+            zones_write_handle.insert(make_test_zone(
+                DOOR_SOURCE_ZONE_GUID,
+                &mut characters_write_handle,
+            ));
+            zones_write_handle.insert(make_test_zone(
+                DOOR_DESTINATION_ZONE_GUID,
+                &mut characters_write_handle,
+            ));
+            if !destination_exists {
+                zones_write_handle.remove(DOOR_DESTINATION_ZONE_GUID);
+            }
+
+            let mut player = make_test_character(1, CharacterType::Player(PlayerState::default()));
+            player.instance_guid = DOOR_SOURCE_ZONE_GUID;
+            characters_write_handle.insert(player);
+
+            let mut door_character = make_test_character(door_guid, CharacterType::Door(door));
+            door_character.instance_guid = DOOR_SOURCE_ZONE_GUID;
+            characters_write_handle.insert(door_character);
+        }
+
+        let game_server = GameServer::for_test(characters, zones);
+        (game_server, door_guid)
+    }
+
+    fn players_in_instance(game_server: &GameServer, instance_guid: u64) -> Vec<u64> {
+        game_server
+            .lock_enforcer()
+            .read_characters(|characters_table_read_handle| {
+                let players = characters_table_read_handle
+                    .keys_by_index((instance_guid, CharacterCategory::Player))
+                    .collect();
+                CharacterLockRequest {
+                    read_guids: Vec::new(),
+                    write_guids: Vec::new(),
+                    character_consumer: move |_, _, _, _| players,
+                }
+            })
+    }
+
+    fn use_door(
+        game_server: &GameServer,
+        door_guid: u64,
+    ) -> Result<Vec<Broadcast>, ProcessPacketError> {
+        interact_with_character(
+            SelectPlayer {
+                requester: 1,
+                target: door_guid,
+            },
+            game_server,
+        )
+    }
+
+    #[test]
+    fn test_door_teleport_within_zone() {
+        let mut door = make_test_door(None);
+        door.destination_pos_x = 5.0;
+        door.destination_pos_y = 6.0;
+        door.destination_pos_z = 7.0;
+        let (game_server, door_guid) = door_teleport_server(door, true);
+
+        let broadcasts = use_door(&game_server, door_guid).unwrap();
+        let expected_broadcasts = teleport_within_zone(
+            1,
+            Pos {
+                x: 5.0,
+                y: 6.0,
+                z: 7.0,
+                w: 1.0,
+            },
+            Pos {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                w: 0.0,
+            },
+            None,
+        )
+        .unwrap();
+        assert!(matches!((&broadcasts[..], &expected_broadcasts[..]),
+            ([Broadcast::Single(1, packets)], [Broadcast::Single(1, expected_packets)])
+                if packets == expected_packets));
+        assert_eq!(
+            players_in_instance(&game_server, DOOR_SOURCE_ZONE_GUID),
+            vec![1]
+        );
+        assert!(players_in_instance(&game_server, DOOR_DESTINATION_ZONE_GUID).is_empty());
+    }
+
+    #[test]
+    fn test_door_teleport_across_zones() {
+        let mut door = make_test_door(None);
+        door.destination_zone = Some(DOOR_DESTINATION_ZONE_GUID);
+        let (game_server, door_guid) = door_teleport_server(door, true);
+
+        let broadcasts = use_door(&game_server, door_guid).unwrap();
+        let expected_stats = zone_stats_packet(1.0, 1.0, 1.0);
+        assert!(broadcasts.iter().any(|broadcast| matches!(broadcast,
+            Broadcast::Single(1, packets) if packets.contains(&expected_stats))));
+        assert!(players_in_instance(&game_server, DOOR_SOURCE_ZONE_GUID).is_empty());
+        assert_eq!(
+            players_in_instance(&game_server, DOOR_DESTINATION_ZONE_GUID),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_door_teleport_to_destroyed_zone_leaves_player_in_place() {
+        let mut door = make_test_door(None);
+        door.destination_zone = Some(DOOR_DESTINATION_ZONE_GUID);
+        let (game_server, door_guid) = door_teleport_server(door, false);
+
+        assert!(use_door(&game_server, door_guid).unwrap().is_empty());
+        assert_eq!(
+            players_in_instance(&game_server, DOOR_SOURCE_ZONE_GUID),
+            vec![1]
+        );
+        assert!(players_in_instance(&game_server, DOOR_DESTINATION_ZONE_GUID).is_empty());
+    }
+
+    #[test]
+    fn test_door_teleport_to_template_without_instances_fails() {
+        let mut door = make_test_door(None);
+        door.destination_zone_template = Some(9);
+        let (game_server, door_guid) = door_teleport_server(door, true);
+
+        assert!(use_door(&game_server, door_guid).is_err());
+        assert_eq!(
+            players_in_instance(&game_server, DOOR_SOURCE_ZONE_GUID),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_missing_door_cannot_be_used() {
+        let (game_server, door_guid) = door_teleport_server(make_test_door(None), true);
+
+        assert!(use_door(&game_server, door_guid + 1).is_err());
+        assert_eq!(
+            players_in_instance(&game_server, DOOR_SOURCE_ZONE_GUID),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_seek_stops_when_target_leaves_zone() {
+        let source_zone_guid = zone_instance_guid(0, 1);
+        let destination_zone_guid = zone_instance_guid(0, 2);
+        let seeker_guid = npc_guid(AMBIENT_NPC_DISCRIMINANT, source_zone_guid, 0);
+
+        let characters = GuidTable::new();
+        let zones = GuidTable::new();
+        let mut characters_write_handle = characters.write();
+        zones.write().insert(make_test_zone(
+            destination_zone_guid,
+            &mut characters_write_handle,
+        ));
+        for guid in [1, 2] {
+            let mut player =
+                make_test_character(guid, CharacterType::Player(PlayerState::default()));
+            player.instance_guid = source_zone_guid;
+            characters_write_handle.insert(player);
+        }
+        let mut seeker =
+            make_test_character(seeker_guid, CharacterType::Door(make_test_door(None)));
+        seeker.instance_guid = source_zone_guid;
+        characters_write_handle.insert(seeker);
+
+        seek(&characters_write_handle, seeker_guid, 1, 5.0).unwrap();
+        assert_eq!(
+            characters_write_handle
+                .get(seeker_guid)
+                .unwrap()
+                .read()
+                .seek_target,
+            Some(1)
+        );
+
+        // The seeker isn't chasing the player who remains
+        assert!(stop_seeking(&characters_write_handle, source_zone_guid, 2)
+            .unwrap()
+            .is_empty());
+
+        let zones_read_handle = zones.read();
+        let destination_read_handle = zones_read_handle.get(destination_zone_guid).unwrap().read();
+        let broadcasts = enter_zone(
+            &mut characters_write_handle,
+            1,
+            &destination_read_handle,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let expected_packet = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: SeekTargetUpdate {
+                guid: seeker_guid,
+                target_id: 0,
+            },
+        })
+        .unwrap();
+        assert!(matches!(&broadcasts[0],
+            Broadcast::Multi(players, packets) if players == &vec![2] && packets == &vec![expected_packet]));
+        assert_eq!(
+            characters_write_handle
+                .get(seeker_guid)
+                .unwrap()
+                .read()
+                .seek_target,
+            None
+        );
+    }
+
+    #[test]
+    fn test_spawn_and_despawn_transient_npc() {
+        let zone_guid = zone_instance_guid(0, 1);
+        let characters = GuidTable::new();
+        let mut characters_write_handle = characters.write();
+        let zone = make_test_zone(zone_guid, &mut characters_write_handle);
+
+        let mut player = make_test_character(1, CharacterType::Player(PlayerState::default()));
+        player.instance_guid = zone_guid;
+        characters_write_handle.insert(player);
+
+        let npc = TransientNpc {
+            pos: Pos {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+                w: 1.0,
+            },
+            rot: Pos {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                w: 0.0,
+            },
+            state: 0,
+            character_type: CharacterType::Door(make_test_door(None)),
+            interact_radius: 5.0,
+            auto_interact_radius: 0.0,
+        };
+        let (first_guid, broadcasts) = zone
+            .spawn_npc(&mut characters_write_handle, npc.clone())
+            .unwrap();
+        let (second_guid, _) = zone
+            .spawn_npc(&mut characters_write_handle, npc.clone())
+            .unwrap();
+        assert_ne!(first_guid, second_guid);
+        assert_eq!(npc_discriminant(first_guid), TRANSIENT_NPC_DISCRIMINANT);
+
+        let expected_packets = characters_write_handle
+            .get(first_guid)
+            .unwrap()
+            .read()
+            .to_packets()
+            .unwrap();
+        match &broadcasts[..] {
+            [Broadcast::Multi(players, packets)] => {
+                assert_eq!(players, &vec![1]);
+                assert_eq!(packets, &expected_packets);
+            }
+            _ => panic!("Expected the NPC to be sent to the player"),
+        }
+
+        let broadcasts = zone
+            .despawn(&mut characters_write_handle, first_guid)
+            .unwrap();
+        let expected_packet = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: RemoveGracefully {
+                guid: first_guid,
+                unknown1: false,
+                unknown2: 0,
+                unknown3: 0,
+                unknown4: 0,
+                timer: 1000,
+            },
+        })
+        .unwrap();
+        match &broadcasts[..] {
+            [Broadcast::Multi(players, packets)] => {
+                assert_eq!(players, &vec![1]);
+                assert_eq!(packets, &vec![expected_packet]);
+            }
+            _ => panic!("Expected the NPC to be removed for the player"),
+        }
+        assert!(characters_write_handle.get(first_guid).is_none());
+        assert!(characters_write_handle.get(second_guid).is_some());
+
+        // Players can't be despawned
+        assert!(zone
+            .despawn(&mut characters_write_handle, player_guid(1))
+            .is_err());
+
+        // Allocation continues after the last index instead of searching from the start
+        let (third_guid, _) = zone.spawn_npc(&mut characters_write_handle, npc).unwrap();
+        assert_eq!(
+            third_guid,
+            npc_guid(TRANSIENT_NPC_DISCRIMINANT, zone_guid, 2)
+        );
+    }
+
+    fn zone_stats_packet(
+        speed: f32,
+        jump_height_multiplier: f32,
+        gravity_multiplier: f32,
+    ) -> Vec<u8> {
+        let stat = |id, value2| Stat {
+            id,
+            multiplier: 1,
+            value1: 0.0,
+            value2,
+        };
+        GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: Stats {
+                stats: vec![
+                    stat(StatId::Speed, speed),
+                    stat(StatId::JumpHeightMultiplier, jump_height_multiplier),
+                    stat(StatId::GravityMultiplier, gravity_multiplier),
+                ],
+            },
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_entering_zone_sends_gravity() {
+        let zone_guid = zone_instance_guid(0, 1);
+        let characters = GuidTable::new();
+        let zones = GuidTable::new();
+        let mut characters_write_handle = characters.write();
+        let mut zone = make_test_zone(zone_guid, &mut characters_write_handle);
+        zone.gravity_multiplier = 0.5;
+        zones.write().insert(zone);
+        characters_write_handle.insert(make_test_character(
+            1,
+            CharacterType::Player(PlayerState::default()),
+        ));
+
+        let zones_read_handle = zones.read();
+        let zone_read_handle = zones_read_handle.get(zone_guid).unwrap().read();
+        let broadcasts = enter_zone(
+            &mut characters_write_handle,
+            1,
+            &zone_read_handle,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let expected_stats = zone_stats_packet(1.0, 1.0, 0.5);
+        assert!(broadcasts.iter().any(|broadcast| matches!(broadcast,
+            Broadcast::Single(1, packets) if packets.contains(&expected_stats))));
+    }
+
+    #[test]
+    fn test_zone_details_done_only_after_details_sent() {
+        let mut character = make_test_character(1, CharacterType::Player(PlayerState::default()));
+        character.zone_load_state = ZoneLoadState::Loading;
+        assert!(!character.finish_loading_zone_details());
+        assert_eq!(character.zone_load_state, ZoneLoadState::Loading);
+
+        character.zone_load_state = ZoneLoadState::DetailsSent;
+        assert!(character.finish_loading_zone_details());
+        assert_eq!(character.zone_load_state, ZoneLoadState::Loaded);
+
+        // A repeated done packet is out of order
+        assert!(!character.finish_loading_zone_details());
+        assert_eq!(character.zone_load_state, ZoneLoadState::Loaded);
+    }
+
+    #[test]
+    fn test_characters_are_streamed_after_zone_details_done() {
+        let door_guid = npc_guid(AMBIENT_NPC_DISCRIMINANT, 24, 0);
+        let characters = GuidTable::new();
+        let zones = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            let mut player = make_test_character(1, CharacterType::Player(PlayerState::default()));
+            player.zone_load_state = ZoneLoadState::DetailsSent;
+            characters_write_handle.insert(player);
+            characters_write_handle.insert(make_test_character(
+                door_guid,
+                CharacterType::Door(make_test_door(None)),
+            ));
+            zones
+                .write()
+                .insert(make_test_zone(24, &mut characters_write_handle));
+        }
+
+        let game_server = GameServer::for_test(characters, zones);
+        assert!(Zone::move_character(move_to(2.0), &game_server)
+            .unwrap()
+            .is_empty());
+
+        let zone_details_done = (OpCode::ZoneDetailsDone as u16).to_le_bytes().to_vec();
+        let broadcasts = game_server.process_packet(1, zone_details_done).unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)] if !packets.is_empty()));
+
+        // The door was already sent, so moving doesn't send it again
+        assert!(Zone::move_character(move_to(3.0), &game_server)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_non_finite_position_is_rejected() {
+        let characters = GuidTable::new();
+        let zones = GuidTable::new();
+        {
+            let mut characters_write_handle = characters.write();
+            characters_write_handle.insert(make_test_character(
+                1,
+                CharacterType::Player(PlayerState::default()),
+            ));
+            let zone = make_test_zone(24, &mut characters_write_handle);
+            zones.write().insert(zone);
+        }
+
+        let game_server = GameServer::for_test(characters, zones);
+        Zone::move_character(move_to(5.0), &game_server).unwrap();
+
+        assert!(Zone::move_character(move_to(f32::NAN), &game_server).is_err());
+        let mut infinite_rotation = move_to(6.0);
+        infinite_rotation.rot_y = f32::INFINITY;
+        assert!(Zone::move_character(infinite_rotation, &game_server).is_err());
+
+        game_server
+            .lock_enforcer()
+            .read_characters(|_| CharacterLockRequest {
+                read_guids: vec![1],
+                write_guids: Vec::new(),
+                character_consumer: |_, characters_read, _, _| {
+                    let player = characters_read.get(&1).unwrap();
+                    assert_eq!(player.pos.x, 5.0);
+                    assert_eq!(player.rot.y, 0.0);
+                },
+            });
+    }
+
+    #[test]
+    fn test_characters_stream_in_and_out_of_interest_radius() {
+        let door_guid = npc_guid(AMBIENT_NPC_DISCRIMINANT, 24, 0);
+        let characters = GuidTable::new();
+        let zones = GuidTable::new();
+        let door_packets;
+        {
+            let mut characters_write_handle = characters.write();
+            characters_write_handle.insert(make_test_character(
+                1,
+                CharacterType::Player(PlayerState::default()),
+            ));
+            let mut door =
+                make_test_character(door_guid, CharacterType::Door(make_test_door(None)));
+            door.pos.x = 100.0;
+            door_packets = door.to_packets().unwrap();
+            characters_write_handle.insert(door);
+
+            let mut zone = make_test_zone(24, &mut characters_write_handle);
+            zone.interest_radius = Some(10.0);
+            zone.interest_hysteresis = 5.0;
+            zones.write().insert(zone);
+        }
+
+        let game_server = GameServer::for_test(characters, zones);
+
+        // Out of range, so nothing is sent
+        assert!(Zone::move_character(move_to(50.0), &game_server)
+            .unwrap()
+            .is_empty());
+
+        let broadcasts = Zone::move_character(move_to(95.0), &game_server).unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)] if packets == &door_packets));
+
+        // Already visible and still within the hysteresis, so nothing changes
+        assert!(Zone::move_character(move_to(96.0), &game_server)
+            .unwrap()
+            .is_empty());
+        assert!(Zone::move_character(move_to(87.0), &game_server)
+            .unwrap()
+            .is_empty());
+
+        let broadcasts = Zone::move_character(move_to(80.0), &game_server).unwrap();
+        let remove_packet = GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: RemoveGracefully {
+                guid: door_guid,
+                unknown1: false,
+                unknown2: 0,
+                unknown3: 0,
+                unknown4: 0,
+                timer: 1000,
+            },
+        })
+        .unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)] if packets == &vec![remove_packet]));
+    }
+
+    #[test]
+    fn test_spawned_npc_is_not_sent_again_by_interest_updates() {
+        for interest_radius in [None, Some(10.0)] {
+            let characters = GuidTable::new();
+            let zones = GuidTable::new();
+            {
+                let mut characters_write_handle = characters.write();
+                characters_write_handle.insert(make_test_character(
+                    1,
+                    CharacterType::Player(PlayerState::default()),
+                ));
+                let mut zone = make_test_zone(24, &mut characters_write_handle);
+                zone.interest_radius = interest_radius;
+
+                let (_, broadcasts) = zone
+                    .spawn_npc(
+                        &mut characters_write_handle,
+                        TransientNpc {
+                            pos: Pos {
+                                x: 5.0,
+                                y: 2.0,
+                                z: 3.0,
+                                w: 1.0,
+                            },
+                            rot: Pos {
+                                x: 0.0,
+                                y: 0.0,
+                                z: 0.0,
+                                w: 0.0,
+                            },
+                            state: 0,
+                            character_type: CharacterType::Door(make_test_door(None)),
+                            interact_radius: 0.0,
+                            auto_interact_radius: 0.0,
+                        },
+                    )
+                    .unwrap();
+                assert!(matches!(&broadcasts[..],
+                    [Broadcast::Multi(players, _)] if players == &vec![1]));
+                zones.write().insert(zone);
+            }
+
+            let game_server = GameServer::for_test(characters, zones);
+            assert!(Zone::move_character(move_to(2.0), &game_server)
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    fn jump_height_packet(multiplier: f32) -> Vec<u8> {
+        GamePacket::serialize(&TunneledPacket {
+            unknown1: true,
+            inner: Stats {
+                stats: vec![Stat {
+                    id: StatId::JumpHeightMultiplier,
+                    multiplier: 1,
+                    value1: 0.0,
+                    value2: multiplier,
+                }],
+            },
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_jump_height_buff_stacks_with_mount() {
+        let mut character = make_test_character(1, CharacterType::Player(PlayerState::default()));
+        character
+            .set_stat_multiplier(StatId::JumpHeightMultiplier, StatSource::Zone, 1.5)
+            .unwrap();
+        character
+            .set_stat_multiplier(StatId::JumpHeightMultiplier, StatSource::Mount, 2.0)
+            .unwrap();
+
+        let broadcasts = character
+            .set_stat_multiplier(StatId::JumpHeightMultiplier, StatSource::Buff(1), 3.0)
+            .unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)] if packets == &vec![jump_height_packet(9.0)]));
+
+        let broadcasts = character
+            .remove_stat_multiplier(StatId::JumpHeightMultiplier, StatSource::Buff(1))
+            .unwrap();
+        assert!(matches!(&broadcasts[..],
+            [Broadcast::Single(1, packets)] if packets == &vec![jump_height_packet(3.0)]));
+
+        // Removing a buff that was never applied changes nothing
+        assert!(character
+            .remove_stat_multiplier(StatId::JumpHeightMultiplier, StatSource::Buff(2))
+            .unwrap()
+            .is_empty());
+    }
 }
@@ -1,13 +1,20 @@
 use parking_lot::RwLock;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::env;
 use std::net::{SocketAddr, UdpSocket};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::spawn;
 
 use crate::channel_manager::{ChannelManager, ReceiveResult};
-use crate::game_server::GameServer;
-use crate::protocol::Channel;
+use crate::game_server::character_store::JsonCharacterStore;
+use crate::game_server::login::SinglePlayerAuthenticator;
+use crate::game_server::{GameServer, LoginResult, ProcessPacketError};
+use crate::protocol::capture::{load_capture, replay, PacketCapture};
+use crate::protocol::{Channel, DisconnectReason};
 
 mod channel_manager;
 mod game_server;
@@ -30,69 +37,231 @@ async fn main() {
     ))
     .expect("couldn't bind to socket");
 
-    let channel_manager = RwLock::new(ChannelManager::new());
+    // Capturing is opt-in because every inbound datagram is written to disk
+    let mut capture = env::var_os("OXIDE_CAPTURE_FILE").map(|path| {
+        println!("Capturing inbound datagrams to {:?}", path);
+        PacketCapture::create(Path::new(&path)).expect("Unable to create capture file")
+    });
+
+    let channel_manager = RwLock::new(ChannelManager::new(Duration::from_secs(30)));
+
+    let game_server = Arc::new(
+        GameServer::new(
+            config_dir,
+            Box::new(SinglePlayerAuthenticator),
+            Box::new(JsonCharacterStore::new(PathBuf::from(".character_saves"))),
+        )
+        .unwrap(),
+    );
+
+    // Saves write to disk, so they run on their own thread instead of stalling the packet loop
+    let autosave_interval = Duration::from_secs(60);
+    let autosave_server = game_server.clone();
+    thread::spawn(move || loop {
+        thread::sleep(autosave_interval);
+        autosave_server.autosave();
+    });
 
-    let game_server = GameServer::new(config_dir).unwrap();
     let process_delta = 40u8;
     let send_delta = 20u8;
+    let channel_timeout_millis = 10000u128;
+    let stats_interval = Duration::from_secs(60);
+    let mut last_tick = Instant::now();
+    let mut last_stats = last_tick;
+
+    // Datagrams that didn't fit in their client's full receive queue, retried on later iterations
+    let mut blocked_datagrams: BTreeMap<SocketAddr, Vec<u8>> = BTreeMap::new();
+    let new_channel = || {
+        let mut channel = Channel::new(200, 1000, 5);
+        if game_server.adopt_first_client_sequence() {
+            channel = channel.with_first_client_sequence(None);
+        }
+
+        match game_server.stale_gap() {
+            Some(stale_gap) => {
+                channel.with_max_reorder_age(stale_gap.max_age_millis as u128, stale_gap.policy)
+            }
+            None => channel,
+        }
+    };
+
+    // Replaying a capture runs it through fresh channels instead of starting the server
+    if let Some(path) = env::var_os("OXIDE_REPLAY_FILE") {
+        println!("Replaying captured datagrams from {:?}", path);
+        let events = load_capture(Path::new(&path)).expect("Unable to load capture file");
+        let replay = replay(events, new_channel, process_delta);
+        for (addr, packets) in replay.received {
+            println!("Replayed {} packets from {}", packets.len(), addr);
+        }
+        return;
+    }
+
     loop {
         let mut buf = [0; 512];
         if let Ok((len, src)) = socket.recv_from(&mut buf) {
             //println!("Bytes received: {}", len);
             let recv_data = &buf[0..len];
             //println!("Bytes: {:x?}", recv_data);
+            if let Some(capture) = &mut capture {
+                if let Err(err) = capture.capture_datagram(&src, recv_data) {
+                    println!("Unable to capture datagram from {}: {}", src, err);
+                }
+            }
+
+            // The client's data isn't read until its blocked datagram fits. The client resends
+            // any reliable data that isn't acked.
+            if let Entry::Vacant(blocked_datagram) = blocked_datagrams.entry(src) {
+                let mut read_handle = channel_manager.read();
 
-            let mut read_handle = channel_manager.read();
+                let mut receive_result = read_handle.receive(&src, recv_data);
+                if receive_result == ReceiveResult::CreateChannelFirst {
+                    drop(read_handle);
+                    let mut write_handle = channel_manager.write();
+                    if write_handle.resume(&src, recv_data) {
+                        println!("Resuming suspended channel for {}", src);
+                    } else {
+                        println!("Creating channel for {}", src);
+                        let mut channel = new_channel();
+                        if let Some(capture) = &mut capture {
+                            channel = match capture.capture_channel(&src, channel) {
+                                Ok(channel) => channel,
+                                Err(err) => {
+                                    println!("Unable to capture channel for {}: {}", src, err);
+                                    new_channel()
+                                }
+                            };
+                        }
+                        let previous_channel = write_handle.insert(&src, channel);
+
+                        if previous_channel.is_some() {
+                            println!("Client {} reconnected, dropping old channel", src);
+                        }
+                    }
+                    drop(write_handle);
+                    read_handle = channel_manager.read();
 
-            let receive_result = read_handle.receive(&src, recv_data);
-            if receive_result == ReceiveResult::CreateChannelFirst {
-                println!("Creating channel for {}", src);
+                    receive_result = read_handle.receive(&src, recv_data);
+                }
                 drop(read_handle);
-                let previous_channel = channel_manager
-                    .write()
-                    .insert(&src, Channel::new(200, 1000, 5));
-                read_handle = channel_manager.read();
 
-                if previous_channel.is_some() {
-                    println!("Client {} reconnected, dropping old channel", src);
+                if receive_result == ReceiveResult::WouldBlock {
+                    blocked_datagram.insert(recv_data.to_vec());
                 }
 
-                read_handle.receive(&src, recv_data);
+                process_packets(&channel_manager, &game_server, &src, process_delta);
+                send_packets(&socket, &channel_manager, &src, send_delta);
+            } else {
+                println!("Receive queue for {} is still full, skipping datagram", src);
             }
+        }
 
-            //println!("Processing at most {} packets", process_delta);
-            let packets_for_game_server = read_handle.process_next(&src, process_delta);
-            let mut broadcasts = Vec::new();
-            for packet in packets_for_game_server {
-                if let Some(guid) = read_handle.guid(&src) {
-                    match game_server.process_packet(guid, packet) {
-                        Ok(mut new_broadcasts) => broadcasts.append(&mut new_broadcasts),
-                        Err(err) => println!("Unable to process packet: {:?}", err),
-                    }
-                } else {
-                    match game_server.login(packet) {
-                        Ok((guid, mut new_broadcasts)) => {
-                            drop(read_handle);
-                            channel_manager.write().authenticate(&src, guid);
-                            broadcasts.append(&mut new_broadcasts);
-                            read_handle = channel_manager.read();
-                        }
-                        Err(err) => println!("Unable to process login packet: {:?}", err),
-                    }
-                }
+        // Clients with a blocked datagram get one batch processed per iteration, like any other
+        // client, until their queue has room for it
+        blocked_datagrams.retain(|src, data| {
+            if channel_manager.read().get_by_addr(src).is_none() {
+                return false;
             }
 
-            read_handle.broadcast(broadcasts);
+            process_packets(&channel_manager, &game_server, src, process_delta);
+            send_packets(&socket, &channel_manager, src, send_delta);
+            channel_manager.read().receive(src, data) == ReceiveResult::WouldBlock
+        });
+
+        let mut write_handle = channel_manager.write();
+        write_handle.suspend_timed_out(channel_timeout_millis);
+        let mut removed_guids = write_handle.remove_expired();
+        removed_guids.append(&mut write_handle.remove_closed());
+        let mut logout_broadcasts = Vec::new();
+        for guid in removed_guids {
+            match game_server.logout(guid) {
+                Ok(mut broadcasts) => logout_broadcasts.append(&mut broadcasts),
+                Err(err) => println!("Unable to log out player {}: {:?}", guid, err),
+            }
+        }
+        drop(write_handle);
+        channel_manager.read().broadcast(logout_broadcasts);
 
-            let packets_to_send = read_handle.send_next(&src, send_delta);
-            //println!("Sending {} packets", packets_to_send.len());
-            for buffer in packets_to_send {
-                //println!("Sending {} bytes: {:x?}", buffer.len(), buffer);
-                socket
-                    .send_to(&buffer, src)
-                    .expect("Unable to send packet to client");
+        let now = Instant::now();
+        match game_server.tick(now.duration_since(last_tick).as_millis()) {
+            Ok(broadcasts) => {
+                channel_manager.read().broadcast(broadcasts);
             }
+            Err(err) => println!("Unable to tick game server: {:?}", err),
+        }
+        last_tick = now;
+
+        if now.duration_since(last_stats) >= stats_interval {
+            println!(
+                "Disconnects by reason: {:?}",
+                channel_manager.read().disconnect_counts()
+            );
+            last_stats = now;
         }
+
         thread::sleep(Duration::from_millis(5));
     }
 }
+
+fn send_packets(
+    socket: &UdpSocket,
+    channel_manager: &RwLock<ChannelManager>,
+    src: &SocketAddr,
+    send_delta: u8,
+) {
+    let packets_to_send = channel_manager.read().send_next(src, send_delta);
+    //println!("Sending {} packets", packets_to_send.len());
+    for buffer in packets_to_send {
+        //println!("Sending {} bytes: {:x?}", buffer.len(), buffer);
+        socket
+            .send_to(&buffer, src)
+            .expect("Unable to send packet to client");
+    }
+}
+
+fn process_packets(
+    channel_manager: &RwLock<ChannelManager>,
+    game_server: &GameServer,
+    src: &SocketAddr,
+    process_delta: u8,
+) {
+    let mut read_handle = channel_manager.read();
+
+    //println!("Processing at most {} packets", process_delta);
+    let packets_for_game_server = read_handle.process_next(src, process_delta);
+    let mut broadcasts = Vec::new();
+    for packet in packets_for_game_server {
+        if let Some(guid) = read_handle.guid(src) {
+            match game_server.process_packet(guid, packet) {
+                Ok(mut new_broadcasts) => {
+                    read_handle.reset_unknown_op_codes(src);
+                    broadcasts.append(&mut new_broadcasts);
+                }
+                Err(ProcessPacketError::UnknownOpCode) => {
+                    if read_handle.record_unknown_op_code(src, game_server.max_unknown_op_codes()) {
+                        break;
+                    }
+                }
+                Err(err) => println!("Unable to process packet: {:?}", err),
+            }
+        } else {
+            match game_server.login(packet) {
+                Ok(LoginResult::Accepted(guid, mut new_broadcasts)) => {
+                    drop(read_handle);
+                    channel_manager.write().authenticate(src, guid);
+                    broadcasts.append(&mut new_broadcasts);
+                    read_handle = channel_manager.read();
+                }
+                Ok(LoginResult::Rejected(packets)) => {
+                    read_handle.send_and_disconnect(
+                        src,
+                        packets,
+                        DisconnectReason::ConnectionRefused,
+                    );
+                }
+                Err(err) => println!("Unable to process login packet: {:?}", err),
+            }
+        }
+    }
+
+    read_handle.broadcast(broadcasts);
+}
@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+
+use rand::random;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::hash::CrcSeed;
+use crate::protocol::{Channel, PendingPacket};
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum CaptureEvent {
+    // Server-generated values are recorded so that replayed channels make the same choices
+    ChannelCreated { crc_seed: CrcSeed },
+    Datagram { data: Vec<u8> },
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CapturedEvent {
+    pub timestamp_millis: u128,
+    pub addr: SocketAddr,
+    pub event: CaptureEvent,
+}
+
+// Records every inbound datagram as one JSON object per line
+pub struct PacketCapture<W: Write> {
+    writer: W,
+}
+
+impl PacketCapture<BufWriter<File>> {
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        Ok(PacketCapture::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> PacketCapture<W> {
+    pub fn new(writer: W) -> Self {
+        PacketCapture { writer }
+    }
+
+    pub fn capture_channel(
+        &mut self,
+        addr: &SocketAddr,
+        channel: Channel,
+    ) -> Result<Channel, Error> {
+        let crc_seed = random::<CrcSeed>();
+        self.record(addr, CaptureEvent::ChannelCreated { crc_seed })?;
+        Ok(channel.with_crc_seed(crc_seed))
+    }
+
+    pub fn capture_datagram(&mut self, addr: &SocketAddr, data: &[u8]) -> Result<(), Error> {
+        self.record(
+            addr,
+            CaptureEvent::Datagram {
+                data: data.to_vec(),
+            },
+        )
+    }
+
+    #[cfg(test)]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn record(&mut self, addr: &SocketAddr, event: CaptureEvent) -> Result<(), Error> {
+        let captured_event = CapturedEvent {
+            timestamp_millis: PendingPacket::now(),
+            addr: *addr,
+            event,
+        };
+        serde_json::to_writer(&mut self.writer, &captured_event)?;
+        self.writer.write_all(b"\n")?;
+
+        // Flush each event so the capture is complete even if the server crashes
+        self.writer.flush()
+    }
+}
+
+pub fn load_capture(path: &Path) -> Result<Vec<CapturedEvent>, Error> {
+    read_capture(BufReader::new(File::open(path)?))
+}
+
+pub fn read_capture(reader: impl BufRead) -> Result<Vec<CapturedEvent>, Error> {
+    let mut events = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let event =
+            serde_json::from_str(&line).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        events.push(event);
+    }
+
+    Ok(events)
+}
+
+#[derive(Default)]
+pub struct Replay {
+    pub channels: BTreeMap<SocketAddr, Channel>,
+    pub received: BTreeMap<SocketAddr, Vec<Vec<u8>>>,
+}
+
+// Feeds captured datagrams through fresh channels in the same order as the main loop, so the
+// channels end up in the same state as they did when the capture was recorded
+pub fn replay(
+    events: impl IntoIterator<Item = CapturedEvent>,
+    make_channel: impl Fn() -> Channel,
+    process_count: u8,
+) -> Replay {
+    let mut replay = Replay::default();
+    for captured_event in events {
+        match captured_event.event {
+            CaptureEvent::ChannelCreated { crc_seed } => {
+                replay
+                    .channels
+                    .insert(captured_event.addr, make_channel().with_crc_seed(crc_seed));
+            }
+            CaptureEvent::Datagram { data } => {
+                let Some(channel) = replay.channels.get_mut(&captured_event.addr) else {
+                    println!(
+                        "Skipping captured datagram from {} with no channel",
+                        captured_event.addr
+                    );
+                    continue;
+                };
+
+                if let Err(err) = channel.receive(&data) {
+                    println!(
+                        "Unable to receive captured datagram from {}: {:?}",
+                        captured_event.addr, err
+                    );
+                }
+
+                let mut data = channel.process_next(process_count);
+                replay
+                    .received
+                    .entry(captured_event.addr)
+                    .or_default()
+                    .append(&mut data);
+
+                // Sending updates the acknowledgement state, just like in the main loop
+                if let Err(err) = channel.send_next(process_count) {
+                    println!(
+                        "Unable to send replies to {}: {:?}",
+                        captured_event.addr, err
+                    );
+                }
+            }
+        }
+    }
+
+    replay
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange(
+        client: &mut Channel,
+        server: &mut Channel,
+        capture: &mut PacketCapture<Vec<u8>>,
+        addr: &SocketAddr,
+    ) -> Vec<Vec<u8>> {
+        let mut server_received = Vec::new();
+        for datagram in client.send_next(10).unwrap() {
+            capture.capture_datagram(addr, &datagram).unwrap();
+            server.receive(&datagram).unwrap();
+            server_received.append(&mut server.process_next(10));
+            for reply in server.send_next(10).unwrap() {
+                client.receive(&reply).unwrap();
+            }
+        }
+        client.process_next(10);
+        server_received
+    }
+
+    #[test]
+    fn test_captured_handshake_and_data_replay_to_same_state() {
+        let addr: SocketAddr = "127.0.0.1:20225".parse().unwrap();
+        let mut capture = PacketCapture::new(Vec::new());
+        let mut client = Channel::new(512, 1000, 0);
+        let mut server = capture
+            .capture_channel(&addr, Channel::new(512, 1000, 0))
+            .unwrap();
+
+        client.connect(12345, 512, "CloneWars".to_string());
+        exchange(&mut client, &mut server, &mut capture, &addr);
+        assert!(client.is_connected());
+
+        for index in 1..=5u8 {
+            client.prepare_to_send_data(vec![index, 0xff]).unwrap();
+        }
+        let server_received = exchange(&mut client, &mut server, &mut capture, &addr);
+        assert_eq!(server_received.len(), 5);
+
+        let events = read_capture(capture.into_inner().as_slice()).unwrap();
+        let replay = replay(events, || Channel::new(512, 1000, 0), 10);
+
+        let replayed_server = &replay.channels[&addr];
+        assert_eq!(replay.received[&addr], server_received);
+        assert_eq!(replayed_server.session(), server.session());
+        assert_eq!(replayed_server.stats(), server.stats());
+    }
+}
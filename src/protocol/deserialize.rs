@@ -1,4 +1,4 @@
-use crate::protocol::hash::{compute_crc, CrcHash};
+use crate::protocol::hash::{compute_crc, is_valid_crc_size, CrcHash};
 use crate::protocol::{DisconnectReason, Packet, ProtocolOpCode, Session};
 use byteorder::{BigEndian, ReadBytesExt};
 use miniz_oxide::inflate::{decompress_to_vec_zlib, DecompressError};
@@ -15,6 +15,8 @@ pub enum DeserializeError {
     UnknownDisconnectReason(u16),
     MissingSession(ProtocolOpCode),
     BadSubPacketLength,
+    BadPacketLength(usize),
+    BadCrcLength,
 }
 
 impl From<Error> for DeserializeError {
@@ -110,7 +112,10 @@ fn deserialize_multi_packet(data: &[u8]) -> Result<Vec<Packet>, DeserializeError
         offset += new_offset;
         cursor.set_position(offset as u64);
 
-        if packet_length as usize > data[offset..].len() {
+        // Every sub-packet needs at least enough space for its own op code
+        if packet_length as usize > data[offset..].len()
+            || (packet_length as usize) < size_of::<u16>()
+        {
             return Err(DeserializeError::BadSubPacketLength);
         }
 
@@ -259,6 +264,8 @@ fn deserialize_packet_data(
     }
 }
 
+// Must return an error rather than panic for any input, since the data comes straight from
+// the socket
 pub fn deserialize_packet(
     data: &[u8],
     possible_session: &Option<Session>,
@@ -269,6 +276,10 @@ pub fn deserialize_packet(
     let mut packet_data;
     if op_code.requires_session() {
         if let Some(session) = possible_session {
+            if !is_valid_crc_size(session.crc_length) {
+                return Err(DeserializeError::BadCrcLength);
+            }
+
             let compressed = session.allow_compression && cursor.read_u8()? != 0;
 
             // Two bytes for the op code and, optionally, one byte for the compression flag
@@ -282,6 +293,9 @@ pub fn deserialize_packet(
                 .len()
                 .checked_sub(session.crc_length as usize)
                 .unwrap_or(data_offset);
+            if crc_offset < data_offset {
+                return Err(DeserializeError::BadPacketLength(data.len()));
+            }
             cursor.set_position(crc_offset as u64);
             let expected_hash = cursor.read_uint::<BigEndian>(session.crc_length as usize)? as u32;
 
@@ -304,3 +318,88 @@ pub fn deserialize_packet(
 
     deserialize_packet_data(&packet_data, op_code)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::hash::compute_crc;
+
+    fn make_test_session() -> Session {
+        Session {
+            session_id: 12345,
+            crc_length: 3,
+            crc_seed: 67890,
+            allow_compression: true,
+            use_encryption: false,
+        }
+    }
+
+    fn with_crc(mut data: Vec<u8>, session: &Session) -> Vec<u8> {
+        let crc = compute_crc(&data, session.crc_seed, session.crc_length);
+        data.extend_from_slice(&crc.to_be_bytes()[1..]);
+        data
+    }
+
+    #[test]
+    fn test_invalid_crc_length_returns_err() {
+        for crc_length in [0, 5, 9] {
+            let session = Session {
+                crc_length,
+                ..make_test_session()
+            };
+            assert!(matches!(
+                deserialize_packet(&[0, 9, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9], &Some(session)),
+                Err(DeserializeError::BadCrcLength)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_truncated_and_garbage_packets_return_err() {
+        let session = make_test_session();
+        let inputs: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![0],
+            vec![0xFF, 0xFF],
+            vec![0, 1],
+            vec![0, 1, 0, 0, 0],
+            vec![0, 2, 0, 0, 0, 1],
+            vec![0, 9],
+            vec![0, 9, 0],
+            vec![0, 9, 0, 1],
+            vec![0, 9, 0, 0, 1, 2],
+            vec![0, 3, 1, 2, 3, 4, 5, 6],
+            vec![0, 5, 0],
+            vec![0, 7, 0, 0, 0],
+            vec![0, 8, 0, 0, 0],
+            vec![0, 30, 0, 0],
+            // Compressed flag is set, but the data is not valid zlib
+            with_crc(vec![0, 9, 1, 0, 1, 2, 3], &session),
+            // Sub-packet lengths too short to hold an op code
+            with_crc(vec![0, 3, 0, 0, 0, 9, 0, 1], &session),
+            with_crc(vec![0, 3, 0, 1, 0, 9, 0, 1], &session),
+            // Sub-packet length longer than the remaining data
+            with_crc(vec![0, 3, 0, 200, 0, 9, 0, 1], &session),
+            // Sub-packet length uses the 4-byte form but is truncated
+            with_crc(vec![0, 3, 0, 0xFF, 0xFF, 0xFF, 0], &session),
+            // Sub-packet is truncated after its op code
+            with_crc(vec![0, 3, 0, 4, 0, 17, 0], &session),
+            with_crc(vec![0, 3, 0, 3, 0, 5, 0, 0], &session),
+            with_crc(vec![0, 3, 0, 2, 0, 1], &session),
+            with_crc(vec![0, 3, 0, 2, 0, 99], &session),
+        ];
+
+        for input in inputs {
+            assert!(
+                deserialize_packet(&input, &Some(make_test_session())).is_err(),
+                "Expected error for {:?}",
+                input
+            );
+            assert!(
+                deserialize_packet(&input, &None).is_err(),
+                "Expected error for {:?} without session",
+                input
+            );
+        }
+    }
+}
@@ -2,6 +2,11 @@ pub type CrcSeed = u32;
 pub type CrcHash = u32;
 pub type CrcSize = u8;
 
+// The hash is a u32, so it can be truncated to 1 to 4 bytes but not extended
+pub fn is_valid_crc_size(crc_size: CrcSize) -> bool {
+    (1..=4).contains(&crc_size)
+}
+
 pub fn compute_crc(data: &[u8], seed: CrcSeed, crc_size: CrcSize) -> u32 {
     let mut crc = CRC_TABLE[((!seed) & 0xFF) as usize];
     crc ^= 0x00FFFFFF;
@@ -0,0 +1,209 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::protocol::Channel;
+
+#[derive(Copy, Clone, Default)]
+pub struct NetworkConditions {
+    pub loss_rate: f64,
+    pub duplicate_rate: f64,
+    pub max_latency_rounds: u32,
+    pub reorder: bool,
+}
+
+struct InTransit {
+    deliver_at_round: u32,
+    to_client: bool,
+    datagram: Vec<u8>,
+}
+
+// Connects two channels in memory instead of through sockets. Datagrams can be dropped,
+// duplicated, delayed, or reordered with a seeded RNG so that tests are deterministic.
+pub struct LoopbackPair {
+    pub client: Channel,
+    pub server: Channel,
+    conditions: NetworkConditions,
+    rng: StdRng,
+    round: u32,
+    in_transit: Vec<InTransit>,
+}
+
+impl LoopbackPair {
+    pub fn new(client: Channel, server: Channel, conditions: NetworkConditions, seed: u64) -> Self {
+        LoopbackPair {
+            client,
+            server,
+            conditions,
+            rng: StdRng::seed_from_u64(seed),
+            round: 0,
+            in_transit: Vec::new(),
+        }
+    }
+
+    // Sends up to `count` packets in each direction, then returns the data that the client and
+    // server received, respectively. Latency is measured in calls to this method.
+    pub fn exchange(&mut self, count: u8) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+        self.round += 1;
+
+        let client_datagrams = self
+            .client
+            .send_next(count)
+            .expect("Client could not send packets");
+        self.transmit(client_datagrams, false);
+        self.deliver(false);
+        let server_data = self.server.process_next(count);
+
+        let server_datagrams = self
+            .server
+            .send_next(count)
+            .expect("Server could not send packets");
+        self.transmit(server_datagrams, true);
+        self.deliver(true);
+        let client_data = self.client.process_next(count);
+
+        (client_data, server_data)
+    }
+
+    fn transmit(&mut self, datagrams: Vec<Vec<u8>>, to_client: bool) {
+        for datagram in datagrams {
+            if self.rng.gen_bool(self.conditions.loss_rate) {
+                continue;
+            }
+
+            let copies = if self.rng.gen_bool(self.conditions.duplicate_rate) {
+                2
+            } else {
+                1
+            };
+            for _ in 0..copies {
+                let latency = self.rng.gen_range(0..=self.conditions.max_latency_rounds);
+                self.in_transit.push(InTransit {
+                    deliver_at_round: self.round + latency,
+                    to_client,
+                    datagram: datagram.clone(),
+                });
+            }
+        }
+    }
+
+    fn deliver(&mut self, to_client: bool) {
+        let round = self.round;
+        let (mut arrived, in_transit): (Vec<InTransit>, Vec<InTransit>) = self
+            .in_transit
+            .drain(..)
+            .partition(|packet| packet.to_client == to_client && packet.deliver_at_round <= round);
+        self.in_transit = in_transit;
+
+        if self.conditions.reorder {
+            arrived.shuffle(&mut self.rng);
+        }
+
+        let destination = if to_client {
+            &mut self.client
+        } else {
+            &mut self.server
+        };
+        for packet in arrived {
+            destination
+                .receive(&packet.datagram)
+                .expect("Channel received corrupted datagram");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connect(pair: &mut LoopbackPair) {
+        // Session requests are unreliable, so keep asking until the handshake succeeds
+        let mut rounds = 0;
+        while !pair.client.is_connected() {
+            assert!(rounds < 100, "Handshake did not complete");
+            pair.client.connect(12345, 512, "CloneWars".to_string());
+            pair.exchange(10);
+            rounds += 1;
+        }
+        assert!(pair.server.is_connected());
+    }
+
+    fn send_to_server(pair: &mut LoopbackPair, expected: &[Vec<u8>], max_rounds: u32) {
+        for data in expected.iter() {
+            pair.client.prepare_to_send_data(data.clone()).unwrap();
+        }
+
+        let mut server_received = Vec::new();
+        let mut rounds = 0;
+        while server_received.len() < expected.len() {
+            assert!(rounds < max_rounds, "Data was not delivered");
+            let (_, mut server_data) = pair.exchange(10);
+            server_received.append(&mut server_data);
+            rounds += 1;
+        }
+
+        assert_eq!(server_received, expected);
+    }
+
+    #[test]
+    fn test_handshake_and_data_exchange_with_loss() {
+        let mut pair = LoopbackPair::new(
+            Channel::new(512, 1000, 0),
+            Channel::new(512, 1000, 0),
+            NetworkConditions {
+                loss_rate: 0.1,
+                ..Default::default()
+            },
+            42,
+        );
+        connect(&mut pair);
+
+        pair.server.prepare_to_send_data(vec![1, 2, 3]).unwrap();
+        let mut client_received = Vec::new();
+        while client_received.is_empty() {
+            let (mut client_data, _) = pair.exchange(10);
+            client_received.append(&mut client_data);
+        }
+        assert_eq!(client_received, vec![vec![1, 2, 3]]);
+
+        let expected: Vec<Vec<u8>> = (1..=50u8).map(|index| vec![index, 0xff]).collect();
+        send_to_server(&mut pair, &expected, 1000);
+    }
+
+    #[test]
+    fn test_handshake_negotiates_session() {
+        let mut pair = LoopbackPair::new(
+            Channel::new(512, 1000, 0),
+            Channel::new(512, 1000, 0),
+            NetworkConditions::default(),
+            1,
+        );
+        assert!(pair.server.session().is_none());
+        connect(&mut pair);
+
+        let server_session = pair.server.session().unwrap();
+        let client_session = pair.client.session().unwrap();
+        assert_eq!(server_session.session_id, 12345);
+        assert_eq!(client_session.session_id, 12345);
+        assert_eq!(client_session.crc_seed, server_session.crc_seed);
+    }
+
+    #[test]
+    fn test_reliable_stream_survives_heavy_loss() {
+        let mut pair = LoopbackPair::new(
+            Channel::new(512, 1000, 0),
+            Channel::new(512, 1000, 0),
+            NetworkConditions {
+                loss_rate: 0.5,
+                duplicate_rate: 0.1,
+                max_latency_rounds: 3,
+                reorder: true,
+            },
+            7,
+        );
+        connect(&mut pair);
+
+        let expected: Vec<Vec<u8>> = (1..=100u8).map(|index| vec![index, 0xff]).collect();
+        send_to_server(&mut pair, &expected, 5000);
+    }
+}
@@ -1,17 +1,23 @@
 use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{Display, Formatter};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use rand::random;
+use serde::Deserialize;
 
 use crate::protocol::deserialize::{deserialize_packet, DeserializeError};
-use crate::protocol::hash::{CrcSeed, CrcSize};
+use crate::protocol::hash::{is_valid_crc_size, CrcSeed, CrcSize};
 use crate::protocol::reliable_data_ops::{
-    fragment_data, unbundle_reliable_data, DataPacket, FragmentState,
+    bundle_reliable_data, fragment_count, fragment_data, unbundle_reliable_data, DataPacket,
+    FragmentState,
 };
-use crate::protocol::serialize::{serialize_packets, SerializeError};
+use crate::protocol::serialize::{serialize_packets, serialize_single_packet, SerializeError};
 
+pub mod capture;
 mod deserialize;
 mod hash;
+#[cfg(test)]
+mod loopback;
 mod reliable_data_ops;
 mod serialize;
 
@@ -58,7 +64,7 @@ pub type SessionId = u32;
 pub type BufferSize = u32;
 pub type ApplicationProtocol = String;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub enum DisconnectReason {
     Unknown = 0,
     IcmpError = 1,
@@ -79,6 +85,79 @@ pub enum DisconnectReason {
     ProtocolMismatch = 16,
 }
 
+impl DisconnectReason {
+    pub fn as_metric_label(&self) -> &'static str {
+        match self {
+            DisconnectReason::Unknown => "unknown",
+            DisconnectReason::IcmpError => "icmp_error",
+            DisconnectReason::Timeout => "timeout",
+            DisconnectReason::OtherSideTerminated => "other_side_terminated",
+            DisconnectReason::ManagerDeleted => "manager_deleted",
+            DisconnectReason::ConnectFail => "connect_fail",
+            DisconnectReason::Application => "application",
+            DisconnectReason::UnreachableConnection => "unreachable_connection",
+            DisconnectReason::UnacknowledgedTimeout => "unacknowledged_timeout",
+            DisconnectReason::NewConnectionAttempt => "new_connection_attempt",
+            DisconnectReason::ConnectionRefused => "connection_refused",
+            DisconnectReason::ConnectError => "connect_error",
+            DisconnectReason::ConnectingToSelf => "connecting_to_self",
+            DisconnectReason::ReliableOverflow => "reliable_overflow",
+            DisconnectReason::ApplicationReleased => "application_released",
+            DisconnectReason::CorruptPacket => "corrupt_packet",
+            DisconnectReason::ProtocolMismatch => "protocol_mismatch",
+        }
+    }
+}
+
+impl Display for DisconnectReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            DisconnectReason::Unknown => "unknown reason",
+            DisconnectReason::IcmpError => "ICMP error",
+            DisconnectReason::Timeout => "timed out",
+            DisconnectReason::OtherSideTerminated => "other side terminated the connection",
+            DisconnectReason::ManagerDeleted => "connection manager deleted",
+            DisconnectReason::ConnectFail => "failed to connect",
+            DisconnectReason::Application => "disconnected by application",
+            DisconnectReason::UnreachableConnection => "connection unreachable",
+            DisconnectReason::UnacknowledgedTimeout => "timed out waiting for acknowledgement",
+            DisconnectReason::NewConnectionAttempt => "new connection attempt",
+            DisconnectReason::ConnectionRefused => "connection refused",
+            DisconnectReason::ConnectError => "connection error",
+            DisconnectReason::ConnectingToSelf => "connecting to self",
+            DisconnectReason::ReliableOverflow => "reliable data overflow",
+            DisconnectReason::ApplicationReleased => "released by application",
+            DisconnectReason::CorruptPacket => "corrupt packet",
+            DisconnectReason::ProtocolMismatch => "protocol mismatch",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ReceiveOverflow {
+    Drop,
+    Block,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub enum StaleGapPolicy {
+    Skip,
+    Disconnect,
+}
+
+#[derive(Debug)]
+pub enum ReceiveError {
+    Deserialize(DeserializeError),
+    WouldBlock,
+}
+
+impl From<DeserializeError> for ReceiveError {
+    fn from(value: DeserializeError) -> Self {
+        ReceiveError::Deserialize(value)
+    }
+}
+
 pub type ClientTick = u16;
 pub type ServerTick = u32;
 pub type Timestamp = u32;
@@ -189,6 +268,7 @@ impl PendingPacket {
     }
 }
 
+#[derive(Debug, Eq, PartialEq)]
 pub struct Session {
     pub session_id: SessionId,
     pub crc_length: CrcSize,
@@ -197,18 +277,52 @@ pub struct Session {
     pub use_encryption: bool,
 }
 
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ChannelStats {
+    pub in_order: u64,
+    pub reordered: u64,
+    pub out_of_window: u64,
+    pub duplicates: u64,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SequenceSnapshot {
+    pub next_client_sequence: SequenceNumber,
+    pub next_server_sequence: SequenceNumber,
+    pub last_client_ack: SequenceNumber,
+    pub last_server_ack: SequenceNumber,
+}
+
+// Each fragment consumes a sequence number, so huge messages could exhaust the sequence space
+const DEFAULT_MAX_FRAGMENTS: usize = 4096;
+const DEFAULT_MAX_RECEIVE_QUEUE: usize = 1024;
+
 pub struct Channel {
     session: Option<Session>,
+    requested_session_id: Option<SessionId>,
+    fixed_crc_seed: Option<CrcSeed>,
     buffer_size: BufferSize,
+    max_fragments: usize,
+    max_receive_queue: usize,
+    receive_overflow: ReceiveOverflow,
+    max_reorder_age: Option<(u128, StaleGapPolicy)>,
     recency_limit: SequenceNumber,
     millis_until_resend: u128,
     fragment_state: FragmentState,
     send_queue: VecDeque<PendingPacket>,
     receive_queue: VecDeque<Packet>,
-    reordered_packets: BTreeMap<SequenceNumber, Packet>,
+    reordered_packets: BTreeMap<SequenceNumber, (Packet, u128)>,
+    first_client_sequence: Option<SequenceNumber>,
     next_client_sequence: SequenceNumber,
+    received_first_sequence: bool,
     next_server_sequence: SequenceNumber,
+    last_client_ack: SequenceNumber,
     last_server_ack: SequenceNumber,
+    stats: ChannelStats,
+    last_receive_time: u128,
+    unknown_op_codes: u32,
+    disconnect_reason: Option<DisconnectReason>,
+    closed: bool,
 }
 
 impl Channel {
@@ -219,21 +333,98 @@ impl Channel {
     ) -> Self {
         Channel {
             session: None,
+            requested_session_id: None,
+            fixed_crc_seed: None,
             buffer_size: initial_buffer_size,
+            max_fragments: DEFAULT_MAX_FRAGMENTS,
+            max_receive_queue: DEFAULT_MAX_RECEIVE_QUEUE,
+            receive_overflow: ReceiveOverflow::Block,
+            max_reorder_age: None,
             recency_limit,
             millis_until_resend,
             fragment_state: FragmentState::new(),
             send_queue: VecDeque::new(),
             receive_queue: VecDeque::new(),
             reordered_packets: BTreeMap::new(),
+            first_client_sequence: Some(0),
             next_client_sequence: 0,
+            received_first_sequence: true,
             next_server_sequence: 0,
+            last_client_ack: 0,
             last_server_ack: 0,
+            stats: ChannelStats::default(),
+            last_receive_time: PendingPacket::now(),
+            unknown_op_codes: 0,
+            disconnect_reason: None,
+            closed: false,
         }
     }
 
-    pub fn receive(&mut self, data: &[u8]) -> Result<u32, DeserializeError> {
+    pub fn with_crc_seed(mut self, crc_seed: CrcSeed) -> Self {
+        self.fixed_crc_seed = Some(crc_seed);
+        self
+    }
+
+    // Without a first sequence number, the channel adopts the first one it receives. A packet
+    // from before it is then dropped if it arrives second.
+    pub fn with_first_client_sequence(
+        mut self,
+        first_client_sequence: Option<SequenceNumber>,
+    ) -> Self {
+        self.first_client_sequence = first_client_sequence;
+        self.next_client_sequence = first_client_sequence.unwrap_or(0);
+        self.received_first_sequence = first_client_sequence.is_some();
+        self
+    }
+
+    pub fn with_max_fragments(mut self, max_fragments: usize) -> Self {
+        self.max_fragments = max_fragments;
+        self
+    }
+
+    pub fn with_receive_limit(
+        mut self,
+        max_receive_queue: usize,
+        receive_overflow: ReceiveOverflow,
+    ) -> Self {
+        self.max_receive_queue = max_receive_queue;
+        self.receive_overflow = receive_overflow;
+        self
+    }
+
+    pub fn with_max_reorder_age(
+        mut self,
+        max_reorder_age_millis: u128,
+        stale_gap_policy: StaleGapPolicy,
+    ) -> Self {
+        self.max_reorder_age = Some((max_reorder_age_millis, stale_gap_policy));
+        self
+    }
+
+    pub fn receive(&mut self, data: &[u8]) -> Result<u32, ReceiveError> {
+        // Leave the data unread so the transport can retry once the queue has been processed
+        let is_full = self.receive_queue.len() >= self.max_receive_queue;
+        if is_full && self.receive_overflow == ReceiveOverflow::Block {
+            return Err(ReceiveError::WouldBlock);
+        }
+
         let mut packets = deserialize_packet(data, &self.session)?;
+        self.last_receive_time = PendingPacket::now();
+
+        // A blocking channel accepts a whole datagram once it has room, so it may exceed the limit
+        // by the packets in one datagram
+        if self.receive_overflow == ReceiveOverflow::Drop {
+            let room = self
+                .max_receive_queue
+                .saturating_sub(self.receive_queue.len());
+            if packets.len() > room {
+                println!(
+                    "Receive queue full, dropping {} packets",
+                    packets.len() - room
+                );
+                packets.truncate(room);
+            }
+        }
 
         let packet_count = packets.len() as u32;
         packets
@@ -245,16 +436,40 @@ impl Channel {
     pub fn process_next(&mut self, count: u8) -> Vec<Vec<u8>> {
         let mut needs_new_ack = false;
         let mut packets_to_process = Vec::new();
+        self.resolve_stale_gap(PendingPacket::now());
 
         for _ in 0..count {
             if let Some(packet) = self.receive_queue.pop_front() {
+                let mut ready_packets = Vec::new();
+
                 // Special processing for reliable packets
                 if let Some(sequence_number) = packet.sequence_number() {
+                    // Not every client starts its stream at 0, so start from the client's first
+                    // sequence number instead of treating the whole stream as out of order
+                    if !self.received_first_sequence {
+                        self.next_client_sequence = sequence_number;
+                        self.received_first_sequence = true;
+                    }
+
                     // Add out-of-order packets to a separate queue until the expected
                     // packets arrive.
                     if sequence_number != self.next_client_sequence {
-                        if self.save_for_reorder(sequence_number) {
-                            self.reordered_packets.insert(sequence_number, packet);
+                        if self.already_processed(sequence_number) {
+                            // The client resent a packet because it missed our ack, so ack it
+                            // again without delivering the packet a second time
+                            self.stats.duplicates += 1;
+                        } else if self.save_for_reorder(sequence_number) {
+                            if self
+                                .reordered_packets
+                                .insert(sequence_number, (packet, PendingPacket::now()))
+                                .is_some()
+                            {
+                                self.stats.duplicates += 1;
+                            } else {
+                                self.stats.reordered += 1;
+                            }
+                        } else {
+                            self.stats.out_of_window += 1;
                         }
 
                         // Ack single packet in case the client didn't receive the ack
@@ -263,25 +478,35 @@ impl Channel {
                         continue;
                     }
 
+                    self.stats.in_order += 1;
                     self.last_server_ack = sequence_number;
                     self.next_client_sequence = self.next_client_sequence.wrapping_add(1);
                     needs_new_ack = true;
+                    ready_packets.push(packet);
 
-                    // Add a previously-received data packet if it is next in sequence
-                    if let Some(next_packet) =
+                    // Add the whole run of previously-received packets that are now next in
+                    // sequence, so they are delivered in order in this pass
+                    while let Some((next_packet, _)) =
                         self.reordered_packets.remove(&self.next_client_sequence)
                     {
-                        self.receive_queue.push_front(next_packet);
+                        self.stats.in_order += 1;
+                        self.last_server_ack = self.next_client_sequence;
+                        self.next_client_sequence = self.next_client_sequence.wrapping_add(1);
+                        ready_packets.push(next_packet);
                     }
+                } else {
+                    ready_packets.push(packet);
                 }
 
-                match self.fragment_state.add(packet) {
-                    Ok(possible_packet) => {
-                        if let Some(packet) = possible_packet {
-                            packets_to_process.push(packet);
+                for packet in ready_packets {
+                    match self.fragment_state.add(packet) {
+                        Ok(possible_packet) => {
+                            if let Some(packet) = possible_packet {
+                                packets_to_process.push(packet);
+                            }
                         }
+                        Err(err) => println!("Unable to process packet: {:?}", err),
                     }
-                    Err(err) => println!("Unable to process packet: {:?}", err),
                 }
             } else {
                 break;
@@ -311,7 +536,14 @@ impl Channel {
         packets
     }
 
-    pub fn prepare_to_send_data(&mut self, data: Vec<u8>) {
+    pub fn prepare_to_send_data(&mut self, data: Vec<u8>) -> Result<(), SerializeError> {
+        let Some(session) = &self.session else {
+            return Err(SerializeError::MissingSession);
+        };
+        if fragment_count(self.buffer_size, session, data.len()) > self.max_fragments {
+            return Err(SerializeError::MessageTooLarge(data.len()));
+        }
+
         let packets =
             fragment_data(self.buffer_size, &self.session, data).expect("Unable to fragment data");
 
@@ -325,6 +557,21 @@ impl Channel {
             self.send_queue
                 .push_back(PendingPacket::new(sequenced_packet));
         }
+
+        Ok(())
+    }
+
+    // Bundled packets share one sequence number, unless the bundle needs to be fragmented
+    pub fn prepare_to_send_data_bundle(
+        &mut self,
+        mut packets: Vec<Vec<u8>>,
+    ) -> Result<(), SerializeError> {
+        if packets.len() == 1 {
+            return self.prepare_to_send_data(packets.remove(0));
+        }
+
+        let bundle = bundle_reliable_data(&packets).expect("Unable to bundle data");
+        self.prepare_to_send_data(bundle)
     }
 
     pub fn send_next(&mut self, count: u8) -> Result<Vec<Vec<u8>>, SerializeError> {
@@ -333,6 +580,7 @@ impl Channel {
         // If the packet was acked, it was already sent, so don't send it again
         self.send_queue.retain(|packet| packet.needs_send);
 
+        let mut sends_disconnect = false;
         let mut index = 0;
         while indices_to_send.len() < count as usize && index < self.send_queue.len() {
             let packet = &mut self.send_queue[index];
@@ -349,6 +597,10 @@ impl Channel {
                 packet.needs_send = false;
             }
 
+            if let Packet::Disconnect(..) = packet.packet {
+                sends_disconnect = true;
+            }
+
             indices_to_send.push(index);
             packet.update_last_prepare_to_send_time();
             index += 1;
@@ -359,15 +611,140 @@ impl Channel {
             .map(|index| &self.send_queue[index].packet)
             .collect();
 
+        // The client has been told the channel is ending, so nothing else needs to be sent
+        if sends_disconnect {
+            self.closed = true;
+        }
+
+        if let [packet] = packets_to_send[..] {
+            return Ok(vec![serialize_single_packet(
+                packet,
+                self.buffer_size,
+                &self.session,
+            )?]);
+        }
+
         serialize_packets(&packets_to_send, self.buffer_size, &self.session)
     }
 
+    // Acts as the client side of the connection by requesting a session from the other side
+    pub fn connect(
+        &mut self,
+        session_id: SessionId,
+        buffer_size: BufferSize,
+        app_protocol: ApplicationProtocol,
+    ) {
+        self.requested_session_id = Some(session_id);
+        self.buffer_size = buffer_size;
+        self.send_queue
+            .push_back(PendingPacket::new(Packet::SessionRequest(
+                3,
+                session_id,
+                buffer_size,
+                app_protocol,
+            )));
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.session.is_some()
+    }
+
+    pub fn session(&self) -> Option<&Session> {
+        self.session.as_ref()
+    }
+
+    pub fn disconnect(&mut self, reason: DisconnectReason) {
+        // The client only needs to be told once
+        if self.disconnect_reason.is_some() {
+            return;
+        }
+        self.disconnect_reason = Some(reason);
+
+        // There is no session to end if the client never finished connecting
+        if let Some(session) = &self.session {
+            self.send_queue
+                .push_back(PendingPacket::new(Packet::Disconnect(
+                    session.session_id,
+                    reason,
+                )));
+        } else {
+            self.closed = true;
+        }
+    }
+
+    pub fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        self.disconnect_reason
+    }
+
+    // A closed channel has sent its Disconnect packet and can be removed
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn millis_since_last_receive(&self) -> u128 {
+        let now = PendingPacket::now();
+        now.saturating_sub(self.last_receive_time)
+    }
+
+    pub fn matches_remap(&self, data: &[u8]) -> bool {
+        if let Some(session) = &self.session {
+            if let Ok(packets) = deserialize_packet(data, &None) {
+                return packets.iter().any(|packet| {
+                    if let Packet::RemapConnection(session_id, crc_seed) = packet {
+                        *session_id == session.session_id && *crc_seed == session.crc_seed
+                    } else {
+                        false
+                    }
+                });
+            }
+        }
+
+        false
+    }
+
+    pub fn stats(&self) -> ChannelStats {
+        self.stats
+    }
+
+    // Only consecutive unknown op codes count, so a client that sends one occasionally is fine
+    pub fn record_unknown_op_code(&mut self) -> u32 {
+        self.unknown_op_codes = self.unknown_op_codes.saturating_add(1);
+        self.unknown_op_codes
+    }
+
+    pub fn reset_unknown_op_codes(&mut self) {
+        self.unknown_op_codes = 0;
+    }
+
+    pub fn sequence_snapshot(&self) -> SequenceSnapshot {
+        SequenceSnapshot {
+            next_client_sequence: self.next_client_sequence,
+            next_server_sequence: self.next_server_sequence,
+            last_client_ack: self.last_client_ack,
+            last_server_ack: self.last_server_ack,
+        }
+    }
+
+    pub fn encode_for_wire(&self, packet: &Packet) -> Result<Vec<u8>, SerializeError> {
+        serialize_single_packet(packet, self.buffer_size, &self.session)
+    }
+
+    pub fn decode_from_wire(&self, data: &[u8]) -> Result<Vec<Packet>, DeserializeError> {
+        deserialize_packet(data, &self.session)
+    }
+
     fn next_server_sequence(&mut self) -> SequenceNumber {
         let next_sequence = self.next_server_sequence;
         self.next_server_sequence = self.next_server_sequence.wrapping_add(1);
         next_sequence
     }
 
+    fn already_processed(&self, sequence_number: SequenceNumber) -> bool {
+        // Wrapping subtraction handles sequence numbers on either side of the wrap boundary
+        let age = self.next_client_sequence.wrapping_sub(sequence_number);
+        age > 0 && age <= self.recency_limit
+    }
+
     fn save_for_reorder(&self, sequence_number: SequenceNumber) -> bool {
         let max_sequence_number = self.next_client_sequence.wrapping_add(self.recency_limit);
 
@@ -379,6 +756,61 @@ impl Channel {
         }
     }
 
+    // If the missing packet was lost for good, the packets buffered behind it would wait forever
+    fn resolve_stale_gap(&mut self, now: u128) {
+        let Some((max_reorder_age_millis, stale_gap_policy)) = self.max_reorder_age else {
+            return;
+        };
+
+        let Some(oldest_arrival_time) = self
+            .reordered_packets
+            .values()
+            .map(|(_, arrival_time)| *arrival_time)
+            .min()
+        else {
+            return;
+        };
+
+        if now.saturating_sub(oldest_arrival_time) < max_reorder_age_millis {
+            return;
+        }
+
+        match stale_gap_policy {
+            StaleGapPolicy::Skip => {
+                // The first buffered packet after the gap, accounting for wraparound
+                let next_client_sequence = self.next_client_sequence;
+                let first_buffered_sequence = *self
+                    .reordered_packets
+                    .keys()
+                    .min_by_key(|sequence_number| {
+                        sequence_number.wrapping_sub(next_client_sequence)
+                    })
+                    .expect("Reordered packets should not be empty");
+                println!(
+                    "Skipping missing packets {} to {}",
+                    next_client_sequence,
+                    first_buffered_sequence.wrapping_sub(1)
+                );
+
+                // Requeue the packet as the next one expected so the whole run is delivered
+                // and acked normally
+                let (packet, _) = self
+                    .reordered_packets
+                    .remove(&first_buffered_sequence)
+                    .expect("First buffered packet should exist");
+                self.next_client_sequence = first_buffered_sequence;
+                self.receive_queue.push_front(packet);
+
+                // A partial message can never be completed once part of it is lost
+                self.fragment_state = FragmentState::new();
+            }
+            StaleGapPolicy::Disconnect => {
+                self.reordered_packets.clear();
+                self.disconnect(DisconnectReason::Timeout);
+            }
+        }
+    }
+
     fn should_client_ack(
         recency_limit: SequenceNumber,
         next_server_sequence: SequenceNumber,
@@ -405,6 +837,22 @@ impl Channel {
                     *buffer_size,
                     app_protocol,
                 ),
+            Packet::SessionReply(
+                session_id,
+                crc_seed,
+                crc_length,
+                allow_compression,
+                use_encryption,
+                buffer_size,
+                _,
+            ) => self.process_session_reply(
+                *session_id,
+                *crc_seed,
+                *crc_length,
+                *allow_compression,
+                *use_encryption,
+                *buffer_size,
+            ),
             Packet::Heartbeat => self.process_heartbeat(),
             Packet::Ack(acked_sequence) => self.process_ack(*acked_sequence),
             Packet::AckAll(acked_sequence) => self.process_ack_all(*acked_sequence),
@@ -419,29 +867,90 @@ impl Channel {
         buffer_size: BufferSize,
         app_protocol: &ApplicationProtocol,
     ) {
-        // TODO: disallow session overwrite
+        if let Some(session) = &self.session {
+            // The client is retrying because our reply was lost, so keep the existing session
+            if session.session_id == session_id {
+                let reply = Channel::session_reply(session);
+                self.send_queue.push_back(PendingPacket::new(reply));
+                return;
+            }
+
+            self.reset_sequences();
+        }
+
         let session = Session {
             session_id,
             crc_length: 3,
-            crc_seed: random::<CrcSeed>(),
+            crc_seed: self.fixed_crc_seed.unwrap_or_else(random::<CrcSeed>),
             allow_compression: true,
             use_encryption: false,
         };
 
         self.buffer_size = buffer_size;
         self.send_queue
-            .push_back(PendingPacket::new(Packet::SessionReply(
-                session_id,
-                session.crc_seed,
-                session.crc_length,
-                session.allow_compression,
-                session.use_encryption,
-                512,
-                3,
-            )));
+            .push_back(PendingPacket::new(Channel::session_reply(&session)));
         self.session = Some(session);
     }
 
+    fn session_reply(session: &Session) -> Packet {
+        Packet::SessionReply(
+            session.session_id,
+            session.crc_seed,
+            session.crc_length,
+            session.allow_compression,
+            session.use_encryption,
+            512,
+            3,
+        )
+    }
+
+    // Nothing from the previous session may be sent, acked, or reassembled in the new one
+    fn reset_sequences(&mut self) {
+        self.send_queue.clear();
+        self.reordered_packets.clear();
+        self.fragment_state = FragmentState::new();
+        self.next_client_sequence = self.first_client_sequence.unwrap_or(0);
+        self.received_first_sequence = self.first_client_sequence.is_some();
+        self.next_server_sequence = 0;
+        self.last_client_ack = 0;
+        self.last_server_ack = 0;
+    }
+
+    fn process_session_reply(
+        &mut self,
+        session_id: SessionId,
+        crc_seed: CrcSeed,
+        crc_length: CrcSize,
+        allow_compression: bool,
+        use_encryption: bool,
+        buffer_size: BufferSize,
+    ) {
+        // Only accept replies to a session this channel requested, so a client cannot
+        // overwrite the server's session
+        if self.requested_session_id != Some(session_id) {
+            return;
+        }
+
+        // The peer chooses the CRC length, so reject one we can't compute or read
+        if !is_valid_crc_size(crc_length) {
+            println!(
+                "Ignoring session reply for session {} with invalid CRC length {}",
+                session_id, crc_length
+            );
+            return;
+        }
+
+        // Neither side may receive packets larger than the buffer it asked for
+        self.buffer_size = self.buffer_size.min(buffer_size);
+        self.session = Some(Session {
+            session_id,
+            crc_length,
+            crc_seed,
+            allow_compression,
+            use_encryption,
+        });
+    }
+
     fn process_heartbeat(&mut self) {
         self.send_queue
             .push_back(PendingPacket::new(Packet::Heartbeat));
@@ -454,6 +963,7 @@ impl Channel {
             self.next_server_sequence.wrapping_sub(1),
             acked_sequence,
         ) {
+            self.last_client_ack = acked_sequence;
             for pending_packet in self.send_queue.iter_mut() {
                 if let Some(pending_sequence) = pending_packet.packet.sequence_number() {
                     if acked_sequence == pending_sequence {
@@ -465,6 +975,15 @@ impl Channel {
     }
 
     fn process_ack_all(&mut self, acked_sequence: SequenceNumber) {
+        if Channel::should_client_ack(
+            self.recency_limit,
+            self.next_server_sequence,
+            self.next_server_sequence.wrapping_sub(1),
+            acked_sequence,
+        ) {
+            self.last_client_ack = acked_sequence;
+        }
+
         for pending_packet in self.send_queue.iter_mut() {
             if let Some(pending_sequence) = pending_packet.packet.sequence_number() {
                 if Channel::should_client_ack(
@@ -485,7 +1004,603 @@ impl Channel {
     }
 
     fn acknowledge_all(&mut self, sequence_number: SequenceNumber) {
+        // The client treats an AckAll as covering every earlier packet, so never ack past the
+        // contiguous packets we actually received
+        let high_water_mark = self.next_client_sequence.wrapping_sub(1);
+        let sequence_number = if self.already_processed(sequence_number) {
+            sequence_number
+        } else {
+            println!(
+                "Tried to ack all packets up to {}, but only received up to {}",
+                sequence_number, high_water_mark
+            );
+            high_water_mark
+        };
+
         self.send_queue
             .push_back(PendingPacket::new(Packet::AckAll(sequence_number)));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use super::*;
+    use crate::protocol::serialize::max_fragment_data_size;
+
+    fn make_test_channel(allow_compression: bool) -> Channel {
+        make_test_channel_with_encryption(allow_compression, false)
+    }
+
+    fn make_test_channel_with_encryption(allow_compression: bool, use_encryption: bool) -> Channel {
+        let mut channel = Channel::new(512, 1000, 5);
+        channel.session = Some(Session {
+            session_id: 12345,
+            crc_length: 3,
+            crc_seed: 67890,
+            allow_compression,
+            use_encryption,
+        });
+        channel
+    }
+
+    // Bitwise CRC-32 over the little-endian seed followed by the data, kept separate from the
+    // table-driven implementation so that the expected bytes don't come from the code under test
+    fn reference_crc(seed: u32, data: &[u8]) -> u32 {
+        let mut crc = 0xFFFFFFFF;
+        for byte in seed.to_le_bytes().iter().chain(data) {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xEDB88320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+
+    #[test]
+    fn test_encode_ack_for_wire() {
+        let channel = make_test_channel(false);
+        let actual = channel.encode_for_wire(&Packet::Ack(5)).unwrap();
+
+        // Op code 0x11 and sequence number 5, followed by the low three bytes of the CRC
+        let mut expected = vec![0, 17, 0, 5];
+        expected.extend_from_slice(&reference_crc(67890, &expected).to_be_bytes()[1..]);
+        assert_eq!(actual, expected);
+        assert!(channel.send_queue.is_empty());
+    }
+
+    #[test]
+    fn test_encode_ack_for_wire_with_compression_flag() {
+        let channel = make_test_channel(true);
+        let actual = channel.encode_for_wire(&Packet::Ack(5)).unwrap();
+        assert_eq!(actual.len(), 8);
+        assert_eq!(actual[..5], [0, 17, 0, 0, 5]);
+    }
+
+    #[test]
+    fn test_decode_from_wire_round_trips_data() {
+        let channel = make_test_channel_with_encryption(true, true);
+        let data = vec![7; 200];
+        let encoded = channel
+            .encode_for_wire(&Packet::Data(3, data.clone()))
+            .unwrap();
+
+        // Check that the packet was actually compressed
+        assert_eq!(encoded[2], 1);
+
+        let decoded = channel.decode_from_wire(&encoded).unwrap();
+        assert_eq!(decoded.len(), 1);
+        if let Packet::Data(sequence_number, decoded_data) = &decoded[0] {
+            assert_eq!(*sequence_number, 3);
+            assert_eq!(*decoded_data, data);
+        } else {
+            panic!("Expected data packet, got {:?}", decoded[0].op_code());
+        }
+        assert!(channel.receive_queue.is_empty());
+    }
+
+    fn receive_data(channel: &mut Channel, sequence_numbers: &[SequenceNumber]) {
+        for sequence_number in sequence_numbers {
+            channel
+                .receive_queue
+                .push_back(Packet::Data(*sequence_number, vec![*sequence_number as u8]));
+        }
+    }
+
+    #[test]
+    fn test_stats_count_each_receive_path() {
+        let mut channel = make_test_channel(false);
+
+        receive_data(&mut channel, &[0, 1, 2]);
+        channel.process_next(10);
+        assert_eq!(
+            channel.stats(),
+            ChannelStats {
+                in_order: 3,
+                reordered: 0,
+                out_of_window: 0,
+                duplicates: 0,
+            }
+        );
+
+        receive_data(&mut channel, &[4, 4]);
+        channel.process_next(10);
+        assert_eq!(
+            channel.stats(),
+            ChannelStats {
+                in_order: 3,
+                reordered: 1,
+                out_of_window: 0,
+                duplicates: 1,
+            }
+        );
+
+        receive_data(&mut channel, &[3, 2000]);
+        channel.process_next(10);
+        assert_eq!(
+            channel.stats(),
+            ChannelStats {
+                in_order: 5,
+                reordered: 1,
+                out_of_window: 1,
+                duplicates: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_sequence_snapshot_tracks_exchange() {
+        let mut channel = make_test_channel(false);
+        assert_eq!(
+            channel.sequence_snapshot(),
+            SequenceSnapshot {
+                next_client_sequence: 0,
+                next_server_sequence: 0,
+                last_client_ack: 0,
+                last_server_ack: 0,
+            }
+        );
+
+        receive_data(&mut channel, &[0, 1, 2]);
+        channel.process_next(10);
+        channel.prepare_to_send_data(vec![1]).unwrap();
+        channel.prepare_to_send_data(vec![2]).unwrap();
+        channel.process_packet(&Packet::AckAll(1));
+        assert_eq!(
+            channel.sequence_snapshot(),
+            SequenceSnapshot {
+                next_client_sequence: 3,
+                next_server_sequence: 2,
+                last_client_ack: 1,
+                last_server_ack: 2,
+            }
+        );
+
+        // Acks for packets that were never sent are ignored
+        channel.process_packet(&Packet::AckAll(500));
+        assert_eq!(channel.sequence_snapshot().last_client_ack, 1);
+    }
+
+    #[test]
+    fn test_empty_data_is_acked_without_app_packets() {
+        let mut channel = make_test_channel(false);
+        channel.receive_queue.push_back(Packet::Data(0, Vec::new()));
+        assert!(channel.process_next(10).is_empty());
+        assert!(matches!(
+            channel.send_queue.back().unwrap().packet,
+            Packet::AckAll(0)
+        ));
+
+        // Empty sub-packets inside a bundle are skipped too
+        channel
+            .receive_queue
+            .push_back(Packet::Data(1, vec![0x00, 0x19, 0, 1, 7]));
+        assert_eq!(channel.process_next(10), vec![vec![7]]);
+        assert_eq!(channel.sequence_snapshot().next_client_sequence, 2);
+        assert!(matches!(
+            channel.send_queue.back().unwrap().packet,
+            Packet::AckAll(1)
+        ));
+    }
+
+    #[test]
+    fn test_replayed_packet_is_reacked_and_dropped() {
+        let mut channel = make_test_channel(false);
+        receive_data(&mut channel, &[0, 1]);
+        assert_eq!(channel.process_next(10).len(), 2);
+        channel.send_queue.clear();
+
+        receive_data(&mut channel, &[0]);
+        assert!(channel.process_next(10).is_empty());
+        assert_eq!(channel.stats().duplicates, 1);
+        assert!(channel.reordered_packets.is_empty());
+        assert_eq!(channel.send_queue.len(), 1);
+        assert!(matches!(channel.send_queue[0].packet, Packet::Ack(0)));
+    }
+
+    #[test]
+    fn test_replayed_packet_across_wrap_boundary_is_dropped() {
+        let mut channel = make_test_channel(false);
+        channel.next_client_sequence = SequenceNumber::MAX;
+        receive_data(&mut channel, &[SequenceNumber::MAX]);
+        assert_eq!(channel.process_next(10).len(), 1);
+        assert_eq!(channel.next_client_sequence, 0);
+
+        receive_data(&mut channel, &[SequenceNumber::MAX]);
+        assert!(channel.process_next(10).is_empty());
+        assert_eq!(channel.stats().duplicates, 1);
+        assert!(channel.reordered_packets.is_empty());
+    }
+
+    #[test]
+    fn test_new_session_resets_sequences() {
+        let mut channel = make_test_channel(false).with_first_client_sequence(None);
+        receive_data(&mut channel, &[0, 1, 3]);
+        channel.process_next(10);
+        channel.prepare_to_send_data(vec![1, 2, 3]).unwrap();
+        assert!(!channel.reordered_packets.is_empty());
+
+        // A retried request for the current session doesn't reset anything
+        channel.process_packet(&Packet::SessionRequest(3, 12345, 512, "".to_string()));
+        assert_eq!(channel.next_client_sequence, 2);
+        assert_eq!(channel.next_server_sequence, 1);
+        assert!(matches!(
+            channel.send_queue.back().unwrap().packet,
+            Packet::SessionReply(12345, 67890, ..)
+        ));
+
+        channel.process_packet(&Packet::SessionRequest(3, 54321, 512, "".to_string()));
+        assert_eq!(channel.session().unwrap().session_id, 54321);
+        assert_eq!(channel.next_client_sequence, 0);
+        assert_eq!(channel.next_server_sequence, 0);
+        assert!(channel.reordered_packets.is_empty());
+        assert_eq!(channel.send_queue.len(), 1);
+        assert!(matches!(
+            channel.send_queue[0].packet,
+            Packet::SessionReply(54321, ..)
+        ));
+
+        // The new session's stream may start at any sequence number
+        receive_data(&mut channel, &[7]);
+        assert_eq!(channel.process_next(10), vec![vec![7]]);
+    }
+
+    #[test]
+    fn test_stale_gap_is_skipped() {
+        let mut channel = make_test_channel(false).with_max_reorder_age(1000, StaleGapPolicy::Skip);
+        receive_data(&mut channel, &[0, 2, 3]);
+        assert_eq!(channel.process_next(10), vec![vec![0]]);
+        let arrival_time = channel.reordered_packets[&2].1;
+
+        // The gap is still young enough that the missing packet may arrive
+        channel.resolve_stale_gap(arrival_time + 999);
+        assert_eq!(channel.next_client_sequence, 1);
+        assert_eq!(channel.reordered_packets.len(), 2);
+
+        channel.resolve_stale_gap(arrival_time + 1000);
+        assert_eq!(channel.process_next(10), vec![vec![2], vec![3]]);
+        assert!(channel.reordered_packets.is_empty());
+        assert_eq!(channel.next_client_sequence, 4);
+        assert!(matches!(
+            channel.send_queue.back().unwrap().packet,
+            Packet::AckAll(3)
+        ));
+
+        // The skipped packet is treated as already processed if it finally arrives
+        receive_data(&mut channel, &[1, 4]);
+        assert_eq!(channel.process_next(10), vec![vec![4]]);
+    }
+
+    #[test]
+    fn test_stale_gap_disconnects() {
+        let mut channel =
+            make_test_channel(false).with_max_reorder_age(1000, StaleGapPolicy::Disconnect);
+        receive_data(&mut channel, &[0, 2]);
+        channel.process_next(10);
+        channel.send_queue.clear();
+        let arrival_time = channel.reordered_packets[&2].1;
+
+        channel.resolve_stale_gap(arrival_time + 1000);
+        assert!(channel.reordered_packets.is_empty());
+        assert!(matches!(
+            channel.send_queue.back().unwrap().packet,
+            Packet::Disconnect(12345, DisconnectReason::Timeout)
+        ));
+        assert_eq!(channel.disconnect_reason(), Some(DisconnectReason::Timeout));
+
+        // The channel can only be removed once the client has been told
+        assert!(!channel.is_closed());
+        channel.send_next(10).unwrap();
+        assert!(channel.is_closed());
+    }
+
+    #[test]
+    fn test_skipped_gap_discards_partial_message() {
+        let mut channel = make_test_channel(false).with_max_reorder_age(1000, StaleGapPolicy::Skip);
+        channel
+            .receive_queue
+            .push_back(Packet::DataFragment(0, vec![0, 0, 0, 10, 1, 2, 3, 4]));
+        receive_data(&mut channel, &[2]);
+        assert!(channel.process_next(10).is_empty());
+        let arrival_time = channel.reordered_packets[&2].1;
+
+        // The rest of the message was lost with packet 1, so packet 2 starts fresh
+        channel.resolve_stale_gap(arrival_time + 1000);
+        assert_eq!(channel.process_next(10), vec![vec![2]]);
+    }
+
+    #[test]
+    fn test_contiguous_reordered_packets_deliver_in_one_pass() {
+        let mut channel = make_test_channel(false);
+        receive_data(&mut channel, &[0, 1, 2, 3, 4]);
+        assert_eq!(channel.process_next(10).len(), 5);
+        channel.send_queue.clear();
+
+        // Only one pass per received packet, so the buffered run must not need extra passes
+        receive_data(&mut channel, &[6, 7, 8, 5]);
+        assert_eq!(
+            channel.process_next(4),
+            vec![vec![5], vec![6], vec![7], vec![8]]
+        );
+        assert!(channel.reordered_packets.is_empty());
+        assert_eq!(channel.next_client_sequence, 9);
+        assert!(matches!(
+            channel.send_queue.back().unwrap().packet,
+            Packet::AckAll(8)
+        ));
+    }
+
+    #[test]
+    fn test_filled_gap_is_acked_through_released_run() {
+        let mut channel = make_test_channel(false);
+        receive_data(&mut channel, &[0]);
+        channel.process_next(10);
+        channel.send_queue.clear();
+
+        receive_data(&mut channel, &[2, 3, 1, 5]);
+        assert_eq!(channel.process_next(10), vec![vec![1], vec![2], vec![3]]);
+        let acks: Vec<&Packet> = channel
+            .send_queue
+            .iter()
+            .map(|pending| &pending.packet)
+            .collect();
+        // The batch's AckAll is queued in the same pass, so it already covers the released run
+        assert!(matches!(
+            acks[..],
+            [
+                Packet::Ack(2),
+                Packet::Ack(3),
+                Packet::Ack(5),
+                Packet::AckAll(3)
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_ack_all_never_exceeds_contiguous_sequence() {
+        let mut channel = make_test_channel(false);
+        receive_data(&mut channel, &[0, 1, 4, 3, 6]);
+        assert_eq!(channel.process_next(10).len(), 2);
+        let ack_alls: Vec<SequenceNumber> = channel
+            .send_queue
+            .iter()
+            .filter_map(|pending| match pending.packet {
+                Packet::AckAll(sequence_number) => Some(sequence_number),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ack_alls, vec![1]);
+
+        receive_data(&mut channel, &[2]);
+        assert_eq!(channel.process_next(10).len(), 3);
+        assert!(matches!(
+            channel.send_queue.back().unwrap().packet,
+            Packet::AckAll(4)
+        ));
+
+        // Packet 6 is still missing 5, so it must not be covered even with a bad last ack
+        channel.last_server_ack = 6;
+        channel.acknowledge_all(channel.last_server_ack);
+        assert!(matches!(
+            channel.send_queue.back().unwrap().packet,
+            Packet::AckAll(4)
+        ));
+    }
+
+    #[test]
+    fn test_message_over_fragment_cap_is_rejected() {
+        let mut channel = make_test_channel(false).with_max_fragments(3);
+        let max_size = max_fragment_data_size(512, channel.session().unwrap()) as usize;
+
+        // Three full fragments, minus the length prefix in the first one
+        let largest_allowed = 3 * max_size - size_of::<u32>();
+        let session = channel.session().unwrap();
+        assert_eq!(fragment_count(512, session, largest_allowed), 3);
+        assert_eq!(
+            fragment_data(512, &channel.session, vec![0; largest_allowed])
+                .unwrap()
+                .len(),
+            3
+        );
+        assert_eq!(fragment_count(512, session, largest_allowed + 1), 4);
+
+        assert!(matches!(
+            channel.prepare_to_send_data(vec![0; largest_allowed + 1]),
+            Err(SerializeError::MessageTooLarge(len)) if len == largest_allowed + 1
+        ));
+        assert!(channel.send_queue.is_empty());
+        assert_eq!(channel.next_server_sequence, 0);
+
+        channel
+            .prepare_to_send_data(vec![0; largest_allowed])
+            .unwrap();
+        assert_eq!(channel.send_queue.len(), 3);
+    }
+
+    #[test]
+    fn test_session_reply_with_invalid_crc_length_is_ignored() {
+        for crc_length in [0, 5, 9] {
+            let mut client = Channel::new(200, 1000, 5);
+            client.connect(12345, 512, "CloneWars".to_string());
+            client.process_packet(&Packet::SessionReply(
+                12345, 67890, crc_length, false, false, 512, 3,
+            ));
+            assert!(!client.is_connected());
+        }
+    }
+
+    #[test]
+    fn test_client_adopts_smaller_server_buffer_size() {
+        let mut client = Channel::new(200, 1000, 5);
+        client.connect(12345, 512, "CloneWars".to_string());
+        client.process_packet(&Packet::SessionReply(12345, 67890, 3, false, false, 256, 3));
+        assert_eq!(client.buffer_size, 256);
+
+        client.send_queue.clear();
+        client.prepare_to_send_data(vec![0; 1000]).unwrap();
+        let datagrams = client.send_next(10).unwrap();
+        assert!(datagrams.len() > 1);
+        assert!(datagrams.iter().all(|datagram| datagram.len() <= 256));
+
+        // A server with a larger buffer can't make the client exceed its own request
+        let mut client = Channel::new(200, 1000, 5);
+        client.connect(12345, 512, "CloneWars".to_string());
+        client.process_packet(&Packet::SessionReply(
+            12345, 67890, 3, false, false, 1024, 3,
+        ));
+        assert_eq!(client.buffer_size, 512);
+    }
+
+    #[test]
+    fn test_full_receive_queue_blocks() {
+        let mut channel = make_test_channel(false).with_receive_limit(2, ReceiveOverflow::Block);
+        let datagram = channel.encode_for_wire(&Packet::Ack(5)).unwrap();
+
+        assert_eq!(channel.receive(&datagram).unwrap(), 1);
+        assert_eq!(channel.receive(&datagram).unwrap(), 1);
+        assert!(matches!(
+            channel.receive(&datagram),
+            Err(ReceiveError::WouldBlock)
+        ));
+        assert_eq!(channel.receive_queue.len(), 2);
+
+        channel.process_next(1);
+        assert_eq!(channel.receive(&datagram).unwrap(), 1);
+        assert_eq!(channel.receive_queue.len(), 2);
+    }
+
+    #[test]
+    fn test_full_receive_queue_drops() {
+        let mut channel = make_test_channel(false).with_receive_limit(2, ReceiveOverflow::Drop);
+        let datagram = channel.encode_for_wire(&Packet::Ack(5)).unwrap();
+
+        for _ in 0..5 {
+            channel.receive(&datagram).unwrap();
+        }
+        assert_eq!(channel.receive(&datagram).unwrap(), 0);
+        assert_eq!(channel.receive_queue.len(), 2);
+    }
+
+    #[test]
+    fn test_single_pending_packet_is_not_bundled() {
+        let mut channel = make_test_channel(false);
+        channel.acknowledge_one(5);
+        let datagrams = channel.send_next(10).unwrap();
+        assert_eq!(
+            datagrams,
+            vec![channel.encode_for_wire(&Packet::Ack(5)).unwrap()]
+        );
+
+        channel.acknowledge_one(6);
+        channel.acknowledge_one(7);
+        let datagrams = channel.send_next(10).unwrap();
+        assert_eq!(datagrams.len(), 1);
+        assert_eq!(
+            datagrams[0][..2],
+            (ProtocolOpCode::MultiPacket as u16).to_be_bytes()
+        );
+    }
+
+    #[test]
+    fn test_bundle_uses_fewer_data_packets() {
+        let packets = vec![vec![1, 2, 3], vec![4; 300], vec![5]];
+
+        let mut individual_channel = make_test_channel(false);
+        packets.iter().for_each(|packet| {
+            individual_channel
+                .prepare_to_send_data(packet.clone())
+                .unwrap()
+        });
+
+        let mut bundled_channel = make_test_channel(false);
+        bundled_channel
+            .prepare_to_send_data_bundle(packets.clone())
+            .unwrap();
+
+        assert_eq!(individual_channel.send_queue.len(), 3);
+        assert_eq!(bundled_channel.send_queue.len(), 1);
+        if let Packet::Data(_, data) = &bundled_channel.send_queue[0].packet {
+            assert_eq!(unbundle_reliable_data(data).unwrap(), packets);
+        } else {
+            panic!("Expected bundled data packet");
+        }
+    }
+
+    #[test]
+    fn test_disconnect_reason_labels() {
+        assert_eq!(DisconnectReason::Timeout.as_metric_label(), "timeout");
+        assert_eq!(
+            DisconnectReason::ConnectionRefused.as_metric_label(),
+            "connection_refused"
+        );
+        assert_eq!(
+            DisconnectReason::CorruptPacket.to_string(),
+            "corrupt packet"
+        );
+    }
+
+    #[test]
+    fn test_disconnect_queues_disconnect_packet() {
+        let mut channel = make_test_channel(false);
+        channel.disconnect(DisconnectReason::Application);
+        assert!(matches!(
+            channel.send_queue.back().unwrap().packet,
+            Packet::Disconnect(12345, DisconnectReason::Application)
+        ));
+
+        let mut unconnected_channel = Channel::new(512, 1000, 5);
+        unconnected_channel.disconnect(DisconnectReason::Application);
+        assert!(unconnected_channel.send_queue.is_empty());
+    }
+
+    #[test]
+    fn test_first_sequence_number_is_baseline() {
+        let mut channel = make_test_channel(false).with_first_client_sequence(None);
+        receive_data(&mut channel, &[1, 2, 3]);
+        assert_eq!(channel.process_next(10), vec![vec![1], vec![2], vec![3]]);
+        assert_eq!(channel.stats().in_order, 3);
+        assert_eq!(channel.next_client_sequence, 4);
+    }
+
+    #[test]
+    fn test_configured_first_sequence_number() {
+        let mut channel = make_test_channel(false).with_first_client_sequence(Some(1));
+        receive_data(&mut channel, &[1, 2]);
+        assert_eq!(channel.process_next(10), vec![vec![1], vec![2]]);
+        assert_eq!(channel.stats().in_order, 2);
+    }
+
+    #[test]
+    fn test_reordered_first_packets_are_both_delivered() {
+        let mut channel = make_test_channel(false);
+        receive_data(&mut channel, &[1, 0]);
+        assert_eq!(channel.process_next(10), vec![vec![0], vec![1]]);
+        assert_eq!(channel.stats().duplicates, 0);
+        assert_eq!(channel.next_client_sequence, 2);
+    }
+}
@@ -90,6 +90,11 @@ fn read_data_bundle_variable_length_int(data: &[u8]) -> Result<(u32, usize), Dat
 }
 
 pub fn unbundle_reliable_data(data: &[u8]) -> Result<Vec<Vec<u8>>, DataError> {
+    // An empty payload carries no application packet, but it is still acked like any other data
+    if data.is_empty() {
+        return Ok(Vec::new());
+    }
+
     // Check for the magic bytes 0x00, 0x19 that indicate data packets
     if data.len() < 2 || data[0] != 0x00 || data[1] != 0x19 {
         return Ok(vec![data.to_vec()]);
@@ -109,13 +114,52 @@ pub fn unbundle_reliable_data(data: &[u8]) -> Result<Vec<Vec<u8>>, DataError> {
             return Err(DataError::BadSubPacketLength);
         }
 
-        packets.push(data[offset..(offset + packet_length as usize)].to_vec());
+        if packet_length > 0 {
+            packets.push(data[offset..(offset + packet_length as usize)].to_vec());
+        }
         offset += packet_length as usize;
     }
 
     Ok(packets)
 }
 
+fn write_data_bundle_variable_length_int(buffer: &mut Vec<u8>, value: u32) -> Result<(), Error> {
+    if value < 0xFF {
+        buffer.write_u8(value as u8)?;
+    } else if value < 0xFFFF {
+        buffer.write_u8(0xFF)?;
+        buffer.write_u16::<BigEndian>(value as u16)?;
+    } else {
+        buffer.write_all(&[0xFF, 0xFF, 0xFF])?;
+        buffer.write_u32::<BigEndian>(value)?;
+    }
+
+    Ok(())
+}
+
+pub fn bundle_reliable_data(packets: &[Vec<u8>]) -> Result<Vec<u8>, DataError> {
+    // Start with the magic bytes 0x00, 0x19 that indicate data packets
+    let mut buffer = vec![0x00, 0x19];
+
+    for packet in packets {
+        write_data_bundle_variable_length_int(&mut buffer, packet.len() as u32)?;
+        buffer.write_all(packet)?;
+    }
+
+    Ok(buffer)
+}
+
+pub fn fragment_count(buffer_size: BufferSize, session: &Session, data_len: usize) -> usize {
+    let max_size = max_fragment_data_size(buffer_size, session) as usize;
+    if data_len <= max_size {
+        return 1;
+    }
+
+    // The first fragment also carries the total data length
+    let first_fragment_size = max_size - size_of::<u32>();
+    1 + (data_len - first_fragment_size).div_ceil(max_size)
+}
+
 pub fn fragment_data(
     buffer_size: BufferSize,
     possible_session: &Option<Session>,
@@ -19,7 +19,8 @@ const ZLIB_COMPRESSION_LEVEL: u8 = 2;
 #[derive(Debug)]
 pub enum SerializeError {
     MissingSession,
-    BufferTooSmall(usize),
+    PacketTooLarge(usize),
+    MessageTooLarge(usize),
     IoError(Error),
 }
 
@@ -276,7 +277,7 @@ fn add_non_session_packets(
 
     // Fragmented packets require a session, so reject non-session packets that are too large
     if max_no_session_len > buffer_size as usize {
-        return Err(SerializeError::BufferTooSmall(max_no_session_len));
+        return Err(SerializeError::PacketTooLarge(max_no_session_len));
     }
 
     buffers.append(&mut serialized_packets);
@@ -342,7 +343,7 @@ fn group_session_packets(
             group.push((op_code, serialized_packet));
         } else if serialized_packet.len() > data_max_size as usize {
             // Prevent infinite loop if the packet cannot fit into the buffer by itself
-            return Err(SerializeError::BufferTooSmall(serialized_packet.len()));
+            return Err(SerializeError::PacketTooLarge(serialized_packet.len()));
         } else {
             groups.push(group.clone());
             group.clear();
@@ -388,6 +389,22 @@ fn try_compress(data: &mut Vec<u8>, session: &Session) -> bool {
     false
 }
 
+fn write_session_buffer(
+    op_code: ProtocolOpCode,
+    mut data: Vec<u8>,
+    session: &Session,
+) -> Result<Vec<u8>, SerializeError> {
+    let mut buffer = Vec::new();
+    let compressed = try_compress(&mut data, session);
+    write_header(&mut buffer, op_code, session, compressed)?;
+    buffer.write_all(&data)?;
+    buffer.write_uint::<BigEndian>(
+        compute_crc(&buffer, session.crc_seed, session.crc_length) as u64,
+        session.crc_length as usize,
+    )?;
+    Ok(buffer)
+}
+
 fn add_session_packets(
     buffers: &mut Vec<Vec<u8>>,
     session_packets: Vec<&Packet>,
@@ -401,12 +418,9 @@ fn add_session_packets(
             continue;
         }
 
-        let mut buffer = Vec::new();
         if group.len() == 1 {
-            let (op_code, mut data) = group.pop().unwrap();
-            let compressed = try_compress(&mut data, session);
-            write_header(&mut buffer, op_code, session, compressed)?;
-            buffer.write_all(&data)?;
+            let (op_code, data) = group.pop().unwrap();
+            buffers.push(write_session_buffer(op_code, data, session)?);
         } else {
             let mut all_data = Vec::new();
             for (op_code, data) in group {
@@ -415,21 +429,12 @@ fn add_session_packets(
                 all_data.write_all(&data)?;
             }
 
-            let compressed = try_compress(&mut all_data, session);
-            write_header(
-                &mut buffer,
+            buffers.push(write_session_buffer(
                 ProtocolOpCode::MultiPacket,
+                all_data,
                 session,
-                compressed,
-            )?;
-            buffer.write_all(&all_data)?;
+            )?);
         }
-
-        buffer.write_uint::<BigEndian>(
-            compute_crc(&buffer, session.crc_seed, session.crc_length) as u64,
-            session.crc_length as usize,
-        )?;
-        buffers.push(buffer);
     }
 
     Ok(())
@@ -440,6 +445,36 @@ pub fn max_fragment_data_size(buffer_size: BufferSize, session: &Session) -> u32
     buffer_size - header_size(session) - size_of::<u16>() as u32 - footer_size(session)
 }
 
+// A lone packet has nothing to share a datagram with, so it skips grouping entirely
+pub fn serialize_single_packet(
+    packet: &Packet,
+    buffer_size: BufferSize,
+    possible_session: &Option<Session>,
+) -> Result<Vec<u8>, SerializeError> {
+    if !packet.op_code().requires_session() {
+        let mut buffers = Vec::new();
+        add_non_session_packets(&mut buffers, vec![packet], buffer_size)?;
+        return Ok(buffers.remove(0));
+    }
+
+    let Some(session) = possible_session else {
+        return Err(SerializeError::MissingSession);
+    };
+
+    let data = serialize_packet_data(packet)?;
+    let data_max_size = buffer_size.saturating_sub(header_size(session) + footer_size(session));
+    if data.len() > data_max_size as usize {
+        return Err(SerializeError::PacketTooLarge(data.len()));
+    }
+
+    let buffer = write_session_buffer(packet.op_code(), data, session)?;
+    if buffer.len() > buffer_size as usize {
+        return Err(SerializeError::PacketTooLarge(buffer.len()));
+    }
+
+    Ok(buffer)
+}
+
 pub fn serialize_packets(
     packets: &[&Packet],
     buffer_size: BufferSize,
@@ -458,6 +493,14 @@ pub fn serialize_packets(
         return Err(SerializeError::MissingSession);
     }
 
+    // Datagrams larger than the buffer size are dropped by the client, so never send one
+    if let Some(buffer) = buffers
+        .iter()
+        .find(|buffer| buffer.len() > buffer_size as usize)
+    {
+        return Err(SerializeError::PacketTooLarge(buffer.len()));
+    }
+
     Ok(buffers)
 }
 
@@ -686,6 +729,58 @@ mod tests {
         assert!(actual.is_err());
     }
 
+    #[test]
+    fn test_single_packet_matches_grouped_serialization() {
+        let buffer_size = 512;
+        let session = Some(Session {
+            session_id: 12345,
+            crc_length: 3,
+            crc_seed: 67890,
+            allow_compression: true,
+            use_encryption: false,
+        });
+
+        let packets = [
+            Packet::Ack(11),
+            Packet::Heartbeat,
+            Packet::Data(3, vec![4; 300]),
+            Packet::Data(9, vec![10; buffer_size as usize]),
+            Packet::SessionRequest(3, 12345, 512, "".to_string()),
+        ];
+        for packet in packets.iter() {
+            let single = serialize_single_packet(packet, buffer_size, &session);
+            let grouped = serialize_packets(&[packet], buffer_size, &session);
+            match (single, grouped) {
+                (Ok(single), Ok(grouped)) => assert_eq!(vec![single], grouped),
+                (Err(_), Err(_)) => {}
+                _ => panic!("Serializers disagree on {:?}", packet.op_code()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_too_large_non_data_packet() {
+        let buffer_size = 8;
+        let session = Session {
+            session_id: 12345,
+            crc_length: 3,
+            crc_seed: 67890,
+            allow_compression: false,
+            use_encryption: false,
+        };
+
+        // 2 bytes for the op code, 4 bytes for the session ID, 2 bytes for the reason,
+        // and 3 bytes for the CRC
+        let packets = [Packet::Disconnect(12345, DisconnectReason::Application)];
+
+        let actual = serialize_packets(
+            &packets.iter().collect::<Vec<&Packet>>(),
+            buffer_size,
+            &Some(session),
+        );
+        assert!(matches!(actual, Err(SerializeError::PacketTooLarge(_))));
+    }
+
     #[test]
     fn test_good_session_packets_without_compression_single_full_size_packet() {
         let buffer_size = 512;